@@ -0,0 +1,5 @@
+fn main() {
+    // Migrations are embedded by sqlx::migrate!, which can't tell cargo when a new one shows up
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=migrations_sqlite");
+}
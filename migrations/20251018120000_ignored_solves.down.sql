@@ -0,0 +1 @@
+alter table sent_solves drop column ignore_reason;
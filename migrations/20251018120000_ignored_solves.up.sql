@@ -0,0 +1,2 @@
+-- Null means the solve was announced, anything else is why it was not
+alter table sent_solves add column ignore_reason text;
@@ -0,0 +1,11 @@
+create table announcements (
+	announcement_id serial primary key,
+	-- Only set for announcements coming from config, used to upsert them on startup
+	config_key text unique,
+	announce_at timestamptz not null,
+	role text not null,
+	webhook_id bigint,
+	content text not null,
+	posted_at timestamptz
+);
+create index announcements_pending on announcements(announce_at) where posted_at is null;
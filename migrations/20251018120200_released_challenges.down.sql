@@ -0,0 +1 @@
+drop table released_challenges;
@@ -0,0 +1,4 @@
+create table released_challenges (
+	challenge_name text primary key,
+	released_at timestamptz not null default now()
+);
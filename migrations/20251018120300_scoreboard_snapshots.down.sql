@@ -0,0 +1,2 @@
+drop table scoreboard_snapshot_entries;
+drop table scoreboard_snapshots;
@@ -0,0 +1,12 @@
+create table scoreboard_snapshots (
+	snapshot_id serial primary key,
+	taken_at timestamptz not null default now()
+);
+create table scoreboard_snapshot_entries (
+	snapshot_id integer not null references scoreboard_snapshots(snapshot_id) on delete cascade,
+	team_id uuid not null,
+	team_name text not null,
+	rank integer not null,
+	score bigint not null,
+	primary key (snapshot_id, team_id)
+);
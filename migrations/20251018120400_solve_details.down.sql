@@ -0,0 +1,7 @@
+alter table sent_solves
+	drop column solved_at,
+	drop column player_name,
+	drop column team_id,
+	drop column team_name,
+	drop column challenge_category,
+	drop column is_first_blood;
@@ -0,0 +1,8 @@
+-- Details for statistics, older rows predate these and are left null
+alter table sent_solves
+	add column solved_at timestamptz,
+	add column player_name text,
+	add column team_id uuid,
+	add column team_name text,
+	add column challenge_category text,
+	add column is_first_blood boolean not null default false;
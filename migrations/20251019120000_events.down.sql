@@ -0,0 +1,18 @@
+-- Only the default event can be kept, nothing would tell the others apart
+delete from sent_solves where event <> 'default';
+delete from announcements where event <> 'default';
+delete from released_challenges where event <> 'default';
+delete from scoreboard_snapshots where event <> 'default';
+drop index scoreboard_snapshots_latest;
+alter table scoreboard_snapshots drop column event;
+alter table released_challenges drop constraint released_challenges_pkey;
+alter table released_challenges drop column event;
+alter table released_challenges add primary key (challenge_name);
+drop index announcements_pending;
+alter table announcements drop constraint announcements_event_config_key;
+alter table announcements drop column event;
+alter table announcements add constraint announcements_config_key_key unique (config_key);
+create index announcements_pending on announcements(announce_at) where posted_at is null;
+drop index sent_solves_lookup;
+alter table sent_solves drop column event;
+create index sent_solves_lookup on sent_solves(challenge_name, player_id);
@@ -0,0 +1,14 @@
+-- Everything recorded before events could be named belongs to the default event
+alter table sent_solves add column event text not null default 'default';
+drop index sent_solves_lookup;
+create index sent_solves_lookup on sent_solves(event, challenge_name, player_id);
+alter table announcements add column event text not null default 'default';
+alter table announcements drop constraint announcements_config_key_key;
+alter table announcements add constraint announcements_event_config_key unique (event, config_key);
+drop index announcements_pending;
+create index announcements_pending on announcements(event, announce_at) where posted_at is null;
+alter table released_challenges add column event text not null default 'default';
+alter table released_challenges drop constraint released_challenges_pkey;
+alter table released_challenges add primary key (event, challenge_name);
+alter table scoreboard_snapshots add column event text not null default 'default';
+create index scoreboard_snapshots_latest on scoreboard_snapshots(event, taken_at);
@@ -0,0 +1,2 @@
+alter table released_challenges
+	drop column is_seed;
@@ -0,0 +1,4 @@
+-- Seeded releases were already visible when dal first saw them, released_at is only when dal
+-- noticed. Older rows predate this and count as real releases
+alter table released_challenges
+	add column is_seed boolean not null default false;
@@ -0,0 +1,5 @@
+drop table scoreboard_snapshot_entries;
+drop table scoreboard_snapshots;
+drop table released_challenges;
+drop table announcements;
+drop table sent_solves;
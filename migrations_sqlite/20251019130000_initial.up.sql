@@ -0,0 +1,51 @@
+-- SQLite gets the whole schema at once, it showed up after the postgres migrations were written.
+-- Timestamps are rfc3339 text and uuids are 16 byte blobs, that is what sqlx stores them as
+create table sent_solves (
+	solve_id integer primary key autoincrement,
+	event text not null default 'default',
+	challenge_name text not null,
+	player_id blob not null,
+	-- Null means the solve was announced, anything else is why it was not
+	ignore_reason text,
+	-- Details for statistics
+	solved_at text,
+	player_name text,
+	team_id blob,
+	team_name text,
+	challenge_category text,
+	is_first_blood boolean not null default false
+);
+create index sent_solves_lookup on sent_solves(event, challenge_name, player_id);
+create table announcements (
+	announcement_id integer primary key autoincrement,
+	event text not null default 'default',
+	-- Only set for announcements coming from config, used to upsert them on startup
+	config_key text,
+	announce_at text not null,
+	role text not null,
+	webhook_id integer,
+	content text not null,
+	posted_at text,
+	unique (event, config_key)
+);
+create index announcements_pending on announcements(event, announce_at) where posted_at is null;
+create table released_challenges (
+	event text not null default 'default',
+	challenge_name text not null,
+	released_at text not null,
+	primary key (event, challenge_name)
+);
+create table scoreboard_snapshots (
+	snapshot_id integer primary key autoincrement,
+	event text not null default 'default',
+	taken_at text not null
+);
+create index scoreboard_snapshots_latest on scoreboard_snapshots(event, taken_at);
+create table scoreboard_snapshot_entries (
+	snapshot_id integer not null references scoreboard_snapshots(snapshot_id) on delete cascade,
+	team_id blob not null,
+	team_name text not null,
+	rank integer not null,
+	score integer not null,
+	primary key (snapshot_id, team_id)
+);
@@ -1,73 +1,439 @@
+use std::io::ErrorKind;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
 use std::sync::Arc;
-use crate::config::Config;
-use crate::repository::Repository;
+use std::time::Duration;
+use crate::berg_client::BergClient;
+use crate::cli::{Cli, Command, ExportFormat, MigrateAction};
+use crate::config::{Config, ConfigError, EventConfig, ListenAddress, SourceKind, TlsConfig, WebhookRole};
+use crate::report::{escape_csv, Report, ReportFormat};
+use crate::repository::{MigrationError, MigrationState, Repository};
+use crate::services::alerter::AlerterService;
+use crate::services::challenge_fetcher::ChallengeFetcherService;
+use crate::services::config_reloader::ConfigReloaderService;
 use crate::services::player_fetcher::PlayerFetcherService;
+use crate::services::release_sender::ReleaseSenderService;
+use crate::services::scheduler::SchedulerService;
+use crate::services::scoreboard::ScoreboardService;
 use crate::services::solve_fetcher::SolveFetcherService;
-use crate::services::solve_sender::SolveSenderService;
+use crate::services::solve_sender::{SolveOutcome, SolveSenderService};
 use crate::services::team_fetcher::TeamFetcherService;
 use crate::services::webhook::WebhookService;
-use crate::state::AppState;
-use crate::USER_AGENT;
-use sqlx::migrate::MigrateError;
+use crate::shutdown::{shutdown_signal, Shutdown};
+use crate::sources::berg::BergSource;
+use crate::sources::ctfd::CtfdSource;
+use crate::sources::json::JsonSource;
+use crate::sources::rctf::RctfSource;
+use crate::sources::{ScoreboardSource, SourceError};
+use crate::state::{AppState, EventState};
+use crate::tls::{load_tls_acceptor, TlsListener, TlsLoadError};
 use tokio::sync::mpsc;
-use http::header::USER_AGENT as USER_AGENT_HEADER_KEY;
-use http::{HeaderMap, HeaderValue};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, UnixListener};
+use tokio::time::{sleep, Instant};
 use tracing::Instrument;
 
-pub(crate) async fn run() -> Result<(), AppRunError> {
-    let config = {
-        let raw_config = tokio::fs::read_to_string("config.toml").await.map_err(AppRunError::FailedToReadConfig)?;
-        toml::from_str::<Config>(&raw_config).map_err(AppRunError::ConfigParseError)?
-    };
-    let repository = Arc::new(Repository::new(&config.postgres_url).await.map_err(AppRunError::PostgresConnectionError)?);
-    let http_client = {
-        let mut default_headers = HeaderMap::new();
-        default_headers.insert(USER_AGENT_HEADER_KEY, HeaderValue::from_static(USER_AGENT));
-        reqwest::Client::builder()
-            .default_headers(default_headers)
-            .build()
-            .expect("all options is known to be good")
-    };
+// How long replay waits for the player, team and challenge caches before giving up
+const REPLAY_POPULATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(crate) async fn run(cli: Cli) -> Result<(), AppRunError> {
+    let config = Config::load(cli.config.as_deref()).await?;
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config, cli.config).await,
+        Command::Migrate { action } => migrate(config, action.unwrap_or(MigrateAction::Up)).await,
+        Command::CheckConfig => check_config(config).await,
+        Command::SendTest => send_test(config).await,
+        Command::Replay { event } => replay(config, event).await,
+        Command::Export { format, event } => export(config, format, event).await,
+        Command::Report { format, event } => report(config, format, event).await
+    }
+}
+
+async fn serve(config: Config, config_path: Option<PathBuf>) -> Result<(), AppRunError> {
+    let repository = Arc::new(Repository::new(&config.database_url).await.map_err(AppRunError::DatabaseConnectionError)?);
 
     // Run migrations
     async {
         repository.run_migrations().await
     }.instrument(tracing::info_span!("run migrations")).await?;
     
+    let shutdown = Shutdown::default();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!("shutting down");
+            shutdown.trigger();
+        }
+    });
+
     // Services
-    let (solve_tx, solve_rx) = mpsc::unbounded_channel();
-    let webhook_service = Arc::new(WebhookService::new(config.webhooks.clone()));
-    let player_fetcher_service = PlayerFetcherService::new(config.berg_api_base.clone(), http_client.clone());
-    let team_fetcher_service = TeamFetcherService::new(config.berg_api_base.clone(), http_client.clone());
-    let solve_fetcher_service = SolveFetcherService::new(config.berg_api_base.clone(), http_client.clone(), solve_tx);
-    let solve_sender_service = SolveSenderService::new(webhook_service.clone(), player_fetcher_service.clone(), team_fetcher_service.clone(), repository.clone());
-    solve_fetcher_service.clone().start();
-    solve_sender_service.clone().start(solve_rx);
+    let events = config.events().iter()
+        .map(|event_config| start_event(&config, event_config, &repository, &shutdown))
+        .collect::<Vec<_>>();
+    let config_reloader_service = ConfigReloaderService::new(config_path, events.clone(), repository.clone());
+    config_reloader_service.clone().start(shutdown.clone());
+
+    let alerter_service = AlerterService::new_shared(config.alerting.clone(), events.iter().map(|event| event.webhook_service.clone()).collect());
 
     let state = Arc::new(AppState {
-        solve_fetcher_service
+        admin_token: config.admin_token.clone(),
+        readiness_config: config.readiness.clone(),
+        repository,
+        events,
+        config_reloader_service,
+        alerter_service
     });
-    let service = crate::routers::router().with_state(state);
-    let listener = TcpListener::bind(("0.0.0.0", 5000)).await.map_err(AppRunError::BindError)?;
+    state.alerter_service.clone().start(state.clone(), None, shutdown.clone());
+    for event in &state.events {
+        event.alerter_service.clone().start(state.clone(), Some(event.clone()), shutdown.clone());
+    }
+    let http_config = &config.http;
+    let public_router = crate::routers::public_router().with_state(state.clone());
+    let private_router = crate::routers::private_router().with_state(state);
+    match &http_config.admin {
+        Some(admin_config) => {
+            tokio::try_join!(
+                serve_http(&http_config.listen, http_config.tls.as_ref(), with_base_path(&http_config.base_path, public_router), &shutdown),
+                serve_http(&admin_config.listen, admin_config.tls.as_ref(), with_base_path(&http_config.base_path, private_router), &shutdown)
+            )?;
+        },
+        None => {
+            let router = with_base_path(&http_config.base_path, public_router.merge(private_router));
+            serve_http(&http_config.listen, http_config.tls.as_ref(), router, &shutdown).await?;
+        }
+    }
+
+    // Only reached once shutdown was triggered and in-flight requests are done
+    if !shutdown.drain(Duration::from_secs(config.shutdown_timeout_seconds)).await {
+        tracing::warn!("timed out waiting for in-flight deliveries, they will be retried on the next start");
+    }
+
+    Ok(())
+}
+
+// Events only share the database and the http listeners, everything else runs once per event
+fn start_event(config: &Config, event_config: &EventConfig, repository: &Repository, shutdown: &Shutdown) -> Arc<EventState> {
+    let repository = Arc::new(repository.for_event(&event_config.name));
+    let (source, berg_client) = build_source(event_config);
+    let (solve_tx, solve_rx) = mpsc::unbounded_channel();
+    let (release_tx, release_rx) = mpsc::unbounded_channel();
+    let webhook_service = Arc::new(WebhookService::new(event_config.webhooks.clone()));
+    let player_fetcher_service = PlayerFetcherService::new(source.clone());
+    let team_fetcher_service = TeamFetcherService::new(source.clone());
+    let challenge_fetcher_service = ChallengeFetcherService::new(source.clone(), release_tx);
+    let solve_fetcher_service = SolveFetcherService::new(source.clone(), event_config.berg_connection.clone(), player_fetcher_service.clone(), team_fetcher_service.clone(), challenge_fetcher_service.clone(), solve_tx);
+    let solve_sender_service = SolveSenderService::new(false, event_config.ignore.clone(), event_config.window.clone(), webhook_service.clone(), player_fetcher_service.clone(), team_fetcher_service.clone(), challenge_fetcher_service.clone(), repository.clone());
+    solve_fetcher_service.clone().start(shutdown.clone());
+    let release_sender_service = ReleaseSenderService::new(event_config.ignore.clone(), webhook_service.clone(), repository.clone());
+    let scoreboard_service = ScoreboardService::new(event_config.scoreboard.clone(), event_config.window.clone(), source.clone(), webhook_service.clone(), repository.clone());
+    let scheduler_service = SchedulerService::new(event_config.window.clone(), webhook_service.clone(), repository.clone());
+    solve_sender_service.clone().start(solve_rx, shutdown.clone());
+    release_sender_service.clone().start(release_rx, shutdown.clone());
+    scoreboard_service.clone().start(shutdown.clone());
+    scheduler_service.clone().start(crate::services::scheduler::config_announcements(event_config), shutdown.clone());
+    let alerter_service = AlerterService::new(config.alerting.clone(), &event_config.berg_connection, webhook_service.clone());
+
+    Arc::new(EventState {
+        name: event_config.name.clone(),
+        window_config: event_config.window.clone(),
+        source: event_config.source,
+        berg_api_base: event_config.berg_api_base.clone(),
+        berg_connection_config: event_config.berg_connection.clone(),
+        repository,
+        berg_client,
+        webhook_service,
+        solve_fetcher_service,
+        solve_sender_service,
+        player_fetcher_service,
+        team_fetcher_service,
+        challenge_fetcher_service,
+        scheduler_service,
+        release_sender_service,
+        scoreboard_service,
+        alerter_service
+    })
+}
+
+async fn serve_http(listen: &ListenAddress, tls_config: Option<&TlsConfig>, router: axum::Router, shutdown: &Shutdown) -> Result<(), AppRunError> {
+    let shutdown = shutdown.clone();
+    let graceful_shutdown = async move { shutdown.triggered().await };
+    tracing::info!(?listen, is_tls = tls_config.is_some(), "listening for http");
+    match (listen, tls_config) {
+        (ListenAddress::Tcp(address), None) => {
+            let listener = TcpListener::bind(address).await.map_err(AppRunError::BindError)?;
+            axum::serve(listener, router).with_graceful_shutdown(graceful_shutdown).await.map_err(AppRunError::ServeError)
+        },
+        (ListenAddress::Tcp(address), Some(tls_config)) => {
+            let acceptor = load_tls_acceptor(tls_config)?;
+            let listener = TcpListener::bind(address).await.map_err(AppRunError::BindError)?;
+            let listener = TlsListener::new(listener, acceptor).map_err(AppRunError::BindError)?;
+            axum::serve(listener, router).with_graceful_shutdown(graceful_shutdown).await.map_err(AppRunError::ServeError)
+        },
+        // Config validation rejects tls on unix sockets
+        (ListenAddress::Unix(path), _) => {
+            // A socket left behind by a previous run would make bind fail, anything else at the path is
+            // left alone
+            match tokio::fs::symlink_metadata(path).await {
+                Ok(metadata) if metadata.file_type().is_socket() => tokio::fs::remove_file(path).await.map_err(AppRunError::BindError)?,
+                Ok(_) => return Err(AppRunError::BindError(std::io::Error::new(ErrorKind::AlreadyExists, format!("{} exists and isn't a socket", path.display())))),
+                Err(error) if error.kind() == ErrorKind::NotFound => {},
+                Err(error) => return Err(AppRunError::BindError(error))
+            }
+            let listener = UnixListener::bind(path).map_err(AppRunError::BindError)?;
+            axum::serve(listener, router).with_graceful_shutdown(graceful_shutdown).await.map_err(AppRunError::ServeError)
+        }
+    }
+}
+
+fn with_base_path(base_path: &str, router: axum::Router) -> axum::Router {
+    if base_path.is_empty() {
+        router
+    } else {
+        axum::Router::new().nest(base_path, router)
+    }
+}
+
+async fn migrate(config: Config, action: MigrateAction) -> Result<(), AppRunError> {
+    let repository = Repository::new(&config.database_url).await.map_err(AppRunError::DatabaseConnectionError)?;
+    match action {
+        MigrateAction::Status => {
+            for status in repository.migration_status().await.map_err(MigrationError::Migrate)? {
+                let state = match status.state {
+                    MigrationState::Applied => "applied",
+                    MigrationState::Pending => "pending",
+                    MigrationState::Modified => "applied, but changed since",
+                    MigrationState::Unknown => "applied by a newer version of dal"
+                };
+                println!("{} {}: {state}", status.version, status.description.as_deref().unwrap_or("(unknown)"));
+            }
+        },
+        MigrateAction::Up => {
+            repository.run_migrations().await?;
+            println!("migrations are up to date");
+        },
+        MigrateAction::Down { to, yes } => {
+            let mut applied_migrations = repository.migration_status().await.map_err(MigrationError::Migrate)?
+                .into_iter()
+                .filter(|status| status.state != MigrationState::Pending)
+                .collect::<Vec<_>>();
+            applied_migrations.sort_by_key(|status| status.version);
+            let target = match to {
+                Some(target) => target,
+                // Back to the migration before the latest applied one
+                None => applied_migrations.iter().rev().nth(1).map_or(0, |status| status.version)
+            };
+            let reverted_migrations = applied_migrations.iter()
+                .rev()
+                .filter(|status| status.version > target)
+                .collect::<Vec<_>>();
+            if reverted_migrations.is_empty() {
+                println!("no migrations newer than {target} have been applied");
+                return Ok(());
+            }
+            // Dropping tables loses data, so a bare migrate down only says what it would do
+            let is_confirmed = to.is_some() || yes;
+            println!("{}", if is_confirmed { "reverting:" } else { "would revert:" });
+            for status in &reverted_migrations {
+                println!("  {} {}", status.version, status.description.as_deref().unwrap_or("(unknown)"));
+            }
+            if !is_confirmed {
+                return Err(AppRunError::MigrateDownNotConfirmed);
+            }
+            repository.undo_migrations(target).await?;
+            println!("reverted migrations newer than {target}");
+        }
+    }
+
+    Ok(())
+}
+
+// The config has already been parsed at this point, so only the webhooks are left to verify
+async fn check_config(config: Config) -> Result<(), AppRunError> {
+    let mut failed_webhook_count = 0;
+    for event_config in config.events() {
+        if config.events().len() > 1 {
+            println!("event {}:", event_config.name);
+        }
+        let webhook_service = WebhookService::new(event_config.webhooks.clone());
+        for webhook in &event_config.webhooks {
+            match webhook_service.check_webhook(webhook).await {
+                Ok(()) => println!("webhook {} is reachable", webhook.id),
+                Err(error) => {
+                    println!("webhook {} is not reachable: {error}", webhook.id);
+                    failed_webhook_count += 1;
+                }
+            }
+        }
+        for role in WebhookRole::ALL {
+            if !event_config.webhooks.iter().any(|webhook| webhook.roles.contains(&role)) {
+                println!("warning: no webhook has the {} role", role.as_str());
+            }
+        }
+    }
+    if failed_webhook_count > 0 {
+        return Err(AppRunError::ConfigCheckFailed(failed_webhook_count));
+    }
+    println!("config is valid");
+
+    Ok(())
+}
+
+async fn send_test(config: Config) -> Result<(), AppRunError> {
+    let mut failed_role_count = 0;
+    for event_config in config.events() {
+        if config.events().len() > 1 {
+            println!("event {}:", event_config.name);
+        }
+        let webhook_service = WebhookService::new(event_config.webhooks.clone());
+        for role in WebhookRole::ALL {
+            let content = format!("🧪 Test message for the **{}** role", role.as_str());
+            match webhook_service.send_message(role.clone(), &content).await {
+                Ok(Some(webhook)) => println!("{}: posted to webhook {}", role.as_str(), webhook.id),
+                Ok(None) => println!("{}: no webhook has this role", role.as_str()),
+                Err(error) => {
+                    println!("{}: failed to post: {error}", role.as_str());
+                    failed_role_count += 1;
+                }
+            }
+        }
+    }
+    if failed_role_count > 0 {
+        return Err(AppRunError::SendTestFailed(failed_role_count));
+    }
+
+    Ok(())
+}
+
+// Runs every solve berg knows about through the ignore rules and first blood logic without
+// posting or recording anything
+async fn replay(config: Config, event: Option<String>) -> Result<(), AppRunError> {
+    let event_config = config.find_event(event.as_deref()).map_err(AppRunError::UnknownEvent)?;
+    let repository = Repository::new(&config.database_url).await.map_err(AppRunError::DatabaseConnectionError)?;
+    let repository = Arc::new(repository.for_event(&event_config.name));
+    let (source, _berg_client) = build_source(event_config);
+
+    let (solve_tx, _solve_rx) = mpsc::unbounded_channel();
+    let (release_tx, _release_rx) = mpsc::unbounded_channel();
+    let webhook_service = Arc::new(WebhookService::new(event_config.webhooks.clone()));
+    let player_fetcher_service = PlayerFetcherService::new(source.clone());
+    let team_fetcher_service = TeamFetcherService::new(source.clone());
+    let challenge_fetcher_service = ChallengeFetcherService::new(source.clone(), release_tx);
+    let solve_fetcher_service = SolveFetcherService::new(source.clone(), event_config.berg_connection.clone(), player_fetcher_service.clone(), team_fetcher_service.clone(), challenge_fetcher_service.clone(), solve_tx);
+    let solve_sender_service = SolveSenderService::new(true, event_config.ignore.clone(), event_config.window.clone(), webhook_service, player_fetcher_service.clone(), team_fetcher_service.clone(), challenge_fetcher_service.clone(), repository);
 
-    axum::serve(listener, service).await.map_err(AppRunError::ServeError)?;
+    // Without the caches every solve would be attributed to an unknown player
+    let deadline = Instant::now() + REPLAY_POPULATE_TIMEOUT;
+    while !(player_fetcher_service.is_populated() && team_fetcher_service.is_populated() && challenge_fetcher_service.is_populated()) {
+        if Instant::now() >= deadline {
+            tracing::warn!("caches were not populated in time, replaying anyway");
+            break;
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+
+    let solves = solve_fetcher_service.fetch_solves().await.map_err(AppRunError::SourceRequestError)?;
+    let (mut already_sent_count, mut ignored_count, mut announced_count, mut first_blood_count, mut failed_count) = (0, 0, 0, 0, 0);
+    for (solve, outcome) in solve_sender_service.replay(solves).await {
+        match outcome {
+            Ok(SolveOutcome::AlreadySent) => already_sent_count += 1,
+            Ok(SolveOutcome::Ignored(ignore_reason)) => {
+                println!("ignored {} by {}: {ignore_reason}", solve.challenge_name, solve.player_id);
+                ignored_count += 1;
+            },
+            Ok(SolveOutcome::Announced { is_first_blood }) => {
+                announced_count += 1;
+                if is_first_blood {
+                    first_blood_count += 1;
+                }
+            },
+            Err(error) => {
+                println!("failed to process {} by {}: {error}", solve.challenge_name, solve.player_id);
+                failed_count += 1;
+            }
+        }
+    }
+    println!("{announced_count} would be announced ({first_blood_count} first bloods), {ignored_count} ignored, {already_sent_count} already sent, {failed_count} failed");
+
+    Ok(())
+}
+
+async fn export(config: Config, format: ExportFormat, event: Option<String>) -> Result<(), AppRunError> {
+    let event_config = config.find_event(event.as_deref()).map_err(AppRunError::UnknownEvent)?;
+    let repository = Repository::new(&config.database_url).await.map_err(AppRunError::DatabaseConnectionError)?.for_event(&event_config.name);
+    let solves = repository.get_sent_solves().await.map_err(AppRunError::QueryError)?;
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&solves).expect("sent solves are always serializable")),
+        ExportFormat::Csv => {
+            println!("solve_id,challenge_name,player_id,player_name,team_id,team_name,challenge_category,solved_at,is_first_blood,ignore_reason");
+            for solve in solves {
+                println!(
+                    "{},{},{},{},{},{},{},{},{},{}",
+                    solve.solve_id,
+                    escape_csv(&solve.challenge_name),
+                    solve.player_id,
+                    escape_csv(solve.player_name.as_deref().unwrap_or("")),
+                    solve.team_id.map(|team_id| team_id.to_string()).unwrap_or_default(),
+                    escape_csv(solve.team_name.as_deref().unwrap_or("")),
+                    escape_csv(solve.challenge_category.as_deref().unwrap_or("")),
+                    solve.solved_at.map(|solved_at| solved_at.to_rfc3339()).unwrap_or_default(),
+                    solve.is_first_blood,
+                    escape_csv(solve.ignore_reason.as_deref().unwrap_or(""))
+                );
+            }
+        }
+    }
 
     Ok(())
 }
 
+async fn report(config: Config, format: ReportFormat, event: Option<String>) -> Result<(), AppRunError> {
+    let event_config = config.find_event(event.as_deref()).map_err(AppRunError::UnknownEvent)?;
+    let repository = Repository::new(&config.database_url).await.map_err(AppRunError::DatabaseConnectionError)?.for_event(&event_config.name);
+    let solves = repository.get_report_solves().await.map_err(AppRunError::QueryError)?;
+    let report = Report::build(solves, event_config.window.start_time);
+    println!("{}", report.render(format));
+
+    Ok(())
+}
+
+// The berg client is handed out separately for its login metrics
+fn build_source(config: &EventConfig) -> (Arc<dyn ScoreboardSource>, Option<Arc<BergClient>>) {
+    match config.source {
+        SourceKind::Berg => {
+            let berg_api_base = config.berg_api_base.clone().expect("validated while loading the config");
+            let berg_client = BergClient::new(berg_api_base, config.berg_auth.clone());
+            (BergSource::new(berg_client.clone()), Some(berg_client))
+        },
+        SourceKind::Ctfd => (CtfdSource::new(config.ctfd.as_ref().expect("validated while loading the config")), None),
+        SourceKind::Rctf => (RctfSource::new(config.rctf.as_ref().expect("validated while loading the config")), None),
+        SourceKind::Json => (JsonSource::new(config.json.as_ref().expect("validated while loading the config")), None)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum AppRunError {
     #[error("failed to bind")]
     BindError(std::io::Error),
     #[error("failed to serve")]
     ServeError(std::io::Error),
-    #[error("failed to read config")]
-    FailedToReadConfig(std::io::Error),
-    #[error("failed to parse config")]
-    ConfigParseError(toml::de::Error),
-    #[error("failed to connect to postgres")]
-    PostgresConnectionError(sqlx::Error),
+    #[error("failed to load tls certificate")]
+    TlsLoadError(#[from] TlsLoadError),
+    #[error("failed to load config")]
+    ConfigError(#[from] ConfigError),
+    #[error("failed to connect to the database")]
+    DatabaseConnectionError(sqlx::Error),
+    #[error("failed to query the database")]
+    QueryError(sqlx::Error),
+    #[error("{0}")]
+    UnknownEvent(String),
+    #[error("failed to fetch solves from the source")]
+    SourceRequestError(SourceError),
+    #[error("{0} webhook(s) failed the check")]
+    ConfigCheckFailed(usize),
+    #[error("{0} test message(s) failed to send")]
+    SendTestFailed(usize),
     #[error("failed to run migrations")]
-    FailedToReadMigrations(#[from] MigrateError)
+    MigrationError(#[from] MigrationError),
+    #[error("nothing was reverted, pass --yes to revert the latest migration or --to <version>")]
+    MigrateDownNotConfirmed
 }
@@ -0,0 +1,27 @@
+use std::time::Duration;
+use rand::Rng;
+
+// Exponential backoff with equal jitter, so reconnecting clients don't all retry at once
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32
+}
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            attempt: 0
+        }
+    }
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let bound = self.initial.saturating_mul(2u32.saturating_pow(self.attempt)).min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let half = bound / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+    pub(crate) fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
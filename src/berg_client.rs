@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use http::header::{AUTHORIZATION, COOKIE, SET_COOKIE, USER_AGENT as USER_AGENT_HEADER_KEY};
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use serde_json::json;
+use url::Url;
+
+use crate::backoff::Backoff;
+use crate::config::BergAuthConfig;
+use crate::sources::SourceError;
+use crate::USER_AGENT;
+
+const LOGIN_RETRY_INITIAL: Duration = Duration::from_secs(5);
+const LOGIN_RETRY_MAX: Duration = Duration::from_secs(60 * 5);
+
+// Every request to berg goes through here so credentials are applied the same way everywhere
+pub(crate) struct BergClient {
+    logins_count: AtomicU32,
+    failed_to_login_count: AtomicU32,
+    http_client: reqwest::Client,
+    api_base: Url,
+    auth_config: Option<BergAuthConfig>,
+    credential: RwLock<Option<Credential>>,
+    // Only one login at a time, everyone else waits for its result
+    login_state: tokio::sync::Mutex<LoginState>
+}
+// Keeps a failing login from being retried on every request
+struct LoginState {
+    backoff: Backoff,
+    retry_at: Option<Instant>
+}
+impl BergClient {
+    pub(crate) fn new(api_base: Url, auth_config: Option<BergAuthConfig>) -> Arc<Self> {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(USER_AGENT_HEADER_KEY, HeaderValue::from_static(USER_AGENT));
+        let http_client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .build()
+            .expect("all options is known to be good");
+        Arc::new(Self {
+            logins_count: AtomicU32::default(),
+            failed_to_login_count: AtomicU32::default(),
+            http_client,
+            api_base,
+            auth_config,
+            credential: RwLock::default(),
+            login_state: tokio::sync::Mutex::new(LoginState {
+                backoff: Backoff::new(LOGIN_RETRY_INITIAL, LOGIN_RETRY_MAX),
+                retry_at: None
+            })
+        })
+    }
+    pub(crate) fn logins_count(&self) -> u32 {
+        self.logins_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_login_count(&self) -> u32 {
+        self.failed_to_login_count.load(Ordering::SeqCst)
+    }
+    // Only meant for hard-coded paths
+    pub(crate) fn url(&self, path: &str) -> Url {
+        self.api_base.join(path).expect("hard-coded path should always be fine to join to berg_api_base")
+    }
+    // Logs in again once if berg answers with a 401, any other status is up to the caller
+    pub(crate) async fn get(&self, url: Url, prepare: impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder) -> Result<reqwest::Response, SourceError> {
+        let credential = self.credential().await?;
+        let response = prepare(with_credential(self.http_client.get(url.clone()), credential.as_ref())).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED || self.auth_config.is_none() {
+            return Ok(response);
+        }
+
+        tracing::info!(%url, "berg rejected our credentials, logging in again");
+        let credential = self.relogin(credential).await?;
+        Ok(prepare(with_credential(self.http_client.get(url), credential.as_ref())).send().await?)
+    }
+    // Headers for requests that can't go through reqwest, like the events websocket handshake. None
+    // only when no berg_auth is configured, requests aren't sent anonymously when logging in fails
+    pub(crate) async fn credential(&self) -> Result<Option<Credential>, CredentialError> {
+        if let Some(credential) = self.current_credential() {
+            return Ok(Some(credential));
+        }
+        self.relogin(None).await
+    }
+    // stale_credential is what berg rejected, if someone else already replaced it that is used instead
+    pub(crate) async fn relogin(&self, stale_credential: Option<Credential>) -> Result<Option<Credential>, CredentialError> {
+        let Some(auth_config) = self.auth_config.as_ref() else {
+            return Ok(None);
+        };
+        let mut login_state = self.login_state.lock().await;
+        let current_credential = self.current_credential();
+        if current_credential.is_some() && current_credential != stale_credential {
+            return Ok(current_credential);
+        }
+        if let Some(retry_at) = login_state.retry_at && retry_at > Instant::now() {
+            return Err(CredentialError::WaitingToRetry(retry_at - Instant::now()));
+        }
+
+        match self.login(auth_config).await {
+            Ok(credential) => {
+                tracing::debug!("logged in to berg");
+                self.logins_count.fetch_add(1, Ordering::SeqCst);
+                login_state.backoff.reset();
+                login_state.retry_at = None;
+                *self.credential.write().expect("credential lock poisoned") = Some(credential.clone());
+                Ok(Some(credential))
+            },
+            Err(error) => {
+                let retry_in = login_state.backoff.next_delay();
+                tracing::error!(?error, ?retry_in, "failed to log in to berg");
+                self.failed_to_login_count.fetch_add(1, Ordering::SeqCst);
+                login_state.retry_at = Some(Instant::now() + retry_in);
+                Err(CredentialError::LoginFailed(error))
+            }
+        }
+    }
+    fn current_credential(&self) -> Option<Credential> {
+        self.credential.read().expect("credential lock poisoned").clone()
+    }
+    async fn login(&self, auth_config: &BergAuthConfig) -> Result<Credential, LoginError> {
+        match auth_config {
+            BergAuthConfig::Bearer { token, token_file } => {
+                let token = read_secret(token, token_file).await?;
+                Credential::new(AUTHORIZATION, format!("Bearer {token}"))
+            },
+            BergAuthConfig::ApiKey { header, key, key_file } => {
+                let header = HeaderName::try_from(header.as_str()).map_err(|_error| LoginError::InvalidHeader)?;
+                Credential::new(header, read_secret(key, key_file).await?)
+            },
+            BergAuthConfig::Session { login_path, username, password, password_file } => {
+                let password = read_secret(password, password_file).await?;
+                let login_url = self.api_base.join(login_path).map_err(|_error| LoginError::InvalidLoginPath)?;
+                let response = self.http_client.post(login_url)
+                    .json(&json!({
+                        "username": username,
+                        "password": password
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                // Only the name=value part is sent back, the attributes are for browsers
+                let cookies = response.headers().get_all(SET_COOKIE).iter()
+                    .filter_map(|set_cookie| set_cookie.to_str().ok())
+                    .filter_map(|set_cookie| set_cookie.split(';').next())
+                    .map(str::trim)
+                    .collect::<Vec<_>>();
+                if cookies.is_empty() {
+                    return Err(LoginError::NoSessionCookie);
+                }
+                Credential::new(COOKIE, cookies.join("; "))
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Credential {
+    pub(crate) name: HeaderName,
+    pub(crate) value: HeaderValue
+}
+impl Credential {
+    fn new(name: HeaderName, value: String) -> Result<Self, LoginError> {
+        let mut value = HeaderValue::try_from(value).map_err(|_error| LoginError::InvalidHeader)?;
+        value.set_sensitive(true);
+        Ok(Self {
+            name,
+            value
+        })
+    }
+}
+
+fn with_credential(request: reqwest::RequestBuilder, credential: Option<&Credential>) -> reqwest::RequestBuilder {
+    match credential {
+        Some(credential) => request.header(credential.name.clone(), credential.value.clone()),
+        None => request
+    }
+}
+
+// Files are read on every login so rotated secrets are picked up without a restart
+async fn read_secret(value: &Option<String>, file: &Option<PathBuf>) -> Result<String, LoginError> {
+    match (value, file) {
+        (Some(value), _) => Ok(value.clone()),
+        (None, Some(file)) => read_secret_file(file).await,
+        (None, None) => Err(LoginError::MissingSecret)
+    }
+}
+async fn read_secret_file(file: &Path) -> Result<String, LoginError> {
+    let secret = tokio::fs::read_to_string(file).await.map_err(|error| LoginError::FailedToReadSecret(file.to_path_buf(), error))?;
+    Ok(secret.trim_end().to_string())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum CredentialError {
+    #[error("failed to log in to berg")]
+    LoginFailed(LoginError),
+    #[error("logging in to berg failed recently, retrying in {0:?}")]
+    WaitingToRetry(Duration)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum LoginError {
+    #[error("no secret configured")]
+    MissingSecret,
+    #[error("failed to read secret from {}: {}", .0.display(), .1)]
+    FailedToReadSecret(PathBuf, std::io::Error),
+    #[error("credential isn't a valid header")]
+    InvalidHeader,
+    #[error("login_path can't be joined to berg_api_base")]
+    InvalidLoginPath,
+    #[error("login request failed")]
+    LoginRequestFailed(#[from] reqwest::Error),
+    #[error("login response set no session cookie")]
+    NoSessionCookie
+}
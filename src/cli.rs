@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::report::ReportFormat;
+
+#[derive(Parser)]
+#[command(version, about = "Sends solve notifications from berg to Discord")]
+pub(crate) struct Cli {
+    /// Config file, defaults to config.toml if it exists
+    #[arg(long, global = true, env = "DAL_CONFIG")]
+    pub(crate) config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Run migrations and start sending notifications (default)
+    Serve,
+    /// Manage database migrations, runs pending ones without a subcommand
+    Migrate {
+        #[command(subcommand)]
+        action: Option<MigrateAction>
+    },
+    /// Validate the config and check that every webhook is reachable
+    CheckConfig,
+    /// Post a sample notification to a webhook of every role
+    SendTest,
+    /// Re-process all solves from berg, printing what would be sent instead of sending it
+    Replay {
+        /// Only needed when more than one event is configured
+        #[arg(long)]
+        event: Option<String>
+    },
+    /// Dump all recorded solves, including ignored ones
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Only needed when more than one event is configured
+        #[arg(long)]
+        event: Option<String>
+    },
+    /// Generate post-event statistics
+    Report {
+        #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+        /// Only needed when more than one event is configured
+        #[arg(long)]
+        event: Option<String>
+    }
+}
+
+#[derive(Subcommand, Clone, Copy)]
+pub(crate) enum MigrateAction {
+    /// List every migration and whether it has been applied
+    Status,
+    /// Run pending migrations (default)
+    Up,
+    /// Revert the latest migration, needs --yes or --to
+    Down {
+        /// Revert every migration newer than this version instead, 0 reverts all of them
+        #[arg(long)]
+        to: Option<i64>,
+        /// Confirm reverting the latest migration
+        #[arg(long)]
+        yes: bool
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub(crate) enum ExportFormat {
+    Json,
+    Csv
+}
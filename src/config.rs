@@ -1,25 +1,1036 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
 use url::Url;
-use serde::Deserialize;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
 use twilight_model::id::Id as Snowflake;
 use twilight_model::id::marker::WebhookMarker;
 
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
+use crate::models::solve::Solve;
+use crate::models::team::Team;
+
+pub(crate) const DEFAULT_CONFIG_PATH: &str = "config.toml";
+// Top-level keys that can be set from the environment instead of the config file. Appending _FILE
+// to the variable name reads the value from that file instead, e.g. for Docker secrets
+const ENV_OVERRIDES: [(&str, &str); 6] = [
+    ("berg_api_base", "DAL_BERG_API_BASE"),
+    ("database_url", "DAL_DATABASE_URL"),
+    // The old name of DAL_DATABASE_URL, which wins when both are set
+    ("database_url", "DAL_POSTGRES_URL"),
+    ("admin_token", "DAL_ADMIN_TOKEN"),
+    ("start_time", "DAL_START_TIME"),
+    ("end_time", "DAL_END_TIME")
+];
+// Overrides that only reach the top-level event, which is ignored once events is set
+const EVENT_OVERRIDE_KEYS: [&str; 3] = ["berg_api_base", "start_time", "end_time"];
+const REQUIRED_KEYS: [&str; 1] = ["database_url"];
+// Keys that were renamed, the old name is still read when the new one isn't set
+const RENAMED_KEYS: [(&str, &str); 1] = [("postgres_url", "database_url")];
+
 #[derive(Deserialize, Clone)]
 pub(crate) struct Config {
-    pub(crate) berg_api_base: Url,
-    pub(crate) postgres_url: String,
-    pub(crate) webhooks: Vec<WebhookConfig>
+    // A single event can be configured at the top level, it's ignored once events is set
+    #[serde(flatten)]
+    pub(crate) event: EventConfig,
+    // Several events monitored at once, each with everything an event has at the top level
+    #[serde(default)]
+    pub(crate) events: Vec<EventConfig>,
+    // postgres://... or sqlite:path, sqlite::memory: keeps nothing once dal exits
+    pub(crate) database_url: String,
+    #[serde(default)]
+    pub(crate) http: HttpConfig,
+    #[serde(default)]
+    pub(crate) alerting: AlertingConfig,
+    #[serde(default)]
+    pub(crate) readiness: ReadinessConfig,
+    // How long to wait for in-flight deliveries on shutdown
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub(crate) shutdown_timeout_seconds: u64,
+    pub(crate) admin_token: Option<String>
+}
+impl Config {
+    pub(crate) fn events(&self) -> &[EventConfig] {
+        if self.events.is_empty() {
+            std::slice::from_ref(&self.event)
+        } else {
+            &self.events
+        }
+    }
+    fn events_mut(&mut self) -> &mut [EventConfig] {
+        if self.events.is_empty() {
+            std::slice::from_mut(&mut self.event)
+        } else {
+            &mut self.events
+        }
+    }
+    // Commands that work on one event need to be told which unless there is only one
+    pub(crate) fn find_event(&self, name: Option<&str>) -> Result<&EventConfig, String> {
+        match (name, self.events()) {
+            (None, [event]) => Ok(event),
+            (None, _) => Err("more than one event is configured, pick one with --event".to_string()),
+            (Some(name), events) => events.iter().find(|event| event.name == name).ok_or_else(|| format!("no event is named {name}"))
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct EventConfig {
+    // Keeps each event's solves and announcements apart in the database and labels its metrics
+    #[serde(default = "default_event_name")]
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) source: SourceKind,
+    // Required when the source is berg
+    pub(crate) berg_api_base: Option<Url>,
+    // Required when the source is ctfd
+    pub(crate) ctfd: Option<CtfdConfig>,
+    // Required when the source is rctf
+    pub(crate) rctf: Option<RctfConfig>,
+    // Required when the source is json
+    pub(crate) json: Option<JsonSourceConfig>,
+    #[serde(default)]
+    pub(crate) webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub(crate) ignore: IgnoreConfig,
+    #[serde(flatten)]
+    pub(crate) window: EventWindowConfig,
+    #[serde(default)]
+    pub(crate) announcements: Vec<AnnouncementConfig>,
+    #[serde(default)]
+    pub(crate) scoreboard: ScoreboardConfig,
+    #[serde(default)]
+    pub(crate) berg_connection: BergConnectionConfig,
+    // Berg is queried anonymously without this
+    pub(crate) berg_auth: Option<BergAuthConfig>
+}
+// Everything recorded before events could be named belongs to this one
+pub(crate) const DEFAULT_EVENT_NAME: &str = "default";
+fn default_event_name() -> String {
+    DEFAULT_EVENT_NAME.to_string()
+}
+fn default_shutdown_timeout_seconds() -> u64 {
+    25
 }
 #[derive(Deserialize, Clone)]
 pub(crate) struct WebhookConfig {
     pub(crate) id: Snowflake<WebhookMarker>,
+    #[serde(default)]
     pub(crate) token: String,
+    // Read into token while loading, so the token itself can live in a secret
+    pub(crate) token_file: Option<PathBuf>,
     pub(crate) roles: HashSet<WebhookRole>
 }
-#[derive(Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum WebhookRole {
     FirstBlood,
-    Solve
+    Solve,
+    Announcement,
+    ChallengeRelease,
+    Scoreboard,
+    // Dal's own failures, meant for organisers rather than players
+    Ops
+}
+impl WebhookRole {
+    pub(crate) const ALL: [WebhookRole; 6] = [
+        WebhookRole::FirstBlood,
+        WebhookRole::Solve,
+        WebhookRole::Announcement,
+        WebhookRole::ChallengeRelease,
+        WebhookRole::Scoreboard,
+        WebhookRole::Ops
+    ];
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            WebhookRole::FirstBlood => "first_blood",
+            WebhookRole::Solve => "solve",
+            WebhookRole::Announcement => "announcement",
+            WebhookRole::ChallengeRelease => "challenge_release",
+            WebhookRole::Scoreboard => "scoreboard",
+            WebhookRole::Ops => "ops"
+        }
+    }
+}
+impl FromStr for WebhookRole {
+    type Err = UnknownWebhookRoleError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "first_blood" => Ok(WebhookRole::FirstBlood),
+            "solve" => Ok(WebhookRole::Solve),
+            "announcement" => Ok(WebhookRole::Announcement),
+            "challenge_release" => Ok(WebhookRole::ChallengeRelease),
+            "scoreboard" => Ok(WebhookRole::Scoreboard),
+            "ops" => Ok(WebhookRole::Ops),
+            _ => Err(UnknownWebhookRoleError(raw.to_string()))
+        }
+    }
+}
+#[derive(thiserror::Error, Debug)]
+#[error("unknown webhook role {0}")]
+pub(crate) struct UnknownWebhookRoleError(String);
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct IgnoreConfig {
+    pub(crate) challenges: HashSet<String>,
+    pub(crate) categories: HashSet<String>,
+    pub(crate) player_ids: HashSet<Uuid>,
+    pub(crate) team_ids: HashSet<Uuid>,
+    pub(crate) team_names: HashSet<String>,
+    pub(crate) admin_players: bool,
+    pub(crate) hidden_teams: bool,
+    pub(crate) hidden_challenges: bool
+}
+impl IgnoreConfig {
+    pub(crate) fn ignore_reason(&self, solve: &Solve, challenge: Option<&Challenge>, player: Option<&Player>, team: Option<&Team>) -> Option<&'static str> {
+        if let Some(ignore_reason) = self.challenge_ignore_reason(&solve.challenge_name, challenge) {
+            return Some(ignore_reason);
+        }
+        if self.player_ids.contains(&solve.player_id) {
+            return Some("ignored player");
+        }
+        if self.admin_players && player.is_some_and(|player| player.admin) {
+            return Some("admin player");
+        }
+        if let Some(team) = team {
+            if self.team_ids.contains(&team.id) || self.team_names.contains(&team.name) {
+                return Some("ignored team");
+            }
+            if self.hidden_teams && team.hidden {
+                return Some("hidden team");
+            }
+        }
+        None
+    }
+    // Rules that can only match once players or teams have been fetched
+    pub(crate) fn needs_players(&self) -> bool {
+        self.admin_players
+    }
+    pub(crate) fn needs_teams(&self) -> bool {
+        !self.team_ids.is_empty() || !self.team_names.is_empty() || self.hidden_teams
+    }
+    pub(crate) fn challenge_ignore_reason(&self, challenge_name: &str, challenge: Option<&Challenge>) -> Option<&'static str> {
+        if self.challenges.contains(challenge_name) {
+            return Some("ignored challenge");
+        }
+        if let Some(challenge) = challenge {
+            if challenge.category.as_ref().is_some_and(|category| self.categories.contains(category)) {
+                return Some("ignored category");
+            }
+            if self.hidden_challenges && challenge.hidden {
+                return Some("hidden challenge");
+            }
+        }
+        None
+    }
+}
+#[derive(Deserialize, Clone, Default)]
+pub(crate) struct EventWindowConfig {
+    pub(crate) start_time: Option<DateTime<Utc>>,
+    pub(crate) end_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) window_announcements: WindowAnnouncementsConfig
+}
+impl EventWindowConfig {
+    pub(crate) fn outside_reason(&self, at: DateTime<Utc>) -> Option<&'static str> {
+        if self.start_time.is_some_and(|start_time| at < start_time) {
+            return Some("before event start");
+        }
+        if self.end_time.is_some_and(|end_time| at >= end_time) {
+            return Some("after event end");
+        }
+        None
+    }
+}
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct WindowAnnouncementsConfig {
+    pub(crate) start: bool,
+    pub(crate) end: bool,
+    // Minutes before end_time to post a "x left" reminder
+    pub(crate) remaining_minutes: Vec<u32>
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct AnnouncementConfig {
+    // Stable name so edits to the config update the announcement instead of posting it twice
+    pub(crate) name: String,
+    pub(crate) at: DateTime<Utc>,
+    #[serde(default = "default_announcement_role")]
+    pub(crate) role: WebhookRole,
+    pub(crate) webhook_id: Option<Snowflake<WebhookMarker>>,
+    pub(crate) template: String
+}
+fn default_announcement_role() -> WebhookRole {
+    WebhookRole::Announcement
+}
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct ScoreboardConfig {
+    // Posted every interval while the event is running, disabled if unset
+    pub(crate) interval_minutes: Option<u32>,
+    pub(crate) top: usize,
+    // Extra one-off posts, e.g. at scoreboard freeze
+    pub(crate) post_at: Vec<DateTime<Utc>>,
+    pub(crate) post_at_end: bool
+}
+impl Default for ScoreboardConfig {
+    fn default() -> Self {
+        Self {
+            interval_minutes: None,
+            top: 10,
+            post_at: Vec::new(),
+            post_at_end: false
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct HttpConfig {
+    #[serde(default = "default_listen_address")]
+    pub(crate) listen: ListenAddress,
+    // Prefix for every route, e.g. /dal when behind a reverse proxy
+    #[serde(default)]
+    pub(crate) base_path: String,
+    pub(crate) tls: Option<TlsConfig>,
+    // Moves /metrics and /admin to a listener of their own, keeping them off the public one
+    pub(crate) admin: Option<ListenerConfig>
+}
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            listen: default_listen_address(),
+            base_path: String::new(),
+            tls: None,
+            admin: None
+        }
+    }
+}
+fn default_listen_address() -> ListenAddress {
+    ListenAddress::Tcp("0.0.0.0:5000".to_string())
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct ListenerConfig {
+    pub(crate) listen: ListenAddress,
+    pub(crate) tls: Option<TlsConfig>
+}
+// Either host:port or unix:/path/to/socket
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "String")]
+pub(crate) enum ListenAddress {
+    Tcp(String),
+    Unix(PathBuf)
+}
+impl TryFrom<String> for ListenAddress {
+    type Error = InvalidListenAddressError;
+
+    fn try_from(raw: String) -> Result<Self, Self::Error> {
+        if let Some(path) = raw.strip_prefix("unix:") {
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+        match raw.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(ListenAddress::Tcp(raw)),
+            _ => Err(InvalidListenAddressError(raw))
+        }
+    }
+}
+#[derive(thiserror::Error, Debug)]
+#[error("invalid listen address {0}, expected host:port or unix:/path")]
+pub(crate) struct InvalidListenAddressError(String);
+#[derive(Deserialize, Clone)]
+pub(crate) struct TlsConfig {
+    // PEM encoded, the certificate file may contain the full chain
+    pub(crate) cert_path: PathBuf,
+    pub(crate) key_path: PathBuf
+}
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct BergConnectionConfig {
+    pub(crate) reconnect_initial_seconds: u64,
+    pub(crate) reconnect_max_seconds: u64,
+    // Consecutive failed connection attempts before berg is reported as down, after which we only
+    // retry every reconnect_max_seconds
+    pub(crate) circuit_breaker_threshold: u32,
+    pub(crate) mode: IngestMode,
+    pub(crate) poll_interval_seconds: u64,
+    // Deprecated in favour of the ops role and alerting.websocket_down_minutes, still honoured by
+    // also posting the berg down alert to alert_webhook_id after alert_after_seconds
+    pub(crate) alert_after_seconds: Option<u64>,
+    pub(crate) alert_webhook_id: Option<Snowflake<WebhookMarker>>
+}
+impl Default for BergConnectionConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_initial_seconds: 1,
+            reconnect_max_seconds: 60 * 5,
+            circuit_breaker_threshold: 5,
+            mode: IngestMode::default(),
+            poll_interval_seconds: 15,
+            alert_after_seconds: None,
+            alert_webhook_id: None
+        }
+    }
+}
+// How solves are received from berg
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IngestMode {
+    #[default]
+    Websocket,
+    // Polls /solves, for when the events websocket is blocked or disabled
+    Poll,
+    // Uses the websocket, but polls in between reconnects once the circuit breaker opens
+    Auto
+}
+// Which platform players, teams, challenges and solves are read from
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SourceKind {
+    #[default]
+    Berg,
+    Ctfd,
+    Rctf,
+    // Any API described by endpoints and field paths in [json]
+    Json
+}
+impl SourceKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SourceKind::Berg => "berg",
+            SourceKind::Ctfd => "ctfd",
+            SourceKind::Rctf => "rctf",
+            SourceKind::Json => "json"
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct CtfdConfig {
+    // The CTFd instance itself, not its /api/v1
+    pub(crate) url: Url,
+    // An admin token sees hidden challenges and every submission
+    #[serde(default)]
+    pub(crate) token: String,
+    // Read into token while loading, like webhook tokens
+    pub(crate) token_file: Option<PathBuf>
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct RctfConfig {
+    // The rCTF instance itself, not its /api/v1
+    pub(crate) url: Url,
+    // The team token from the team's login link, exchanged for an auth token on login
+    #[serde(default)]
+    pub(crate) team_token: String,
+    // Read into team_token while loading, like webhook tokens
+    pub(crate) team_token_file: Option<PathBuf>
+}
+// Describes where things live in the responses of an API Dal has no adapter for. Paths are dot
+// separated object keys and array indices, e.g. "data.solves" or "user.0.id", and an empty path is
+// the whole response
+#[derive(Deserialize, Clone)]
+pub(crate) struct JsonSourceConfig {
+    // Sent with every request, e.g. an Authorization header
+    #[serde(default)]
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) solves: JsonSolvesEndpoint,
+    // Players and teams are taken from the solves themselves without this
+    pub(crate) players: Option<JsonPlayersEndpoint>,
+    // Challenges are only known by the name in each solve without this, and releases aren't announced
+    pub(crate) challenges: Option<JsonChallengesEndpoint>,
+    // Required for scoreboard posts
+    pub(crate) scoreboard: Option<JsonScoreboardEndpoint>
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct JsonSolvesEndpoint {
+    pub(crate) url: Url,
+    // Where the list of solves is, each path below is relative to one solve
+    #[serde(default)]
+    pub(crate) items: String,
+    pub(crate) player_id: String,
+    pub(crate) player_name: Option<String>,
+    pub(crate) team_id: Option<String>,
+    pub(crate) team_name: Option<String>,
+    pub(crate) challenge: String,
+    // RFC 3339 or a unix timestamp in seconds or milliseconds. Without it solves are only
+    // deduplicated against the database
+    pub(crate) timestamp: Option<String>
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct JsonPlayersEndpoint {
+    pub(crate) url: Url,
+    #[serde(default)]
+    pub(crate) items: String,
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) team_id: Option<String>,
+    pub(crate) team_name: Option<String>
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct JsonChallengesEndpoint {
+    pub(crate) url: Url,
+    #[serde(default)]
+    pub(crate) items: String,
+    // Has to match what the solves endpoint calls the challenge
+    pub(crate) name: String,
+    pub(crate) display_name: Option<String>,
+    pub(crate) category: Option<String>,
+    pub(crate) points: Option<String>,
+    pub(crate) hidden: Option<String>
+}
+#[derive(Deserialize, Clone)]
+pub(crate) struct JsonScoreboardEndpoint {
+    pub(crate) url: Url,
+    #[serde(default)]
+    pub(crate) items: String,
+    pub(crate) team_id: String,
+    pub(crate) team_name: String,
+    pub(crate) score: String
+}
+// Each secret can be given inline or as a file, files are read again whenever berg answers with a 401
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum BergAuthConfig {
+    Bearer {
+        token: Option<String>,
+        token_file: Option<PathBuf>
+    },
+    ApiKey {
+        #[serde(default = "default_api_key_header")]
+        header: String,
+        key: Option<String>,
+        key_file: Option<PathBuf>
+    },
+    // Logs in with a JSON username and password and sends back the cookies berg sets
+    Session {
+        #[serde(default = "default_login_path")]
+        login_path: String,
+        username: String,
+        password: Option<String>,
+        password_file: Option<PathBuf>
+    }
+}
+impl BergAuthConfig {
+    fn secret_name(&self) -> &'static str {
+        match self {
+            BergAuthConfig::Bearer { .. } => "token",
+            BergAuthConfig::ApiKey { .. } => "key",
+            BergAuthConfig::Session { .. } => "password"
+        }
+    }
+    fn has_one_secret(&self) -> bool {
+        let (value, file) = match self {
+            BergAuthConfig::Bearer { token, token_file } => (token, token_file),
+            BergAuthConfig::ApiKey { key, key_file, .. } => (key, key_file),
+            BergAuthConfig::Session { password, password_file, .. } => (password, password_file)
+        };
+        value.is_some() != file.is_some()
+    }
+}
+fn default_api_key_header() -> String {
+    "X-API-Key".to_string()
+}
+fn default_login_path() -> String {
+    "login".to_string()
+}
+// Alerts go to webhooks with the ops role, nothing is sent if there are none
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct AlertingConfig {
+    // Minimum time between two alerts about the same problem
+    pub(crate) cooldown_minutes: u64,
+    // New failures per minute across all error counters
+    pub(crate) errors_per_minute: u32,
+    pub(crate) websocket_down_minutes: u64,
+    pub(crate) stuck_solve_minutes: u64
+}
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        Self {
+            cooldown_minutes: 15,
+            errors_per_minute: 10,
+            websocket_down_minutes: 5,
+            stuck_solve_minutes: 5
+        }
+    }
+}
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct ReadinessConfig {
+    // How long the events websocket may be down before /readyz fails, reconnects are usually quick
+    pub(crate) websocket_grace_seconds: u64,
+    // Roles that need at least one webhook for /readyz to pass
+    pub(crate) required_roles: Vec<WebhookRole>
+}
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            websocket_grace_seconds: 60,
+            required_roles: vec![WebhookRole::FirstBlood, WebhookRole::Solve]
+        }
+    }
+}
+
+impl Config {
+    // The config file is optional when everything required is set through the environment, unless
+    // a path was explicitly given
+    pub(crate) async fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let table = match path {
+            Some(path) => read_config_table(path).await?,
+            None => match read_config_table(Path::new(DEFAULT_CONFIG_PATH)).await {
+                Err(ConfigError::FailedToRead(_, error)) if error.kind() == ErrorKind::NotFound => toml::Table::new(),
+                result => result?
+            }
+        };
+        Self::from_table(table, |env_name| std::env::var(env_name).ok()).await
+    }
+    // Split from load so tests can pass their own environment
+    async fn from_table(mut table: toml::Table, env_var: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        // Renamed keys only come from the file, so the environment can still override them
+        for (old_key, new_key) in RENAMED_KEYS {
+            if let Some(value) = table.remove(old_key) && !table.contains_key(new_key) {
+                table.insert(new_key.to_string(), value);
+            }
+        }
+
+        let mut problems = Vec::new();
+        let has_events = table.get("events").and_then(toml::Value::as_array).is_some_and(|events| !events.is_empty());
+        let mut overridden_keys = HashSet::new();
+        for (key, env_name) in ENV_OVERRIDES {
+            if overridden_keys.contains(key) {
+                continue;
+            }
+            match read_env_override(env_name, &env_var).await {
+                Ok(Some(_)) if has_events && EVENT_OVERRIDE_KEYS.contains(&key) => {
+                    tracing::warn!("{env_name} is ignored because events is set, set {key} in each event instead");
+                },
+                Ok(Some(value)) => {
+                    table.insert(key.to_string(), toml::Value::String(value));
+                    overridden_keys.insert(key);
+                },
+                Ok(None) => {},
+                Err(problem) => problems.push(problem)
+            }
+        }
+        for key in REQUIRED_KEYS {
+            if !table.contains_key(key) {
+                let env_name = ENV_OVERRIDES.iter().find(|(override_key, _)| *override_key == key).map(|(_, env_name)| *env_name).unwrap_or_default();
+                problems.push(format!("{key} is not set, set it in the config file or with {env_name}"));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+
+        let mut config = toml::Value::Table(table)
+            .try_into::<Config>()
+            .map_err(|error| ConfigError::Invalid(vec![error.message().to_string()]))?;
+        let has_several_events = config.events().len() > 1;
+        for event in config.events_mut() {
+            for problem in event.read_secret_files().await {
+                problems.push(event.describe_problem(problem, has_several_events));
+            }
+        }
+        problems.extend(config.validate());
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+        for event in config.events() {
+            if event.berg_connection.alert_after_seconds.is_some() || event.berg_connection.alert_webhook_id.is_some() {
+                tracing::warn!(event = event.name, "berg_connection.alert_after_seconds and alert_webhook_id are deprecated, give the webhook the ops role and set alerting.websocket_down_minutes instead");
+            }
+        }
+        Ok(config)
+    }
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !self.events.is_empty() && (self.event.berg_api_base.is_some() || self.event.ctfd.is_some() || self.event.rctf.is_some() || self.event.json.is_some() || !self.event.webhooks.is_empty() || !self.event.announcements.is_empty()) {
+            problems.push("events is set, so top-level event settings like berg_api_base and webhooks are ignored, move them into an event".to_string());
+        }
+        let mut event_names = HashSet::new();
+        for event in self.events() {
+            if !event_names.insert(&event.name) {
+                problems.push(format!("event {} is configured more than once", event.name));
+            }
+            // Names end up in metric labels and query strings
+            if event.name.is_empty() || !event.name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_' || character == '-') {
+                problems.push(format!("event name {:?} may only contain letters, digits, _ and -", event.name));
+            }
+            for problem in event.validate() {
+                problems.push(event.describe_problem(problem, self.events().len() > 1));
+            }
+        }
+        if self.database_url.is_empty() {
+            problems.push("database_url is empty".to_string());
+        }
+
+        if self.alerting.errors_per_minute == 0 {
+            problems.push("alerting.errors_per_minute must be at least 1".to_string());
+        }
+
+        if !self.http.base_path.is_empty() && (!self.http.base_path.starts_with('/') || self.http.base_path.ends_with('/')) {
+            problems.push(format!("http.base_path {} must start with a / and not end with one", self.http.base_path));
+        }
+        let listeners = [("http", &self.http.listen, self.http.tls.as_ref())].into_iter()
+            .chain(self.http.admin.as_ref().map(|admin| ("http.admin", &admin.listen, admin.tls.as_ref())));
+        for (name, listen, tls) in listeners {
+            if let (ListenAddress::Unix(_), Some(_)) = (listen, tls) {
+                problems.push(format!("{name}.tls is not supported on unix sockets"));
+            }
+        }
+
+        problems
+    }
+}
+impl EventConfig {
+    fn describe_problem(&self, problem: String, has_several_events: bool) -> String {
+        if has_several_events {
+            format!("event {}: {problem}", self.name)
+        } else {
+            problem
+        }
+    }
+    // Files are read into the inline fields so the rest of Dal never has to care
+    async fn read_secret_files(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for webhook in &mut self.webhooks {
+            let Some(token_file) = &webhook.token_file else {
+                continue;
+            };
+            if !webhook.token.is_empty() {
+                problems.push(format!("webhook {} has both token and token_file", webhook.id));
+                continue;
+            }
+            match tokio::fs::read_to_string(token_file).await {
+                Ok(token) => webhook.token = token.trim_end().to_string(),
+                Err(error) => problems.push(format!("failed to read token_file {} for webhook {}: {error}", token_file.display(), webhook.id))
+            }
+        }
+        if let Some(ctfd) = &mut self.ctfd && let Some(token_file) = &ctfd.token_file {
+            if !ctfd.token.is_empty() {
+                problems.push("ctfd has both token and token_file".to_string());
+            } else {
+                match tokio::fs::read_to_string(token_file).await {
+                    Ok(token) => ctfd.token = token.trim_end().to_string(),
+                    Err(error) => problems.push(format!("failed to read token_file {} for ctfd: {error}", token_file.display()))
+                }
+            }
+        }
+        if let Some(rctf) = &mut self.rctf && let Some(team_token_file) = &rctf.team_token_file {
+            if !rctf.team_token.is_empty() {
+                problems.push("rctf has both team_token and team_token_file".to_string());
+            } else {
+                match tokio::fs::read_to_string(team_token_file).await {
+                    Ok(team_token) => rctf.team_token = team_token.trim_end().to_string(),
+                    Err(error) => problems.push(format!("failed to read team_token_file {} for rctf: {error}", team_token_file.display()))
+                }
+            }
+        }
+        problems
+    }
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        // Url::join replaces the last path segment unless the base ends with a slash
+        match (self.source, &self.berg_api_base, &self.ctfd) {
+            (SourceKind::Berg, None, _) => problems.push("berg_api_base is not set, set it in the config file or with DAL_BERG_API_BASE".to_string()),
+            (SourceKind::Berg, Some(berg_api_base), _) if !berg_api_base.path().ends_with('/') => {
+                problems.push(format!("berg_api_base {berg_api_base} must end with a /"));
+            },
+            (SourceKind::Ctfd, _, None) => problems.push("source is ctfd but [ctfd] is not set".to_string()),
+            (SourceKind::Ctfd, _, Some(ctfd)) => {
+                if !ctfd.url.path().ends_with('/') {
+                    problems.push(format!("ctfd.url {} must end with a /", ctfd.url));
+                }
+                if ctfd.token.is_empty() {
+                    problems.push("ctfd has no token or token_file".to_string());
+                } else if http::HeaderValue::try_from(format!("Token {}", ctfd.token)).is_err() {
+                    problems.push("ctfd token isn't a valid header value".to_string());
+                }
+            },
+            _ => {}
+        }
+        match (self.source, &self.rctf) {
+            (SourceKind::Rctf, None) => problems.push("source is rctf but [rctf] is not set".to_string()),
+            (SourceKind::Rctf, Some(rctf)) => {
+                if !rctf.url.path().ends_with('/') {
+                    problems.push(format!("rctf.url {} must end with a /", rctf.url));
+                }
+                if rctf.team_token.is_empty() {
+                    problems.push("rctf has no team_token or team_token_file".to_string());
+                }
+            },
+            _ => {}
+        }
+        match (self.source, &self.json) {
+            (SourceKind::Json, None) => problems.push("source is json but [json] is not set".to_string()),
+            (SourceKind::Json, Some(json)) => {
+                for (name, value) in &json.headers {
+                    if http::HeaderName::try_from(name.as_str()).is_err() || http::HeaderValue::try_from(value.as_str()).is_err() {
+                        problems.push(format!("json.headers.{name} isn't a valid header"));
+                    }
+                }
+                let scoreboard_posts = self.scoreboard.interval_minutes.is_some() || !self.scoreboard.post_at.is_empty() || self.scoreboard.post_at_end;
+                if scoreboard_posts && json.scoreboard.is_none() {
+                    problems.push("scoreboard posts are enabled but json.scoreboard is not set".to_string());
+                }
+            },
+            _ => {}
+        }
+
+        let mut webhook_ids = HashSet::new();
+        for webhook in &self.webhooks {
+            if !webhook_ids.insert(webhook.id) {
+                problems.push(format!("webhook {} is configured more than once", webhook.id));
+            }
+            if webhook.token.is_empty() {
+                problems.push(format!("webhook {} has no token or token_file", webhook.id));
+            }
+            if webhook.roles.is_empty() {
+                problems.push(format!("webhook {} has no roles", webhook.id));
+            }
+        }
+
+        if let (Some(start_time), Some(end_time)) = (self.window.start_time, self.window.end_time) && start_time >= end_time {
+            problems.push(format!("start_time {start_time} must be before end_time {end_time}"));
+        }
+        if self.window.window_announcements.remaining_minutes.contains(&0) {
+            problems.push("window_announcements.remaining_minutes must be at least 1, use window_announcements.end for the end".to_string());
+        }
+
+        let mut announcement_names = HashSet::new();
+        for announcement in &self.announcements {
+            if !announcement_names.insert(&announcement.name) {
+                problems.push(format!("announcement {} is configured more than once", announcement.name));
+            }
+            if let Some(webhook_id) = announcement.webhook_id && !webhook_ids.contains(&webhook_id) {
+                problems.push(format!("announcement {} uses unknown webhook {webhook_id}", announcement.name));
+            }
+        }
+
+        if self.scoreboard.interval_minutes == Some(0) {
+            problems.push("scoreboard.interval_minutes must be at least 1".to_string());
+        }
+        if self.scoreboard.top == 0 {
+            problems.push("scoreboard.top must be at least 1".to_string());
+        }
+
+        let berg_connection = &self.berg_connection;
+        if berg_connection.reconnect_initial_seconds == 0 || berg_connection.reconnect_initial_seconds > berg_connection.reconnect_max_seconds {
+            problems.push("berg_connection.reconnect_initial_seconds must be between 1 and reconnect_max_seconds".to_string());
+        }
+        if berg_connection.poll_interval_seconds == 0 {
+            problems.push("berg_connection.poll_interval_seconds must be at least 1".to_string());
+        }
+        match (berg_connection.alert_after_seconds, berg_connection.alert_webhook_id) {
+            (Some(_), None) => problems.push("berg_connection.alert_after_seconds requires alert_webhook_id".to_string()),
+            (_, Some(webhook_id)) if !webhook_ids.contains(&webhook_id) => problems.push(format!("berg_connection.alert_webhook_id uses unknown webhook {webhook_id}")),
+            _ => {}
+        }
+
+        if let Some(berg_auth) = &self.berg_auth {
+            if !berg_auth.has_one_secret() {
+                let secret_name = berg_auth.secret_name();
+                problems.push(format!("berg_auth needs exactly one of {secret_name} and {secret_name}_file"));
+            }
+            if let BergAuthConfig::ApiKey { header, .. } = berg_auth && http::HeaderName::try_from(header.as_str()).is_err() {
+                problems.push(format!("berg_auth.header {header} isn't a valid header name"));
+            }
+        }
+
+        problems
+    }
+}
+
+async fn read_config_table(path: &Path) -> Result<toml::Table, ConfigError> {
+    let raw_config = tokio::fs::read_to_string(path).await.map_err(|error| ConfigError::FailedToRead(path.to_path_buf(), error))?;
+    toml::from_str::<toml::Table>(&raw_config).map_err(|error| ConfigError::Invalid(vec![format!("{}: {}", path.display(), error.message())]))
+}
+
+// Returns a description of the problem if the override is set but unusable
+async fn read_env_override(env_name: &str, env_var: &impl Fn(&str) -> Option<String>) -> Result<Option<String>, String> {
+    let file_env_name = format!("{env_name}_FILE");
+    match (env_var(env_name), env_var(&file_env_name)) {
+        (Some(_), Some(_)) => Err(format!("only one of {env_name} and {file_env_name} can be set")),
+        (Some(value), None) => Ok(Some(value)),
+        (None, Some(path)) => match tokio::fs::read_to_string(&path).await {
+            Ok(value) => Ok(Some(value.trim_end().to_string())),
+            Err(error) => Err(format!("failed to read {file_env_name} {path}: {error}"))
+        },
+        (None, None) => Ok(None)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ConfigError {
+    #[error("failed to read config file {}: {}", .0.display(), .1)]
+    FailedToRead(PathBuf, std::io::Error),
+    #[error("invalid config:{}", .0.iter().map(|problem| format!("\n  - {problem}")).collect::<String>())]
+    Invalid(Vec<String>)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use chrono::{DateTime, TimeZone, Utc};
+    use uuid::Uuid;
+    use crate::models::challenge::Challenge;
+    use crate::models::player::Player;
+    use crate::models::solve::Solve;
+    use crate::models::team::Team;
+    use super::{Config, ConfigError, EventWindowConfig, IgnoreConfig};
+
+    async fn load(raw_config: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let table = toml::from_str::<toml::Table>(raw_config).unwrap();
+        let env = env.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<HashMap<_, _>>();
+        Config::from_table(table, |env_name| env.get(env_name).cloned()).await
+    }
+    fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(error) => panic!("unexpected error {error}"),
+            Ok(_) => panic!("config loaded without problems")
+        }
+    }
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("dal-config-test-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+    fn time(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, 20, hour, 0, 0).unwrap()
+    }
+
+    const BERG_EVENT: &str = "berg_api_base = \"https://berg.example/api/\"\n";
+
+    #[tokio::test]
+    async fn environment_overrides_the_file() {
+        let raw_config = format!("{BERG_EVENT}database_url = \"sqlite:file.db\"\nadmin_token = \"from-file\"");
+        let config = load(&raw_config, &[("DAL_DATABASE_URL", "sqlite::memory:"), ("DAL_START_TIME", "2025-10-20T10:00:00Z")]).await.unwrap();
+        assert_eq!(config.database_url, "sqlite::memory:");
+        assert_eq!(config.admin_token.as_deref(), Some("from-file"));
+        assert_eq!(config.event.window.start_time, Some(time(10)));
+    }
+
+    #[tokio::test]
+    async fn old_database_url_environment_still_overrides_the_file() {
+        let raw_config = format!("{BERG_EVENT}database_url = \"sqlite:file.db\"");
+        let config = load(&raw_config, &[("DAL_POSTGRES_URL", "sqlite:old.db")]).await.unwrap();
+        assert_eq!(config.database_url, "sqlite:old.db");
+
+        let config = load(&raw_config, &[("DAL_POSTGRES_URL", "sqlite:old.db"), ("DAL_DATABASE_URL", "sqlite:new.db")]).await.unwrap();
+        assert_eq!(config.database_url, "sqlite:new.db");
+    }
+
+    #[tokio::test]
+    async fn renamed_keys_are_read_when_the_new_one_is_missing() {
+        let config = load(&format!("{BERG_EVENT}postgres_url = \"sqlite:old.db\""), &[]).await.unwrap();
+        assert_eq!(config.database_url, "sqlite:old.db");
+
+        let config = load(&format!("{BERG_EVENT}postgres_url = \"sqlite:old.db\"\ndatabase_url = \"sqlite:new.db\""), &[]).await.unwrap();
+        assert_eq!(config.database_url, "sqlite:new.db");
+    }
+
+    #[tokio::test]
+    async fn event_overrides_are_ignored_once_events_is_set() {
+        let raw_config = "database_url = \"sqlite::memory:\"\n[[events]]\nname = \"main\"\nberg_api_base = \"https://berg.example/api/\"";
+        let config = load(raw_config, &[("DAL_BERG_API_BASE", "https://other.example/api/"), ("DAL_END_TIME", "2025-10-20T12:00:00Z")]).await.unwrap();
+        assert!(config.event.berg_api_base.is_none());
+        assert!(config.event.window.end_time.is_none());
+        assert_eq!(config.events[0].berg_api_base.as_ref().map(|url| url.as_str()), Some("https://berg.example/api/"));
+    }
+
+    #[tokio::test]
+    async fn file_variants_are_read_and_trimmed() {
+        let admin_token_path = temp_file("admin-token", "admin-secret\n");
+        let webhook_token_path = temp_file("webhook-token", "webhook-secret\n");
+        let raw_config = format!("{BERG_EVENT}database_url = \"sqlite::memory:\"\n[[webhooks]]\nid = 1\ntoken_file = {:?}\nroles = [\"solve\"]", webhook_token_path.display().to_string());
+        let config = load(&raw_config, &[("DAL_ADMIN_TOKEN_FILE", &admin_token_path.display().to_string())]).await.unwrap();
+        assert_eq!(config.admin_token.as_deref(), Some("admin-secret"));
+        assert_eq!(config.event.webhooks[0].token, "webhook-secret");
+
+        let problems = problems(load(&raw_config, &[("DAL_ADMIN_TOKEN", "inline"), ("DAL_ADMIN_TOKEN_FILE", "/nonexistent")]).await);
+        assert_eq!(problems, ["only one of DAL_ADMIN_TOKEN and DAL_ADMIN_TOKEN_FILE can be set"]);
+
+        std::fs::remove_file(admin_token_path).unwrap();
+        std::fs::remove_file(webhook_token_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_required_keys_are_reported() {
+        let problems = problems(load(BERG_EVENT, &[]).await);
+        assert_eq!(problems, ["database_url is not set, set it in the config file or with DAL_DATABASE_URL"]);
+    }
+
+    #[tokio::test]
+    async fn every_problem_is_reported_at_once() {
+        let raw_config = "database_url = \"sqlite::memory:\"\n[alerting]\nerrors_per_minute = 0\n[[events]]\nname = \"main event\"\n[[events]]\nname = \"main event\"\nsource = \"ctfd\"";
+        let problems = problems(load(raw_config, &[]).await);
+        assert_eq!(problems, [
+            "event name \"main event\" may only contain letters, digits, _ and -",
+            "event main event: berg_api_base is not set, set it in the config file or with DAL_BERG_API_BASE",
+            "event main event is configured more than once",
+            "event name \"main event\" may only contain letters, digits, _ and -",
+            "event main event: source is ctfd but [ctfd] is not set",
+            "alerting.errors_per_minute must be at least 1"
+        ]);
+    }
+
+    #[tokio::test]
+    async fn ctfd_tokens_must_fit_in_a_header() {
+        let raw_config = "database_url = \"sqlite::memory:\"\nsource = \"ctfd\"\n[ctfd]\nurl = \"https://ctfd.example/\"\ntoken = \"ctfd_abc\\ndef\"";
+        let problems = problems(load(raw_config, &[]).await);
+        assert_eq!(problems, ["ctfd token isn't a valid header value"]);
+    }
+
+    #[test]
+    fn ignore_reason_checks_challenges_before_players_and_teams() {
+        let ignored_player_id = Uuid::new_v4();
+        let ignore = IgnoreConfig {
+            challenges: ["sanity".to_string()].into(),
+            categories: ["misc".to_string()].into(),
+            player_ids: [ignored_player_id].into(),
+            team_names: ["organisers".to_string()].into(),
+            admin_players: true,
+            hidden_teams: true,
+            hidden_challenges: true,
+            ..IgnoreConfig::default()
+        };
+        let solve = |player_id, challenge_name: &str| Solve { player_id, challenge_name: challenge_name.to_string(), created_at: None };
+        let challenge = |category: &str, hidden| Challenge {
+            name: "pwn1".to_string(),
+            display_name: None,
+            category: Some(category.to_string()),
+            author: None,
+            difficulty: None,
+            points: None,
+            hidden
+        };
+        let player = |admin| Player { id: Uuid::new_v4(), name: "alice".to_string(), admin };
+        let team = |name: &str, hidden| Team { id: Uuid::new_v4(), name: name.to_string(), player_ids: Vec::new(), hidden };
+        let player_id = Uuid::new_v4();
+
+        assert_eq!(ignore.ignore_reason(&solve(player_id, "sanity"), None, None, None), Some("ignored challenge"));
+        assert_eq!(ignore.ignore_reason(&solve(ignored_player_id, "pwn1"), Some(&challenge("misc", false)), None, None), Some("ignored category"));
+        assert_eq!(ignore.ignore_reason(&solve(player_id, "pwn1"), Some(&challenge("pwn", true)), None, None), Some("hidden challenge"));
+        assert_eq!(ignore.ignore_reason(&solve(ignored_player_id, "pwn1"), None, None, None), Some("ignored player"));
+        assert_eq!(ignore.ignore_reason(&solve(player_id, "pwn1"), None, Some(&player(true)), None), Some("admin player"));
+        assert_eq!(ignore.ignore_reason(&solve(player_id, "pwn1"), None, None, Some(&team("organisers", false))), Some("ignored team"));
+        assert_eq!(ignore.ignore_reason(&solve(player_id, "pwn1"), None, None, Some(&team("hackers", true))), Some("hidden team"));
+        assert_eq!(ignore.ignore_reason(&solve(player_id, "pwn1"), Some(&challenge("pwn", false)), Some(&player(false)), Some(&team("hackers", false))), None);
+        assert_eq!(IgnoreConfig::default().ignore_reason(&solve(player_id, "sanity"), Some(&challenge("misc", true)), Some(&player(true)), Some(&team("organisers", true))), None);
+    }
+
+    #[test]
+    fn outside_reason_includes_start_and_excludes_end() {
+        let window = EventWindowConfig { start_time: Some(time(10)), end_time: Some(time(12)), ..EventWindowConfig::default() };
+        assert_eq!(window.outside_reason(time(9)), Some("before event start"));
+        assert_eq!(window.outside_reason(time(10)), None);
+        assert_eq!(window.outside_reason(time(11)), None);
+        assert_eq!(window.outside_reason(time(12)), Some("after event end"));
+        assert_eq!(EventWindowConfig::default().outside_reason(time(0)), None);
+    }
 }
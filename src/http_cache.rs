@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use http::header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use crate::berg_client::BergClient;
+use crate::sources::SourceError;
+
+// Validators from the last successful response, sent back so berg can answer with a 304
+#[derive(Default)]
+pub(crate) struct CacheValidators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>
+}
+impl CacheValidators {
+    fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let mut request = request;
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+        request
+    }
+    fn update(&mut self, headers: &HeaderMap) {
+        self.etag = headers.get(ETAG).cloned();
+        self.last_modified = headers.get(LAST_MODIFIED).cloned();
+    }
+}
+
+pub(crate) enum Conditional<T> {
+    Modified(T),
+    NotModified
+}
+
+pub(crate) struct ConditionalResponse<T> {
+    pub(crate) body: Conditional<T>,
+    pub(crate) max_age: Option<Duration>
+}
+
+pub(crate) async fn get_json<T: DeserializeOwned>(berg_client: &BergClient, url: Url, validators: &mut CacheValidators) -> Result<ConditionalResponse<T>, SourceError> {
+    let response = berg_client.get(url, |request| validators.apply(request)).await?;
+    let max_age = max_age(response.headers());
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(ConditionalResponse {
+            body: Conditional::NotModified,
+            max_age
+        });
+    }
+    let response = response.error_for_status()?;
+    let headers = response.headers().clone();
+    let body = response.json::<T>().await?;
+    validators.update(&headers);
+
+    Ok(ConditionalResponse {
+        body: Conditional::Modified(body),
+        max_age
+    })
+}
+
+fn max_age(headers: &HeaderMap) -> Option<Duration> {
+    let cache_control = headers.get(CACHE_CONTROL)?.to_str().ok()?;
+    let mut max_age = None;
+    for directive in cache_control.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            Some((name, value)) if name.eq_ignore_ascii_case("max-age") => {
+                max_age = value.trim_matches('"').parse::<u64>().ok().map(Duration::from_secs);
+            },
+            // Nothing may be reused, so poll as often as we are allowed to
+            _ if directive.eq_ignore_ascii_case("no-store") || directive.eq_ignore_ascii_case("no-cache") => return None,
+            _ => {}
+        }
+    }
+    max_age
+}
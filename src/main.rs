@@ -1,10 +1,20 @@
+use clap::Parser;
+
 mod app;
+mod cli;
 mod routers;
 mod state;
 mod services;
 mod models;
 mod config;
 mod repository;
+mod http_cache;
+mod report;
+mod tls;
+mod shutdown;
+mod backoff;
+mod berg_client;
+mod sources;
 
 const USER_AGENT: &str = "dal v/1.0.0 (https://github.com/Blorptopia/dal)";
 
@@ -12,8 +22,17 @@ const USER_AGENT: &str = "dal v/1.0.0 (https://github.com/Blorptopia/dal)";
 async fn main() {
     tracing_subscriber::fmt::init();
 
-    if let Err(error) = app::run().await {
-        tracing::error!(?error, "failed to run app");
-        std::process::exit(1);
+    let cli = cli::Cli::parse();
+    match app::run(cli).await {
+        Ok(()) => {},
+        // Listing every problem is more useful than a debug dump
+        Err(app::AppRunError::ConfigError(error)) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        },
+        Err(error) => {
+            tracing::error!(?error, "failed to run app");
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use twilight_model::id::Id as Snowflake;
+use twilight_model::id::marker::WebhookMarker;
+
+use crate::config::WebhookRole;
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct Announcement {
+    pub(crate) id: i32,
+    pub(crate) config_key: Option<String>,
+    pub(crate) announce_at: DateTime<Utc>,
+    pub(crate) role: WebhookRole,
+    pub(crate) webhook_id: Option<Snowflake<WebhookMarker>>,
+    pub(crate) content: String,
+    pub(crate) posted_at: Option<DateTime<Utc>>
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct NewAnnouncement {
+    pub(crate) announce_at: DateTime<Utc>,
+    pub(crate) role: WebhookRole,
+    pub(crate) webhook_id: Option<Snowflake<WebhookMarker>>,
+    pub(crate) content: String
+}
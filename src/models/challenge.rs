@@ -0,0 +1,39 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Challenge {
+    pub(crate) name: String,
+    pub(crate) display_name: Option<String>,
+    pub(crate) category: Option<String>,
+    pub(crate) author: Option<String>,
+    pub(crate) difficulty: Option<String>,
+    pub(crate) points: Option<u32>,
+    #[serde(default)]
+    pub(crate) hidden: bool
+}
+impl Challenge {
+    pub(crate) fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+    // Short "web, hard, 500 points, by someone" summary of whatever metadata berg has
+    pub(crate) fn details(&self) -> Option<String> {
+        let mut details = Vec::<String>::new();
+        if let Some(category) = &self.category {
+            details.push(category.clone());
+        }
+        if let Some(difficulty) = &self.difficulty {
+            details.push(difficulty.clone());
+        }
+        if let Some(points) = self.points {
+            details.push(format!("{points} points"));
+        }
+        if let Some(author) = &self.author {
+            details.push(format!("by {author}"));
+        }
+        if details.is_empty() {
+            return None;
+        }
+        Some(details.join(", "))
+    }
+}
@@ -2,3 +2,7 @@ pub(crate) mod solve;
 pub(crate) mod websocket;
 pub(crate) mod player;
 pub(crate) mod team;
+pub(crate) mod challenge;
+pub(crate) mod announcement;
+pub(crate) mod scoreboard;
+pub(crate) mod sent_solve;
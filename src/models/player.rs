@@ -4,5 +4,7 @@ use uuid::Uuid;
 #[derive(Deserialize, Debug, Clone)]
 pub(crate) struct Player {
     pub(crate) id: Uuid,
-    pub(crate) name: String
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) admin: bool
 }
@@ -0,0 +1,18 @@
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ScoreboardEntry {
+    pub(crate) team_id: Uuid,
+    pub(crate) team_name: String,
+    pub(crate) score: i64
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub(crate) struct RankedScoreboardEntry {
+    pub(crate) team_id: Uuid,
+    pub(crate) team_name: String,
+    pub(crate) rank: i32,
+    pub(crate) score: i64
+}
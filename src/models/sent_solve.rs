@@ -0,0 +1,17 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
+pub(crate) struct SentSolve {
+    pub(crate) solve_id: i32,
+    pub(crate) challenge_name: String,
+    pub(crate) player_id: Uuid,
+    pub(crate) player_name: Option<String>,
+    pub(crate) team_id: Option<Uuid>,
+    pub(crate) team_name: Option<String>,
+    pub(crate) challenge_category: Option<String>,
+    pub(crate) solved_at: Option<DateTime<Utc>>,
+    pub(crate) is_first_blood: bool,
+    pub(crate) ignore_reason: Option<String>
+}
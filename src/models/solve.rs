@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use uuid::Uuid;
 
@@ -5,5 +6,7 @@ use uuid::Uuid;
 #[serde(rename_all = "camelCase")]
 pub(crate) struct Solve {
     pub(crate) player_id: Uuid,
-    pub(crate) challenge_name: String
+    pub(crate) challenge_name: String,
+    #[serde(default)]
+    pub(crate) created_at: Option<DateTime<Utc>>
 }
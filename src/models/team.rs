@@ -4,7 +4,10 @@ use uuid::Uuid;
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct Team {
+    pub(crate) id: Uuid,
     pub(crate) name: String,
     #[serde(rename = "players")]
-    pub(crate) player_ids: Vec<Uuid>
+    pub(crate) player_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub(crate) hidden: bool
 }
@@ -1,9 +1,192 @@
-use serde::{Deserialize};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
 
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
 use crate::models::solve::Solve;
+use crate::models::team::Team;
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase", tag = "type", content = "message")]
+// Newest version of berg's event stream Dal understands, berg leaves the version out on 1
+pub(crate) const SUPPORTED_EVENT_VERSION: u32 = 1;
+
+#[derive(Debug)]
 pub(crate) enum WebSocketResponse {
     Solve(Solve),
+    PlayerCreate(Player),
+    PlayerUpdate(Player),
+    TeamCreate(Team),
+    TeamUpdate(Team),
+    ChallengeCreate(Challenge),
+    ChallengeUpdate(Challenge),
+    // An event type berg added after this was written, with the type it was sent as
+    Unknown(String)
+}
+impl WebSocketResponse {
+    pub(crate) fn kind(&self) -> EventKind {
+        match self {
+            WebSocketResponse::Solve(_) => EventKind::Solve,
+            WebSocketResponse::PlayerCreate(_) => EventKind::PlayerCreate,
+            WebSocketResponse::PlayerUpdate(_) => EventKind::PlayerUpdate,
+            WebSocketResponse::TeamCreate(_) => EventKind::TeamCreate,
+            WebSocketResponse::TeamUpdate(_) => EventKind::TeamUpdate,
+            WebSocketResponse::ChallengeCreate(_) => EventKind::ChallengeCreate,
+            WebSocketResponse::ChallengeUpdate(_) => EventKind::ChallengeUpdate,
+            WebSocketResponse::Unknown(_) => EventKind::Unknown
+        }
+    }
+}
+
+// A single message from the events websocket
+#[derive(Debug)]
+pub(crate) struct Event {
+    pub(crate) version: u32,
+    pub(crate) response: WebSocketResponse
+}
+impl Event {
+    pub(crate) fn parse(raw: &str) -> Result<Self, EventParseError> {
+        let envelope = serde_json::from_str::<Envelope>(raw).map_err(EventParseError::InvalidEnvelope)?;
+        let kind = EventKind::from_type(&envelope.event_type);
+        let response = match kind {
+            EventKind::Solve => WebSocketResponse::Solve(parse_message(kind, envelope.message)?),
+            EventKind::PlayerCreate => WebSocketResponse::PlayerCreate(parse_message(kind, envelope.message)?),
+            EventKind::PlayerUpdate => WebSocketResponse::PlayerUpdate(parse_message(kind, envelope.message)?),
+            EventKind::TeamCreate => WebSocketResponse::TeamCreate(parse_message(kind, envelope.message)?),
+            EventKind::TeamUpdate => WebSocketResponse::TeamUpdate(parse_message(kind, envelope.message)?),
+            EventKind::ChallengeCreate => WebSocketResponse::ChallengeCreate(parse_message(kind, envelope.message)?),
+            EventKind::ChallengeUpdate => WebSocketResponse::ChallengeUpdate(parse_message(kind, envelope.message)?),
+            EventKind::Unknown => WebSocketResponse::Unknown(envelope.event_type)
+        };
+        Ok(Self {
+            version: envelope.version,
+            response
+        })
+    }
+}
+
+// The part every event shares, the message is only parsed once the type is known
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    message: Value,
+    #[serde(default = "default_version")]
+    version: u32
+}
+fn default_version() -> u32 {
+    1
+}
+
+fn parse_message<T: DeserializeOwned>(kind: EventKind, message: Value) -> Result<T, EventParseError> {
+    serde_json::from_value(message).map_err(|error| EventParseError::InvalidMessage(kind, error))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum EventKind {
+    Solve,
+    PlayerCreate,
+    PlayerUpdate,
+    TeamCreate,
+    TeamUpdate,
+    ChallengeCreate,
+    ChallengeUpdate,
+    Unknown
+}
+impl EventKind {
+    // In declaration order, so a kind can be used as an index
+    pub(crate) const ALL: [EventKind; 8] = [
+        EventKind::Solve,
+        EventKind::PlayerCreate,
+        EventKind::PlayerUpdate,
+        EventKind::TeamCreate,
+        EventKind::TeamUpdate,
+        EventKind::ChallengeCreate,
+        EventKind::ChallengeUpdate,
+        EventKind::Unknown
+    ];
+    // The type berg sends, except for unknown which only exists on our side
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Solve => "solve",
+            EventKind::PlayerCreate => "playerCreate",
+            EventKind::PlayerUpdate => "playerUpdate",
+            EventKind::TeamCreate => "teamCreate",
+            EventKind::TeamUpdate => "teamUpdate",
+            EventKind::ChallengeCreate => "challengeCreate",
+            EventKind::ChallengeUpdate => "challengeUpdate",
+            EventKind::Unknown => "unknown"
+        }
+    }
+    fn from_type(event_type: &str) -> Self {
+        Self::ALL.into_iter()
+            .find(|kind| *kind != EventKind::Unknown && kind.as_str() == event_type)
+            .unwrap_or(EventKind::Unknown)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum EventParseError {
+    #[error("event isn't an object with a type")]
+    InvalidEnvelope(serde_json::Error),
+    #[error("{} event doesn't look like expected", .0.as_str())]
+    InvalidMessage(EventKind, serde_json::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, EventKind, EventParseError, WebSocketResponse};
+
+    const PLAYER_ID: &str = "0b6a6c1e-5f3c-4d1e-9a8b-6f0e2d4c1a2b";
+
+    #[test]
+    fn parses_solve_without_version() {
+        let raw = format!(r#"{{"type":"solve","message":{{"playerId":"{PLAYER_ID}","challengeName":"pwn-1"}}}}"#);
+        let event = Event::parse(&raw).unwrap();
+        assert_eq!(event.version, 1);
+        let WebSocketResponse::Solve(solve) = event.response else {
+            panic!("expected a solve, got {:?}", event.response);
+        };
+        assert_eq!(solve.player_id.to_string(), PLAYER_ID);
+        assert_eq!(solve.challenge_name, "pwn-1");
+        assert!(solve.created_at.is_none());
+    }
+
+    #[test]
+    fn keeps_newer_version() {
+        let raw = format!(r#"{{"type":"solve","version":2,"message":{{"playerId":"{PLAYER_ID}","challengeName":"pwn-1","createdAt":"2025-01-01T12:00:00Z","extra":true}}}}"#);
+        let event = Event::parse(&raw).unwrap();
+        assert_eq!(event.version, 2);
+        assert_eq!(event.response.kind(), EventKind::Solve);
+    }
+
+    #[test]
+    fn unknown_type_keeps_its_name() {
+        let event = Event::parse(r#"{"type":"hintUnlock","message":{"anything":1}}"#).unwrap();
+        let WebSocketResponse::Unknown(event_type) = event.response else {
+            panic!("expected an unknown event, got {:?}", event.response);
+        };
+        assert_eq!(event_type, "hintUnlock");
+    }
+
+    #[test]
+    fn unknown_type_named_like_our_placeholder_stays_unknown() {
+        let event = Event::parse(r#"{"type":"unknown"}"#).unwrap();
+        assert!(matches!(event.response, WebSocketResponse::Unknown(event_type) if event_type == "unknown"));
+    }
+
+    #[test]
+    fn rejects_malformed_envelope() {
+        for raw in ["not json", "[]", r#"{"message":{}}"#, r#"{"type":1}"#, r#"{"type":"solve","version":-1}"#] {
+            assert!(matches!(Event::parse(raw), Err(EventParseError::InvalidEnvelope(_))), "{raw} should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_message_of_known_type() {
+        let error = Event::parse(r#"{"type":"teamUpdate","message":{"id":"not a uuid"}}"#).unwrap_err();
+        assert!(matches!(error, EventParseError::InvalidMessage(EventKind::TeamUpdate, _)));
+        let error = Event::parse(r#"{"type":"solve"}"#).unwrap_err();
+        assert!(matches!(error, EventParseError::InvalidMessage(EventKind::Solve, _)));
+    }
 }
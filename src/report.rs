@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// An announced solve as stored in the repository
+#[derive(sqlx::FromRow)]
+pub(crate) struct ReportSolve {
+    pub(crate) challenge_name: String,
+    pub(crate) player_id: Uuid,
+    pub(crate) player_name: Option<String>,
+    pub(crate) team_id: Option<Uuid>,
+    pub(crate) team_name: Option<String>,
+    pub(crate) challenge_category: Option<String>,
+    pub(crate) solved_at: Option<DateTime<Utc>>,
+    pub(crate) is_first_blood: bool,
+    pub(crate) released_at: Option<DateTime<Utc>>,
+    // Null without a release, true when released_at is only when dal first saw the challenge
+    pub(crate) is_seeded_release: Option<bool>
+}
+
+#[derive(Deserialize, ValueEnum, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReportFormat {
+    #[default]
+    Markdown,
+    Json,
+    Csv
+}
+impl ReportFormat {
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "text/markdown; charset=utf-8",
+            ReportFormat::Json => "application/json",
+            ReportFormat::Csv => "text/csv; charset=utf-8"
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Report {
+    generated_at: DateTime<Utc>,
+    challenges: Vec<ChallengeReport>,
+    categories: Vec<CategoryReport>,
+    teams: Vec<TeamReport>
+}
+#[derive(Serialize)]
+struct ChallengeReport {
+    challenge_name: String,
+    category: Option<String>,
+    solve_count: usize,
+    first_blood: Option<FirstBloodReport>
+}
+#[derive(Serialize)]
+struct FirstBloodReport {
+    player_name: String,
+    team_name: Option<String>,
+    solved_at: Option<DateTime<Utc>>,
+    seconds_from_release: Option<i64>
+}
+#[derive(Serialize)]
+struct CategoryReport {
+    category: String,
+    challenge_count: usize,
+    solve_count: usize
+}
+#[derive(Serialize)]
+struct TeamReport {
+    team_name: String,
+    solve_count: usize,
+    last_solve_at: Option<DateTime<Utc>>,
+    solves: Vec<TeamSolveReport>
+}
+#[derive(Serialize)]
+struct TeamSolveReport {
+    challenge_name: String,
+    player_name: String,
+    solved_at: Option<DateTime<Utc>>
+}
+
+impl Report {
+    // Solves must be in the order they happened, start_time is used when a challenge was
+    // released before the event started. Challenges that were already visible when dal first saw
+    // them have no known release, so no time from release
+    pub(crate) fn build(solves: Vec<ReportSolve>, start_time: Option<DateTime<Utc>>) -> Self {
+        let mut challenges = Vec::<ChallengeReport>::new();
+        let mut challenge_indices = HashMap::<String, usize>::new();
+        let mut teams = Vec::<TeamReport>::new();
+        let mut team_indices = HashMap::<TeamKey, usize>::new();
+
+        for solve in solves {
+            let player_name = solve.player_name.clone().unwrap_or_else(|| solve.player_id.to_string());
+
+            let challenge_index = *challenge_indices.entry(solve.challenge_name.clone()).or_insert_with(|| {
+                challenges.push(ChallengeReport {
+                    challenge_name: solve.challenge_name.clone(),
+                    category: None,
+                    solve_count: 0,
+                    first_blood: None
+                });
+                challenges.len() - 1
+            });
+            let challenge = &mut challenges[challenge_index];
+            challenge.solve_count += 1;
+            if challenge.category.is_none() {
+                challenge.category = solve.challenge_category.clone();
+            }
+            // Solves recorded before first bloods were tracked, or on hidden challenges, have none
+            if solve.is_first_blood {
+                let released_at = match (solve.released_at, start_time) {
+                    _ if solve.is_seeded_release == Some(true) => None,
+                    (Some(released_at), Some(start_time)) => Some(released_at.max(start_time)),
+                    (released_at, start_time) => released_at.or(start_time)
+                };
+                challenge.first_blood = Some(FirstBloodReport {
+                    player_name: player_name.clone(),
+                    team_name: solve.team_name.clone(),
+                    solved_at: solve.solved_at,
+                    seconds_from_release: solve.solved_at.zip(released_at).map(|(solved_at, released_at)| (solved_at - released_at).num_seconds())
+                });
+            }
+
+            let team_key = match solve.team_id {
+                Some(team_id) => TeamKey::Team(team_id),
+                None => TeamKey::Player(solve.player_id)
+            };
+            let team_index = *team_indices.entry(team_key).or_insert_with(|| {
+                teams.push(TeamReport {
+                    team_name: solve.team_name.clone().unwrap_or_else(|| player_name.clone()),
+                    solve_count: 0,
+                    last_solve_at: None,
+                    solves: Vec::new()
+                });
+                teams.len() - 1
+            });
+            let team = &mut teams[team_index];
+            team.solve_count += 1;
+            team.last_solve_at = team.last_solve_at.max(solve.solved_at);
+            team.solves.push(TeamSolveReport {
+                challenge_name: solve.challenge_name,
+                player_name,
+                solved_at: solve.solved_at
+            });
+        }
+
+        let mut categories = Vec::<CategoryReport>::new();
+        for challenge in &challenges {
+            let category_name = challenge.category.clone().unwrap_or_else(|| "Uncategorized".to_string());
+            match categories.iter_mut().find(|category| category.category == category_name) {
+                Some(category) => {
+                    category.challenge_count += 1;
+                    category.solve_count += challenge.solve_count;
+                },
+                None => categories.push(CategoryReport {
+                    category: category_name,
+                    challenge_count: 1,
+                    solve_count: challenge.solve_count
+                })
+            }
+        }
+
+        challenges.sort_by(|a, b| b.solve_count.cmp(&a.solve_count).then_with(|| a.challenge_name.cmp(&b.challenge_name)));
+        categories.sort_by(|a, b| b.solve_count.cmp(&a.solve_count).then_with(|| a.category.cmp(&b.category)));
+        // Most solves first, ties go to whoever got there first
+        teams.sort_by(|a, b| b.solve_count.cmp(&a.solve_count).then_with(|| a.last_solve_at.cmp(&b.last_solve_at)));
+
+        Self {
+            generated_at: Utc::now(),
+            challenges,
+            categories,
+            teams
+        }
+    }
+    pub(crate) fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.render_markdown(),
+            ReportFormat::Json => serde_json::to_string_pretty(self).expect("report is always serializable"),
+            ReportFormat::Csv => self.render_csv()
+        }
+    }
+    fn render_markdown(&self) -> String {
+        // Writing to a String can't fail
+        let mut output = String::new();
+        let _ = writeln!(output, "# CTF statistics");
+        let _ = writeln!(output, "Generated at {}", self.generated_at.to_rfc3339());
+
+        let _ = writeln!(output, "\n## Challenges");
+        let _ = writeln!(output, "| Challenge | Category | Solves | First blood | Time from release |");
+        let _ = writeln!(output, "| --- | --- | --- | --- | --- |");
+        for challenge in &self.challenges {
+            let (first_blood, time_from_release) = match &challenge.first_blood {
+                Some(first_blood) => {
+                    let holder = match &first_blood.team_name {
+                        Some(team_name) => format!("{} ({team_name})", first_blood.player_name),
+                        None => first_blood.player_name.clone()
+                    };
+                    (holder, first_blood.seconds_from_release.map(format_duration).unwrap_or_default())
+                },
+                None => ("none".to_string(), String::new())
+            };
+            let _ = writeln!(
+                output,
+                "| {} | {} | {} | {} | {} |",
+                escape_markdown(&challenge.challenge_name),
+                escape_markdown(challenge.category.as_deref().unwrap_or("")),
+                challenge.solve_count,
+                escape_markdown(&first_blood),
+                time_from_release
+            );
+        }
+
+        let _ = writeln!(output, "\n## Categories");
+        let _ = writeln!(output, "| Category | Challenges | Solves |");
+        let _ = writeln!(output, "| --- | --- | --- |");
+        for category in &self.categories {
+            let _ = writeln!(output, "| {} | {} | {} |", escape_markdown(&category.category), category.challenge_count, category.solve_count);
+        }
+
+        let _ = writeln!(output, "\n## Teams");
+        for (index, team) in self.teams.iter().enumerate() {
+            let _ = writeln!(output, "\n### {}. {} ({} solves)", index + 1, escape_markdown(&team.team_name), team.solve_count);
+            for solve in &team.solves {
+                let solved_at = solve.solved_at.map(|solved_at| solved_at.to_rfc3339()).unwrap_or_else(|| "unknown time".to_string());
+                let _ = writeln!(output, "- {solved_at}: **{}** by {}", escape_markdown(&solve.challenge_name), escape_markdown(&solve.player_name));
+            }
+        }
+        output
+    }
+    // One table per section, separated by an empty line
+    fn render_csv(&self) -> String {
+        let mut output = String::new();
+        let _ = writeln!(output, "challenge,category,solves,first_blood_player,first_blood_team,first_blood_at,seconds_from_release");
+        for challenge in &self.challenges {
+            let first_blood = challenge.first_blood.as_ref();
+            let _ = writeln!(
+                output,
+                "{},{},{},{},{},{},{}",
+                escape_csv(&challenge.challenge_name),
+                escape_csv(challenge.category.as_deref().unwrap_or("")),
+                challenge.solve_count,
+                escape_csv(first_blood.map(|first_blood| first_blood.player_name.as_str()).unwrap_or("")),
+                escape_csv(first_blood.and_then(|first_blood| first_blood.team_name.as_deref()).unwrap_or("")),
+                first_blood.and_then(|first_blood| first_blood.solved_at).map(|solved_at| solved_at.to_rfc3339()).unwrap_or_default(),
+                first_blood.and_then(|first_blood| first_blood.seconds_from_release).map(|seconds| seconds.to_string()).unwrap_or_default()
+            );
+        }
+
+        let _ = writeln!(output, "\ncategory,challenges,solves");
+        for category in &self.categories {
+            let _ = writeln!(output, "{},{},{}", escape_csv(&category.category), category.challenge_count, category.solve_count);
+        }
+
+        let _ = writeln!(output, "\nteam,challenge,player,solved_at");
+        for team in &self.teams {
+            for solve in &team.solves {
+                let _ = writeln!(
+                    output,
+                    "{},{},{},{}",
+                    escape_csv(&team.team_name),
+                    escape_csv(&solve.challenge_name),
+                    escape_csv(&solve.player_name),
+                    solve.solved_at.map(|solved_at| solved_at.to_rfc3339()).unwrap_or_default()
+                );
+            }
+        }
+        output
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum TeamKey {
+    Team(Uuid),
+    // Players without a known team get a timeline of their own
+    Player(Uuid)
+}
+
+fn format_duration(seconds: i64) -> String {
+    let sign = if seconds < 0 { "-" } else { "" };
+    let seconds = seconds.abs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{sign}{seconds}s"),
+        (0, minutes, seconds) => format!("{sign}{minutes}m {seconds}s"),
+        (hours, minutes, seconds) => format!("{sign}{hours}h {minutes}m {seconds}s")
+    }
+}
+
+fn escape_markdown(raw: &str) -> String {
+    raw.replace('|', "\\|")
+}
+
+pub(crate) fn escape_csv(raw: &str) -> String {
+    if raw.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", raw.replace('"', "\"\""))
+    } else {
+        raw.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use uuid::Uuid;
+    use super::{Report, ReportFormat, ReportSolve};
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_792_324_800, 0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    fn solve(challenge_name: &str, team: &(Uuid, &str), minutes: i64, is_first_blood: bool) -> ReportSolve {
+        ReportSolve {
+            challenge_name: challenge_name.to_string(),
+            player_id: Uuid::new_v4(),
+            player_name: Some(format!("{} player", team.1)),
+            team_id: Some(team.0),
+            team_name: Some(team.1.to_string()),
+            challenge_category: Some("rev".to_string()),
+            solved_at: Some(at(minutes)),
+            is_first_blood,
+            released_at: None,
+            is_seeded_release: None
+        }
+    }
+
+    fn released(mut solve: ReportSolve, minutes: i64, is_seed: bool) -> ReportSolve {
+        solve.released_at = Some(at(minutes));
+        solve.is_seeded_release = Some(is_seed);
+        solve
+    }
+
+    fn json(report: &Report) -> serde_json::Value {
+        serde_json::from_str(&report.render(ReportFormat::Json)).unwrap()
+    }
+
+    #[test]
+    fn ranks_teams_by_solves_then_earliest_last_solve() {
+        let (alpha, bravo, charlie) = ((Uuid::new_v4(), "alpha"), (Uuid::new_v4(), "bravo"), (Uuid::new_v4(), "charlie"));
+        let report = Report::build(vec![
+            solve("warmup", &bravo, 1, true),
+            solve("warmup", &alpha, 2, false),
+            solve("warmup", &charlie, 3, false),
+            solve("pwn", &alpha, 10, true),
+            solve("pwn", &bravo, 8, false),
+            solve("crypto", &charlie, 20, true)
+        ], None);
+        let report = json(&report);
+        let team_names = report["teams"].as_array().unwrap().iter().map(|team| team["team_name"].as_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(team_names, ["bravo", "alpha", "charlie"]);
+        assert_eq!(report["teams"][0]["solve_count"], 2);
+        let challenge_names = report["challenges"].as_array().unwrap().iter().map(|challenge| challenge["challenge_name"].as_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(challenge_names, ["warmup", "pwn", "crypto"]);
+        assert_eq!(report["categories"][0]["challenge_count"], 3);
+        assert_eq!(report["categories"][0]["solve_count"], 6);
+    }
+
+    #[test]
+    fn first_blood_only_from_flagged_solves() {
+        let alpha = (Uuid::new_v4(), "alpha");
+        let bravo = (Uuid::new_v4(), "bravo");
+        let report = Report::build(vec![
+            solve("hidden", &alpha, 1, false),
+            solve("pwn", &alpha, 2, false),
+            solve("pwn", &bravo, 3, true)
+        ], None);
+        let markdown = report.render(ReportFormat::Markdown);
+        let report = json(&report);
+        let first_bloods = report["challenges"].as_array().unwrap().iter().filter(|challenge| !challenge["first_blood"].is_null()).count();
+        assert_eq!(first_bloods, 1);
+        assert_eq!(report["challenges"][0]["first_blood"]["team_name"], "bravo");
+        assert!(report["challenges"][1]["first_blood"].is_null());
+        assert!(markdown.contains("| hidden | rev | 1 | none |  |"));
+        assert!(markdown.contains("| pwn | rev | 2 | bravo player (bravo) |  |"));
+    }
+
+    #[test]
+    fn time_from_release_uses_start_time_for_early_releases() {
+        let alpha = (Uuid::new_v4(), "alpha");
+        let report = Report::build(vec![
+            released(solve("early", &alpha, 15, true), -60, false),
+            released(solve("late", &alpha, 90, true), 30, false),
+            solve("unreleased", &alpha, 5, true)
+        ], Some(at(0)));
+        let csv = report.render(ReportFormat::Csv);
+        let report = json(&report);
+        let seconds_from_release = |name: &str| report["challenges"].as_array().unwrap().iter()
+            .find(|challenge| challenge["challenge_name"] == name).unwrap()["first_blood"]["seconds_from_release"].clone();
+        assert_eq!(seconds_from_release("early"), 15 * 60);
+        assert_eq!(seconds_from_release("late"), 60 * 60);
+        assert_eq!(seconds_from_release("unreleased"), 5 * 60);
+        assert!(csv.contains(",3600\n"));
+    }
+
+    #[test]
+    fn time_from_release_can_be_negative_or_absent() {
+        let alpha = (Uuid::new_v4(), "alpha");
+        let mut untimed = solve("untimed", &alpha, 0, true);
+        untimed.solved_at = None;
+        let report = Report::build(vec![
+            // Solved before dal noticed the release, e.g. a challenge made visible by hand
+            released(solve("negative", &alpha, 5, true), 10, false),
+            released(solve("seeded", &alpha, 60, true), 0, true),
+            solve("no release", &alpha, 1, true),
+            untimed
+        ], None);
+        let markdown = report.render(ReportFormat::Markdown);
+        let report = json(&report);
+        let seconds_from_release = |name: &str| report["challenges"].as_array().unwrap().iter()
+            .find(|challenge| challenge["challenge_name"] == name).unwrap()["first_blood"]["seconds_from_release"].clone();
+        assert_eq!(seconds_from_release("negative"), -5 * 60);
+        assert!(seconds_from_release("seeded").is_null());
+        assert!(seconds_from_release("no release").is_null());
+        assert!(seconds_from_release("untimed").is_null());
+        assert!(markdown.contains("| -5m 0s |"));
+    }
+}
@@ -0,0 +1,261 @@
+mod postgres;
+mod sqlite;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use sqlx::migrate::{AppliedMigration, MigrateError, Migrator};
+use twilight_model::id::Id as Snowflake;
+use twilight_model::id::marker::WebhookMarker;
+use uuid::Uuid;
+
+use crate::config::{WebhookRole, DEFAULT_EVENT_NAME};
+use crate::models::announcement::{Announcement, NewAnnouncement};
+use crate::models::scoreboard::RankedScoreboardEntry;
+use crate::models::sent_solve::SentSolve;
+use crate::report::ReportSolve;
+use crate::repository::postgres::PostgresDatabase;
+use crate::repository::sqlite::SqliteDatabase;
+
+// A storage backend. Everything event specific takes the event explicitly, Repository fills it in
+pub(crate) trait Database: Send + Sync {
+    // Migrations are embedded at compile time, one set per backend
+    fn migrator(&self) -> &'static Migrator;
+    fn applied_migrations(&self) -> BoxFuture<'_, Result<Vec<AppliedMigration>, MigrateError>>;
+    fn run_migrations(&self) -> BoxFuture<'_, Result<(), MigrateError>>;
+    // Reverts every applied migration newer than target
+    fn undo_migrations(&self, target: i64) -> BoxFuture<'_, Result<(), MigrateError>>;
+    fn has_sent_solve<'a>(&'a self, event: &'a str, challenge_name: &'a str, player_id: &'a Uuid) -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+    fn has_been_solved<'a>(&'a self, event: &'a str, challenge_name: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+    fn mark_challenge_as_solved<'a>(&'a self, event: &'a str, record: &'a SolveRecord, is_first_blood: bool) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+    fn mark_solve_as_ignored<'a>(&'a self, event: &'a str, record: &'a SolveRecord, ignore_reason: &'a str) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+    // Takes the config announcements of each event, keyed by their config name, and applies them
+    // all in one transaction
+    fn replace_config_announcements<'a>(&'a self, config_announcements: &'a [(&'a str, &'a [(String, NewAnnouncement)])]) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+    fn create_announcement<'a>(&'a self, event: &'a str, announcement: &'a NewAnnouncement) -> BoxFuture<'a, Result<i32, sqlx::Error>>;
+    fn delete_announcement<'a>(&'a self, event: &'a str, announcement_id: i32) -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+    fn list_announcements<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<Announcement>, sqlx::Error>>;
+    fn next_pending_announcement<'a>(&'a self, event: &'a str, not_before: DateTime<Utc>) -> BoxFuture<'a, Result<Option<Announcement>, sqlx::Error>>;
+    fn mark_announcement_as_posted(&self, announcement_id: i32) -> BoxFuture<'_, Result<(), sqlx::Error>>;
+    fn has_released_challenges<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+    fn has_released_challenge<'a>(&'a self, event: &'a str, challenge_name: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+    fn mark_challenge_as_released<'a>(&'a self, event: &'a str, challenge_name: &'a str, is_seed: bool) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+    fn latest_scoreboard_snapshot<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Option<ScoreboardSnapshot>, sqlx::Error>>;
+    fn get_scoreboard_snapshot_entries(&self, snapshot_id: i32) -> BoxFuture<'_, Result<Vec<RankedScoreboardEntry>, sqlx::Error>>;
+    fn create_scoreboard_snapshot<'a>(&'a self, event: &'a str, entries: &'a [RankedScoreboardEntry]) -> BoxFuture<'a, Result<i32, sqlx::Error>>;
+    fn get_report_solves<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<ReportSolve>, sqlx::Error>>;
+    fn get_sent_solves<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<SentSolve>, sqlx::Error>>;
+    fn ping(&self) -> BoxFuture<'_, Result<(), sqlx::Error>>;
+}
+
+// Every query is scoped to one event, use for_event to get at the others
+pub(crate) struct Repository {
+    database: Arc<dyn Database>,
+    event: String
+}
+impl Repository {
+    // The backend is picked from the url scheme: postgres:// or sqlite:, sqlite::memory: keeps
+    // everything in memory and is gone once dal exits
+    pub(crate) async fn new(database_url: &str) -> Result<Self, sqlx::Error> {
+        let database: Arc<dyn Database> = if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            Arc::new(PostgresDatabase::connect(database_url).await?)
+        } else if database_url.starts_with("sqlite:") {
+            Arc::new(SqliteDatabase::connect(database_url).await?)
+        } else {
+            return Err(sqlx::Error::Configuration("database url should start with postgres:// or sqlite:".into()));
+        };
+
+        Ok(Self {
+            database,
+            event: DEFAULT_EVENT_NAME.to_string()
+        })
+    }
+    // Shares the connection pool
+    pub(crate) fn for_event(&self, event: &str) -> Self {
+        Self {
+            database: self.database.clone(),
+            event: event.to_string()
+        }
+    }
+    // Every migration this build knows about, followed by any the database has that it doesn't
+    pub(crate) async fn migration_status(&self) -> Result<Vec<MigrationStatus>, MigrateError> {
+        let applied_migrations = self.database.applied_migrations().await?;
+        let migrator = self.database.migrator();
+        let mut statuses = migrator.iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| {
+                let applied_migration = applied_migrations.iter().find(|applied_migration| applied_migration.version == migration.version);
+                MigrationStatus {
+                    version: migration.version,
+                    description: Some(migration.description.to_string()),
+                    state: match applied_migration {
+                        None => MigrationState::Pending,
+                        Some(applied_migration) if applied_migration.checksum != migration.checksum => MigrationState::Modified,
+                        Some(_) => MigrationState::Applied
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        statuses.extend(applied_migrations.iter()
+            .filter(|applied_migration| !migrator.version_exists(applied_migration.version))
+            .map(|applied_migration| MigrationStatus {
+                version: applied_migration.version,
+                description: None,
+                state: MigrationState::Unknown
+            }));
+        Ok(statuses)
+    }
+    // A database migrated by a newer dal may not work the way this one expects, so nothing is
+    // touched until dal is upgraded
+    async fn check_schema_version(&self) -> Result<(), MigrationError> {
+        let newest_unknown_version = self.migration_status().await?
+            .into_iter()
+            .filter(|status| status.state == MigrationState::Unknown)
+            .map(|status| status.version)
+            .max();
+        match newest_unknown_version {
+            Some(version) => Err(MigrationError::SchemaTooNew(version)),
+            None => Ok(())
+        }
+    }
+    pub(crate) async fn run_migrations(&self) -> Result<(), MigrationError> {
+        self.check_schema_version().await?;
+        Ok(self.database.run_migrations().await?)
+    }
+    pub(crate) async fn undo_migrations(&self, target: i64) -> Result<(), MigrationError> {
+        self.check_schema_version().await?;
+        Ok(self.database.undo_migrations(target).await?)
+    }
+    pub(crate) async fn has_sent_solve(&self, challenge_name: &str, player_id: &Uuid) -> Result<bool, sqlx::Error> {
+        self.database.has_sent_solve(&self.event, challenge_name, player_id).await
+    }
+    pub(crate) async fn has_been_solved(&self, challenge_name: &str) -> Result<bool, sqlx::Error> {
+        self.database.has_been_solved(&self.event, challenge_name).await
+    }
+    pub(crate) async fn mark_challenge_as_solved(&self, record: &SolveRecord, is_first_blood: bool) -> Result<(), sqlx::Error> {
+        self.database.mark_challenge_as_solved(&self.event, record, is_first_blood).await
+    }
+    pub(crate) async fn mark_solve_as_ignored(&self, record: &SolveRecord, ignore_reason: &str) -> Result<(), sqlx::Error> {
+        self.database.mark_solve_as_ignored(&self.event, record, ignore_reason).await
+    }
+    // Unposted announcements are updated to match the config and dropped once removed from it,
+    // posted ones are history and stay as they were
+    pub(crate) async fn replace_config_announcements(&self, config_announcements: &[(String, NewAnnouncement)]) -> Result<(), sqlx::Error> {
+        self.database.replace_config_announcements(&[(&self.event, config_announcements)]).await
+    }
+    // The same for several events at once, either all of them are replaced or none are
+    pub(crate) async fn replace_config_announcements_of_events(&self, config_announcements: &[(&str, &[(String, NewAnnouncement)])]) -> Result<(), sqlx::Error> {
+        self.database.replace_config_announcements(config_announcements).await
+    }
+    pub(crate) async fn create_announcement(&self, announcement: &NewAnnouncement) -> Result<i32, sqlx::Error> {
+        self.database.create_announcement(&self.event, announcement).await
+    }
+    pub(crate) async fn delete_announcement(&self, announcement_id: i32) -> Result<bool, sqlx::Error> {
+        self.database.delete_announcement(&self.event, announcement_id).await
+    }
+    pub(crate) async fn list_announcements(&self) -> Result<Vec<Announcement>, sqlx::Error> {
+        self.database.list_announcements(&self.event).await
+    }
+    // Announcements due before not_before are never posted, they were missed while we were down
+    pub(crate) async fn next_pending_announcement(&self, not_before: DateTime<Utc>) -> Result<Option<Announcement>, sqlx::Error> {
+        self.database.next_pending_announcement(&self.event, not_before).await
+    }
+    pub(crate) async fn mark_announcement_as_posted(&self, announcement_id: i32) -> Result<(), sqlx::Error> {
+        self.database.mark_announcement_as_posted(announcement_id).await
+    }
+    pub(crate) async fn has_released_challenges(&self) -> Result<bool, sqlx::Error> {
+        self.database.has_released_challenges(&self.event).await
+    }
+    pub(crate) async fn has_released_challenge(&self, challenge_name: &str) -> Result<bool, sqlx::Error> {
+        self.database.has_released_challenge(&self.event, challenge_name).await
+    }
+    // Seeded challenges were already visible when first seen, so their release time isn't known
+    pub(crate) async fn mark_challenge_as_released(&self, challenge_name: &str, is_seed: bool) -> Result<(), sqlx::Error> {
+        self.database.mark_challenge_as_released(&self.event, challenge_name, is_seed).await
+    }
+    pub(crate) async fn latest_scoreboard_snapshot(&self) -> Result<Option<ScoreboardSnapshot>, sqlx::Error> {
+        self.database.latest_scoreboard_snapshot(&self.event).await
+    }
+    pub(crate) async fn get_scoreboard_snapshot_entries(&self, snapshot_id: i32) -> Result<Vec<RankedScoreboardEntry>, sqlx::Error> {
+        self.database.get_scoreboard_snapshot_entries(snapshot_id).await
+    }
+    pub(crate) async fn create_scoreboard_snapshot(&self, entries: &[RankedScoreboardEntry]) -> Result<i32, sqlx::Error> {
+        self.database.create_scoreboard_snapshot(&self.event, entries).await
+    }
+    pub(crate) async fn get_report_solves(&self) -> Result<Vec<ReportSolve>, sqlx::Error> {
+        self.database.get_report_solves(&self.event).await
+    }
+    pub(crate) async fn get_sent_solves(&self) -> Result<Vec<SentSolve>, sqlx::Error> {
+        self.database.get_sent_solves(&self.event).await
+    }
+    pub(crate) async fn ping(&self) -> Result<(), sqlx::Error> {
+        self.database.ping().await
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum MigrationError {
+    #[error("the database has migration {0} which this build doesn't know about, it was migrated by a newer version of dal")]
+    SchemaTooNew(i64),
+    #[error("failed to migrate")]
+    Migrate(#[from] MigrateError)
+}
+
+pub(crate) struct MigrationStatus {
+    pub(crate) version: i64,
+    // Unknown for migrations only the database knows about
+    pub(crate) description: Option<String>,
+    pub(crate) state: MigrationState
+}
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub(crate) enum MigrationState {
+    Applied,
+    Pending,
+    // Applied, but the migration has been edited since
+    Modified,
+    // Applied by a newer version of dal
+    Unknown
+}
+
+pub(crate) struct SolveRecord {
+    pub(crate) challenge_name: String,
+    pub(crate) player_id: Uuid,
+    pub(crate) solved_at: DateTime<Utc>,
+    pub(crate) player_name: Option<String>,
+    pub(crate) team_id: Option<Uuid>,
+    pub(crate) team_name: Option<String>,
+    pub(crate) challenge_category: Option<String>
+}
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct ScoreboardSnapshot {
+    pub(crate) id: i32,
+    pub(crate) taken_at: DateTime<Utc>
+}
+
+#[derive(sqlx::FromRow)]
+struct AnnouncementRow {
+    announcement_id: i32,
+    config_key: Option<String>,
+    announce_at: DateTime<Utc>,
+    role: String,
+    webhook_id: Option<i64>,
+    content: String,
+    posted_at: Option<DateTime<Utc>>
+}
+impl TryFrom<AnnouncementRow> for Announcement {
+    type Error = sqlx::Error;
+
+    fn try_from(row: AnnouncementRow) -> Result<Self, Self::Error> {
+        Ok(Announcement {
+            id: row.announcement_id,
+            config_key: row.config_key,
+            announce_at: row.announce_at,
+            role: row.role.parse::<WebhookRole>().map_err(|error| sqlx::Error::Decode(Box::new(error)))?,
+            webhook_id: row.webhook_id.and_then(|webhook_id| Snowflake::<WebhookMarker>::new_checked(webhook_id as u64)),
+            content: row.content,
+            posted_at: row.posted_at
+        })
+    }
+}
@@ -0,0 +1,482 @@
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use sqlx::migrate::{AppliedMigration, Migrate, MigrateError, Migrator};
+use uuid::Uuid;
+
+use crate::models::announcement::{Announcement, NewAnnouncement};
+use crate::models::scoreboard::RankedScoreboardEntry;
+use crate::models::sent_solve::SentSolve;
+use crate::report::ReportSolve;
+use crate::repository::{AnnouncementRow, Database, ScoreboardSnapshot, SolveRecord};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+pub(crate) struct PostgresDatabase {
+    pool: sqlx::PgPool
+}
+impl PostgresDatabase {
+    pub(crate) async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
+        let pool = sqlx::PgPool::connect(database_url).await?;
+
+        Ok(Self {
+            pool
+        })
+    }
+}
+impl Database for PostgresDatabase {
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+    fn applied_migrations(&self) -> BoxFuture<'_, Result<Vec<AppliedMigration>, MigrateError>> {
+        async move {
+            let mut connection = self.pool.acquire().await?;
+            connection.ensure_migrations_table().await?;
+            connection.list_applied_migrations().await
+        }.boxed()
+    }
+    fn run_migrations(&self) -> BoxFuture<'_, Result<(), MigrateError>> {
+        MIGRATOR.run(&self.pool).boxed()
+    }
+    fn undo_migrations(&self, target: i64) -> BoxFuture<'_, Result<(), MigrateError>> {
+        MIGRATOR.undo(&self.pool, target).boxed()
+    }
+    fn has_sent_solve<'a>(&'a self, event: &'a str, challenge_name: &'a str, player_id: &'a Uuid) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                    "
+                        select
+                            solve_id
+                        from sent_solves
+                        where
+                            event = $1 and
+                            challenge_name = $2 and
+                            player_id = $3
+                        limit 1
+                    ",
+                    event,
+                    challenge_name,
+                    player_id
+                )
+                .fetch_optional(&self.pool)
+                .await
+                .map(|maybe_solve_id| maybe_solve_id.is_some())
+        }.boxed()
+    }
+    fn has_been_solved<'a>(&'a self, event: &'a str, challenge_name: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    select
+                        solve_id
+                    from sent_solves
+                    where
+                        event = $1 and
+                        challenge_name = $2 and
+                        ignore_reason is null
+                    limit 1
+                ",
+                event,
+                challenge_name
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_solve_id| maybe_solve_id.is_some())
+        }.boxed()
+    }
+    fn mark_challenge_as_solved<'a>(&'a self, event: &'a str, record: &'a SolveRecord, is_first_blood: bool) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    insert into sent_solves
+                    (event, challenge_name, player_id, solved_at, player_name, team_id, team_name, challenge_category, is_first_blood)
+                    values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ",
+                event,
+                record.challenge_name,
+                record.player_id,
+                record.solved_at,
+                record.player_name,
+                record.team_id,
+                record.team_name,
+                record.challenge_category,
+                is_first_blood
+            )
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn mark_solve_as_ignored<'a>(&'a self, event: &'a str, record: &'a SolveRecord, ignore_reason: &'a str) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    insert into sent_solves
+                    (event, challenge_name, player_id, solved_at, player_name, team_id, team_name, challenge_category, ignore_reason)
+                    values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ",
+                event,
+                record.challenge_name,
+                record.player_id,
+                record.solved_at,
+                record.player_name,
+                record.team_id,
+                record.team_name,
+                record.challenge_category,
+                ignore_reason
+            )
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn replace_config_announcements<'a>(&'a self, config_announcements: &'a [(&'a str, &'a [(String, NewAnnouncement)])]) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            let mut transaction = self.pool.begin().await?;
+            for (event, announcements) in config_announcements {
+                for (config_key, announcement) in *announcements {
+                    sqlx::query!(
+                        "
+                            insert into announcements
+                            (event, config_key, announce_at, role, webhook_id, content)
+                            values ($1, $2, $3, $4, $5, $6)
+                            on conflict (event, config_key) do update set
+                                announce_at = excluded.announce_at,
+                                role = excluded.role,
+                                webhook_id = excluded.webhook_id,
+                                content = excluded.content
+                            where announcements.posted_at is null
+                        ",
+                        event,
+                        config_key,
+                        announcement.announce_at,
+                        announcement.role.as_str(),
+                        announcement.webhook_id.map(|webhook_id| webhook_id.get() as i64),
+                        announcement.content
+                    )
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+                let config_keys = announcements.iter().map(|(config_key, _)| config_key.clone()).collect::<Vec<_>>();
+                sqlx::query!(
+                    "
+                        delete from announcements
+                        where
+                            event = $1 and
+                            config_key is not null and
+                            posted_at is null and
+                            not (config_key = any($2))
+                    ",
+                    event,
+                    &config_keys
+                )
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await
+        }.boxed()
+    }
+    fn create_announcement<'a>(&'a self, event: &'a str, announcement: &'a NewAnnouncement) -> BoxFuture<'a, Result<i32, sqlx::Error>> {
+        async move {
+            sqlx::query_scalar!(
+                "
+                    insert into announcements
+                    (event, announce_at, role, webhook_id, content)
+                    values ($1, $2, $3, $4, $5)
+                    returning announcement_id
+                ",
+                event,
+                announcement.announce_at,
+                announcement.role.as_str(),
+                announcement.webhook_id.map(|webhook_id| webhook_id.get() as i64),
+                announcement.content
+            )
+            .fetch_one(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn delete_announcement<'a>(&'a self, event: &'a str, announcement_id: i32) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    delete from announcements
+                    where
+                        event = $1 and
+                        announcement_id = $2
+                ",
+                event,
+                announcement_id
+            )
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+        }.boxed()
+    }
+    fn list_announcements<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<Announcement>, sqlx::Error>> {
+        async move {
+            sqlx::query_as!(
+                AnnouncementRow,
+                "
+                    select
+                        announcement_id,
+                        config_key,
+                        announce_at,
+                        role,
+                        webhook_id,
+                        content,
+                        posted_at
+                    from announcements
+                    where event = $1
+                    order by announce_at
+                ",
+                event
+            )
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Announcement::try_from)
+            .collect()
+        }.boxed()
+    }
+    // Announcements due before not_before are never posted, they were missed while we were down
+    fn next_pending_announcement<'a>(&'a self, event: &'a str, not_before: DateTime<Utc>) -> BoxFuture<'a, Result<Option<Announcement>, sqlx::Error>> {
+        async move {
+            sqlx::query_as!(
+                AnnouncementRow,
+                "
+                    select
+                        announcement_id,
+                        config_key,
+                        announce_at,
+                        role,
+                        webhook_id,
+                        content,
+                        posted_at
+                    from announcements
+                    where
+                        event = $1 and
+                        posted_at is null and
+                        announce_at >= $2
+                    order by announce_at
+                    limit 1
+                ",
+                event,
+                not_before
+            )
+            .fetch_optional(&self.pool)
+            .await?
+            .map(Announcement::try_from)
+            .transpose()
+        }.boxed()
+    }
+    fn mark_announcement_as_posted(&self, announcement_id: i32) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    update announcements
+                    set posted_at = now()
+                    where announcement_id = $1
+                ",
+                announcement_id
+            )
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn has_released_challenges<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    select
+                        challenge_name
+                    from released_challenges
+                    where event = $1
+                    limit 1
+                ",
+                event
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_challenge_name| maybe_challenge_name.is_some())
+        }.boxed()
+    }
+    fn has_released_challenge<'a>(&'a self, event: &'a str, challenge_name: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    select
+                        challenge_name
+                    from released_challenges
+                    where
+                        event = $1 and
+                        challenge_name = $2
+                ",
+                event,
+                challenge_name
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_challenge_name| maybe_challenge_name.is_some())
+        }.boxed()
+    }
+    fn mark_challenge_as_released<'a>(&'a self, event: &'a str, challenge_name: &'a str, is_seed: bool) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query!(
+                "
+                    insert into released_challenges
+                    (event, challenge_name, is_seed)
+                    values ($1, $2, $3)
+                    on conflict do nothing
+                ",
+                event,
+                challenge_name,
+                is_seed
+            )
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn latest_scoreboard_snapshot<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Option<ScoreboardSnapshot>, sqlx::Error>> {
+        async move {
+            sqlx::query_as!(
+                ScoreboardSnapshot,
+                "
+                    select
+                        snapshot_id as id,
+                        taken_at
+                    from scoreboard_snapshots
+                    where event = $1
+                    order by taken_at desc
+                    limit 1
+                ",
+                event
+            )
+            .fetch_optional(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn get_scoreboard_snapshot_entries(&self, snapshot_id: i32) -> BoxFuture<'_, Result<Vec<RankedScoreboardEntry>, sqlx::Error>> {
+        async move {
+            sqlx::query_as!(
+                RankedScoreboardEntry,
+                "
+                    select
+                        team_id,
+                        team_name,
+                        rank,
+                        score
+                    from scoreboard_snapshot_entries
+                    where snapshot_id = $1
+                    order by rank
+                ",
+                snapshot_id
+            )
+            .fetch_all(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn create_scoreboard_snapshot<'a>(&'a self, event: &'a str, entries: &'a [RankedScoreboardEntry]) -> BoxFuture<'a, Result<i32, sqlx::Error>> {
+        async move {
+            let mut transaction = self.pool.begin().await?;
+            let snapshot_id = sqlx::query_scalar!(
+                "
+                    insert into scoreboard_snapshots
+                    (event)
+                    values ($1)
+                    returning snapshot_id
+                ",
+                event
+            )
+            .fetch_one(&mut *transaction)
+            .await?;
+            let team_ids = entries.iter().map(|entry| entry.team_id).collect::<Vec<_>>();
+            let team_names = entries.iter().map(|entry| entry.team_name.clone()).collect::<Vec<_>>();
+            let ranks = entries.iter().map(|entry| entry.rank).collect::<Vec<_>>();
+            let scores = entries.iter().map(|entry| entry.score).collect::<Vec<_>>();
+            sqlx::query!(
+                "
+                    insert into scoreboard_snapshot_entries
+                    (snapshot_id, team_id, team_name, rank, score)
+                    select $1, * from unnest($2::uuid[], $3::text[], $4::integer[], $5::bigint[])
+                ",
+                snapshot_id,
+                &team_ids,
+                &team_names,
+                &ranks,
+                &scores
+            )
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            Ok(snapshot_id)
+        }.boxed()
+    }
+    fn get_report_solves<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<ReportSolve>, sqlx::Error>> {
+        async move {
+            sqlx::query_as!(
+                ReportSolve,
+                r#"
+                    select
+                        sent_solves.challenge_name,
+                        sent_solves.player_id,
+                        sent_solves.player_name,
+                        sent_solves.team_id,
+                        sent_solves.team_name,
+                        sent_solves.challenge_category,
+                        sent_solves.solved_at,
+                        sent_solves.is_first_blood,
+                        released_challenges.released_at as "released_at?",
+                        released_challenges.is_seed as "is_seeded_release?"
+                    from sent_solves
+                    left join released_challenges on
+                        released_challenges.event = sent_solves.event and
+                        released_challenges.challenge_name = sent_solves.challenge_name
+                    where
+                        sent_solves.event = $1 and
+                        sent_solves.ignore_reason is null
+                    order by sent_solves.solved_at nulls last, sent_solves.solve_id
+                "#,
+                event
+            )
+            .fetch_all(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn get_sent_solves<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<SentSolve>, sqlx::Error>> {
+        async move {
+            sqlx::query_as!(
+                SentSolve,
+                "
+                    select
+                        solve_id,
+                        challenge_name,
+                        player_id,
+                        player_name,
+                        team_id,
+                        team_name,
+                        challenge_category,
+                        solved_at,
+                        is_first_blood,
+                        ignore_reason
+                    from sent_solves
+                    where event = $1
+                    order by solve_id
+                ",
+                event
+            )
+            .fetch_all(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn ping(&self) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query!("select 1 as one")
+                .fetch_one(&self.pool)
+                .await?;
+            Ok(())
+        }.boxed()
+    }
+}
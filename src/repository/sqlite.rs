@@ -0,0 +1,745 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use sqlx::migrate::{AppliedMigration, Migrate, MigrateError, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use uuid::Uuid;
+
+use crate::models::announcement::{Announcement, NewAnnouncement};
+use crate::models::scoreboard::RankedScoreboardEntry;
+use crate::models::sent_solve::SentSolve;
+use crate::report::ReportSolve;
+use crate::repository::{AnnouncementRow, Database, ScoreboardSnapshot, SolveRecord};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+// The query macros only check against one database, so these are checked at runtime instead.
+// The current time is bound rather than taken from SQLite, so every timestamp is stored the same way
+pub(crate) struct SqliteDatabase {
+    pool: sqlx::SqlitePool
+}
+impl SqliteDatabase {
+    pub(crate) async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
+        let is_in_memory = database_url.contains(":memory:") || database_url.contains("mode=memory");
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true);
+        if !is_in_memory {
+            let pool = SqlitePoolOptions::new()
+                .connect_with(options.journal_mode(SqliteJournalMode::Wal))
+                .await?;
+            return Ok(Self {
+                pool
+            });
+        }
+
+        // Every connection would get a database of its own, so there is only ever one and it is
+        // kept around for as long as we are running
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+        let database = Self {
+            pool
+        };
+        // Nothing else gets a chance to create the schema outside of serve, e.g. for replay
+        database.run_migrations().await.map_err(|error| sqlx::Error::Migrate(Box::new(error)))?;
+        Ok(database)
+    }
+}
+impl Database for SqliteDatabase {
+    fn migrator(&self) -> &'static Migrator {
+        &MIGRATOR
+    }
+    fn applied_migrations(&self) -> BoxFuture<'_, Result<Vec<AppliedMigration>, MigrateError>> {
+        async move {
+            let mut connection = self.pool.acquire().await?;
+            connection.ensure_migrations_table().await?;
+            connection.list_applied_migrations().await
+        }.boxed()
+    }
+    fn run_migrations(&self) -> BoxFuture<'_, Result<(), MigrateError>> {
+        MIGRATOR.run(&self.pool).boxed()
+    }
+    fn undo_migrations(&self, target: i64) -> BoxFuture<'_, Result<(), MigrateError>> {
+        MIGRATOR.undo(&self.pool, target).boxed()
+    }
+    fn has_sent_solve<'a>(&'a self, event: &'a str, challenge_name: &'a str, player_id: &'a Uuid) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    select
+                        solve_id
+                    from sent_solves
+                    where
+                        event = ?1 and
+                        challenge_name = ?2 and
+                        player_id = ?3
+                    limit 1
+                "
+            )
+            .bind(event)
+            .bind(challenge_name)
+            .bind(player_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_solve_id| maybe_solve_id.is_some())
+        }.boxed()
+    }
+    fn has_been_solved<'a>(&'a self, event: &'a str, challenge_name: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    select
+                        solve_id
+                    from sent_solves
+                    where
+                        event = ?1 and
+                        challenge_name = ?2 and
+                        ignore_reason is null
+                    limit 1
+                "
+            )
+            .bind(event)
+            .bind(challenge_name)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_solve_id| maybe_solve_id.is_some())
+        }.boxed()
+    }
+    fn mark_challenge_as_solved<'a>(&'a self, event: &'a str, record: &'a SolveRecord, is_first_blood: bool) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    insert into sent_solves
+                    (event, challenge_name, player_id, solved_at, player_name, team_id, team_name, challenge_category, is_first_blood)
+                    values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                "
+            )
+            .bind(event)
+            .bind(&record.challenge_name)
+            .bind(record.player_id)
+            .bind(record.solved_at)
+            .bind(&record.player_name)
+            .bind(record.team_id)
+            .bind(&record.team_name)
+            .bind(&record.challenge_category)
+            .bind(is_first_blood)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn mark_solve_as_ignored<'a>(&'a self, event: &'a str, record: &'a SolveRecord, ignore_reason: &'a str) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    insert into sent_solves
+                    (event, challenge_name, player_id, solved_at, player_name, team_id, team_name, challenge_category, ignore_reason)
+                    values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                "
+            )
+            .bind(event)
+            .bind(&record.challenge_name)
+            .bind(record.player_id)
+            .bind(record.solved_at)
+            .bind(&record.player_name)
+            .bind(record.team_id)
+            .bind(&record.team_name)
+            .bind(&record.challenge_category)
+            .bind(ignore_reason)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn replace_config_announcements<'a>(&'a self, config_announcements: &'a [(&'a str, &'a [(String, NewAnnouncement)])]) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            let mut transaction = self.pool.begin().await?;
+            for (event, announcements) in config_announcements {
+                for (config_key, announcement) in *announcements {
+                    sqlx::query(
+                        "
+                            insert into announcements
+                            (event, config_key, announce_at, role, webhook_id, content)
+                            values (?1, ?2, ?3, ?4, ?5, ?6)
+                            on conflict (event, config_key) do update set
+                                announce_at = excluded.announce_at,
+                                role = excluded.role,
+                                webhook_id = excluded.webhook_id,
+                                content = excluded.content
+                            where announcements.posted_at is null
+                        "
+                    )
+                    .bind(event)
+                    .bind(config_key)
+                    .bind(announcement.announce_at)
+                    .bind(announcement.role.as_str())
+                    .bind(announcement.webhook_id.map(|webhook_id| webhook_id.get() as i64))
+                    .bind(&announcement.content)
+                    .execute(&mut *transaction)
+                    .await?;
+                }
+                // SQLite can't bind arrays, so the keys go in as a json array instead
+                let config_keys = announcements.iter().map(|(config_key, _)| config_key).collect::<Vec<_>>();
+                let config_keys = serde_json::to_string(&config_keys).expect("a list of strings should always serialize");
+                sqlx::query(
+                    "
+                        delete from announcements
+                        where
+                            event = ?1 and
+                            config_key is not null and
+                            posted_at is null and
+                            config_key not in (select value from json_each(?2))
+                    "
+                )
+                .bind(event)
+                .bind(config_keys)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await
+        }.boxed()
+    }
+    fn create_announcement<'a>(&'a self, event: &'a str, announcement: &'a NewAnnouncement) -> BoxFuture<'a, Result<i32, sqlx::Error>> {
+        async move {
+            sqlx::query_scalar(
+                "
+                    insert into announcements
+                    (event, announce_at, role, webhook_id, content)
+                    values (?1, ?2, ?3, ?4, ?5)
+                    returning announcement_id
+                "
+            )
+            .bind(event)
+            .bind(announcement.announce_at)
+            .bind(announcement.role.as_str())
+            .bind(announcement.webhook_id.map(|webhook_id| webhook_id.get() as i64))
+            .bind(&announcement.content)
+            .fetch_one(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn delete_announcement<'a>(&'a self, event: &'a str, announcement_id: i32) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    delete from announcements
+                    where
+                        event = ?1 and
+                        announcement_id = ?2
+                "
+            )
+            .bind(event)
+            .bind(announcement_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+        }.boxed()
+    }
+    fn list_announcements<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<Announcement>, sqlx::Error>> {
+        async move {
+            sqlx::query_as::<_, AnnouncementRow>(
+                "
+                    select
+                        announcement_id,
+                        config_key,
+                        announce_at,
+                        role,
+                        webhook_id,
+                        content,
+                        posted_at
+                    from announcements
+                    where event = ?1
+                    order by announce_at
+                "
+            )
+            .bind(event)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Announcement::try_from)
+            .collect()
+        }.boxed()
+    }
+    fn next_pending_announcement<'a>(&'a self, event: &'a str, not_before: DateTime<Utc>) -> BoxFuture<'a, Result<Option<Announcement>, sqlx::Error>> {
+        async move {
+            sqlx::query_as::<_, AnnouncementRow>(
+                "
+                    select
+                        announcement_id,
+                        config_key,
+                        announce_at,
+                        role,
+                        webhook_id,
+                        content,
+                        posted_at
+                    from announcements
+                    where
+                        event = ?1 and
+                        posted_at is null and
+                        announce_at >= ?2
+                    order by announce_at
+                    limit 1
+                "
+            )
+            .bind(event)
+            .bind(not_before)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(Announcement::try_from)
+            .transpose()
+        }.boxed()
+    }
+    fn mark_announcement_as_posted(&self, announcement_id: i32) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    update announcements
+                    set posted_at = ?1
+                    where announcement_id = ?2
+                "
+            )
+            .bind(Utc::now())
+            .bind(announcement_id)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn has_released_challenges<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    select
+                        challenge_name
+                    from released_challenges
+                    where event = ?1
+                    limit 1
+                "
+            )
+            .bind(event)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_challenge_name| maybe_challenge_name.is_some())
+        }.boxed()
+    }
+    fn has_released_challenge<'a>(&'a self, event: &'a str, challenge_name: &'a str) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    select
+                        challenge_name
+                    from released_challenges
+                    where
+                        event = ?1 and
+                        challenge_name = ?2
+                "
+            )
+            .bind(event)
+            .bind(challenge_name)
+            .fetch_optional(&self.pool)
+            .await
+            .map(|maybe_challenge_name| maybe_challenge_name.is_some())
+        }.boxed()
+    }
+    fn mark_challenge_as_released<'a>(&'a self, event: &'a str, challenge_name: &'a str, is_seed: bool) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query(
+                "
+                    insert into released_challenges
+                    (event, challenge_name, released_at, is_seed)
+                    values (?1, ?2, ?3, ?4)
+                    on conflict do nothing
+                "
+            )
+            .bind(event)
+            .bind(challenge_name)
+            .bind(Utc::now())
+            .bind(is_seed)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }.boxed()
+    }
+    fn latest_scoreboard_snapshot<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Option<ScoreboardSnapshot>, sqlx::Error>> {
+        async move {
+            sqlx::query_as::<_, ScoreboardSnapshot>(
+                "
+                    select
+                        snapshot_id as id,
+                        taken_at
+                    from scoreboard_snapshots
+                    where event = ?1
+                    order by taken_at desc
+                    limit 1
+                "
+            )
+            .bind(event)
+            .fetch_optional(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn get_scoreboard_snapshot_entries(&self, snapshot_id: i32) -> BoxFuture<'_, Result<Vec<RankedScoreboardEntry>, sqlx::Error>> {
+        async move {
+            sqlx::query_as::<_, RankedScoreboardEntry>(
+                "
+                    select
+                        team_id,
+                        team_name,
+                        rank,
+                        score
+                    from scoreboard_snapshot_entries
+                    where snapshot_id = ?1
+                    order by rank
+                "
+            )
+            .bind(snapshot_id)
+            .fetch_all(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn create_scoreboard_snapshot<'a>(&'a self, event: &'a str, entries: &'a [RankedScoreboardEntry]) -> BoxFuture<'a, Result<i32, sqlx::Error>> {
+        async move {
+            let mut transaction = self.pool.begin().await?;
+            let snapshot_id = sqlx::query_scalar(
+                "
+                    insert into scoreboard_snapshots
+                    (event, taken_at)
+                    values (?1, ?2)
+                    returning snapshot_id
+                "
+            )
+            .bind(event)
+            .bind(Utc::now())
+            .fetch_one(&mut *transaction)
+            .await?;
+            // No unnest either, but inserts inside a transaction are cheap
+            for entry in entries {
+                sqlx::query(
+                    "
+                        insert into scoreboard_snapshot_entries
+                        (snapshot_id, team_id, team_name, rank, score)
+                        values (?1, ?2, ?3, ?4, ?5)
+                    "
+                )
+                .bind(snapshot_id)
+                .bind(entry.team_id)
+                .bind(&entry.team_name)
+                .bind(entry.rank)
+                .bind(entry.score)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await?;
+            Ok(snapshot_id)
+        }.boxed()
+    }
+    fn get_report_solves<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<ReportSolve>, sqlx::Error>> {
+        async move {
+            sqlx::query_as::<_, ReportSolve>(
+                "
+                    select
+                        sent_solves.challenge_name,
+                        sent_solves.player_id,
+                        sent_solves.player_name,
+                        sent_solves.team_id,
+                        sent_solves.team_name,
+                        sent_solves.challenge_category,
+                        sent_solves.solved_at,
+                        sent_solves.is_first_blood,
+                        released_challenges.released_at,
+                        released_challenges.is_seed as is_seeded_release
+                    from sent_solves
+                    left join released_challenges on
+                        released_challenges.event = sent_solves.event and
+                        released_challenges.challenge_name = sent_solves.challenge_name
+                    where
+                        sent_solves.event = ?1 and
+                        sent_solves.ignore_reason is null
+                    order by sent_solves.solved_at nulls last, sent_solves.solve_id
+                "
+            )
+            .bind(event)
+            .fetch_all(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn get_sent_solves<'a>(&'a self, event: &'a str) -> BoxFuture<'a, Result<Vec<SentSolve>, sqlx::Error>> {
+        async move {
+            sqlx::query_as::<_, SentSolve>(
+                "
+                    select
+                        solve_id,
+                        challenge_name,
+                        player_id,
+                        player_name,
+                        team_id,
+                        team_name,
+                        challenge_category,
+                        solved_at,
+                        is_first_blood,
+                        ignore_reason
+                    from sent_solves
+                    where event = ?1
+                    order by solve_id
+                "
+            )
+            .bind(event)
+            .fetch_all(&self.pool)
+            .await
+        }.boxed()
+    }
+    fn ping(&self) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        async move {
+            sqlx::query("select 1")
+                .fetch_one(&self.pool)
+                .await?;
+            Ok(())
+        }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+    use twilight_model::id::Id as Snowflake;
+    use uuid::Uuid;
+
+    use crate::config::WebhookRole;
+    use crate::models::announcement::NewAnnouncement;
+    use crate::models::scoreboard::RankedScoreboardEntry;
+    use crate::repository::{MigrationState, Repository, SolveRecord};
+    use super::{Database, SqliteDatabase};
+
+    async fn repository() -> Repository {
+        Repository::new("sqlite::memory:").await.expect("in-memory sqlite should always open")
+    }
+
+    // Whole seconds, milliseconds and nanoseconds are stored with a different number of digits,
+    // text comparisons have to order them anyway
+    fn at(seconds: i64, nanoseconds: u32) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_792_324_800 + seconds, nanoseconds).unwrap()
+    }
+
+    fn solve(challenge_name: &str, player_id: Uuid, solved_at: DateTime<Utc>) -> SolveRecord {
+        SolveRecord {
+            challenge_name: challenge_name.to_string(),
+            player_id,
+            solved_at,
+            player_name: Some("alice".to_string()),
+            team_id: Some(Uuid::new_v4()),
+            team_name: Some("flaggers".to_string()),
+            challenge_category: Some("rev".to_string())
+        }
+    }
+
+    fn announcement(announce_at: DateTime<Utc>, content: &str) -> NewAnnouncement {
+        NewAnnouncement {
+            announce_at,
+            role: WebhookRole::Announcement,
+            webhook_id: Snowflake::new_checked(1234),
+            content: content.to_string()
+        }
+    }
+
+    fn entry(rank: i32, score: i64) -> RankedScoreboardEntry {
+        RankedScoreboardEntry {
+            team_id: Uuid::new_v4(),
+            team_name: format!("team {rank}"),
+            rank,
+            score
+        }
+    }
+
+    #[tokio::test]
+    async fn migrations_are_applied_and_can_be_undone() {
+        let repository = repository().await;
+        let statuses = repository.migration_status().await.unwrap();
+        assert!(!statuses.is_empty());
+        assert!(statuses.iter().all(|status| status.state == MigrationState::Applied));
+
+        repository.undo_migrations(0).await.unwrap();
+        assert!(repository.migration_status().await.unwrap().iter().all(|status| status.state == MigrationState::Pending));
+        assert!(repository.has_sent_solve("baby rev", &Uuid::new_v4()).await.is_err());
+
+        repository.run_migrations().await.unwrap();
+        assert!(!repository.has_sent_solve("baby rev", &Uuid::new_v4()).await.unwrap());
+        repository.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sent_solves_are_found_by_blob_player_id() {
+        let repository = repository().await;
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        assert!(!repository.has_been_solved("baby rev").await.unwrap());
+
+        repository.mark_solve_as_ignored(&solve("baby rev", bob, at(0, 0)), "outside the event window").await.unwrap();
+        assert!(repository.has_sent_solve("baby rev", &bob).await.unwrap());
+        assert!(!repository.has_been_solved("baby rev").await.unwrap());
+
+        repository.mark_challenge_as_solved(&solve("baby rev", alice, at(60, 0)), true).await.unwrap();
+        assert!(repository.has_sent_solve("baby rev", &alice).await.unwrap());
+        assert!(!repository.has_sent_solve("baby rev", &Uuid::new_v4()).await.unwrap());
+        assert!(!repository.has_sent_solve("secret", &alice).await.unwrap());
+        assert!(repository.has_been_solved("baby rev").await.unwrap());
+
+        let other_event = repository.for_event("junior");
+        assert!(!other_event.has_sent_solve("baby rev", &alice).await.unwrap());
+        assert!(!other_event.has_been_solved("baby rev").await.unwrap());
+        assert!(other_event.get_sent_solves().await.unwrap().is_empty());
+
+        let sent_solves = repository.get_sent_solves().await.unwrap();
+        assert_eq!(sent_solves.len(), 2);
+        assert_eq!(sent_solves[0].player_id, bob);
+        assert_eq!(sent_solves[0].ignore_reason.as_deref(), Some("outside the event window"));
+        assert!(!sent_solves[0].is_first_blood);
+        assert_eq!(sent_solves[1].player_id, alice);
+        assert_eq!(sent_solves[1].solved_at, Some(at(60, 0)));
+        assert_eq!(sent_solves[1].team_name.as_deref(), Some("flaggers"));
+        assert!(sent_solves[1].is_first_blood);
+        assert!(sent_solves[1].ignore_reason.is_none());
+    }
+
+    #[tokio::test]
+    async fn report_solves_are_ordered_by_time_and_joined_with_releases() {
+        let repository = repository().await;
+        repository.mark_challenge_as_solved(&solve("late", Uuid::new_v4(), at(1, 0)), true).await.unwrap();
+        repository.mark_challenge_as_solved(&solve("exact", Uuid::new_v4(), at(0, 0)), true).await.unwrap();
+        repository.mark_challenge_as_solved(&solve("nanos", Uuid::new_v4(), at(0, 123_456_789)), true).await.unwrap();
+        repository.mark_challenge_as_solved(&solve("millis", Uuid::new_v4(), at(0, 5_000_000)), true).await.unwrap();
+        repository.mark_solve_as_ignored(&solve("ignored", Uuid::new_v4(), at(0, 1)), "hidden").await.unwrap();
+        repository.for_event("junior").mark_challenge_as_released("late", false).await.unwrap();
+        repository.mark_challenge_as_released("nanos", false).await.unwrap();
+        repository.mark_challenge_as_released("exact", true).await.unwrap();
+
+        let report_solves = repository.get_report_solves().await.unwrap();
+        let challenge_names = report_solves.iter().map(|solve| solve.challenge_name.as_str()).collect::<Vec<_>>();
+        assert_eq!(challenge_names, ["exact", "millis", "nanos", "late"]);
+        assert_eq!(report_solves[2].solved_at, Some(at(0, 123_456_789)));
+        assert!(report_solves[2].released_at.is_some());
+        assert_eq!(report_solves[2].is_seeded_release, Some(false));
+        assert_eq!(report_solves[0].is_seeded_release, Some(true));
+        assert!(report_solves[3].released_at.is_none());
+        assert_eq!(report_solves[3].is_seeded_release, None);
+    }
+
+    #[tokio::test]
+    async fn challenges_are_released_once_per_event() {
+        let repository = repository().await;
+        assert!(!repository.has_released_challenges().await.unwrap());
+        repository.mark_challenge_as_released("baby rev", true).await.unwrap();
+        repository.mark_challenge_as_released("baby rev", false).await.unwrap();
+        assert!(repository.has_released_challenges().await.unwrap());
+        assert!(repository.has_released_challenge("baby rev").await.unwrap());
+        assert!(!repository.has_released_challenge("secret").await.unwrap());
+        assert!(!repository.for_event("junior").has_released_challenges().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn announcements_are_listed_and_picked_in_time_order() {
+        let repository = repository().await;
+        let late_id = repository.create_announcement(&announcement(at(3600, 0), "late")).await.unwrap();
+        let millis_id = repository.create_announcement(&announcement(at(0, 5_000_000), "millis")).await.unwrap();
+        let exact_id = repository.create_announcement(&announcement(at(0, 0), "exact")).await.unwrap();
+        let missed_id = repository.create_announcement(&announcement(at(-60, 0), "missed")).await.unwrap();
+        assert_ne!(late_id, millis_id);
+
+        let announcements = repository.list_announcements().await.unwrap();
+        let ids = announcements.iter().map(|announcement| announcement.id).collect::<Vec<_>>();
+        assert_eq!(ids, [missed_id, exact_id, millis_id, late_id]);
+        assert_eq!(announcements[1].announce_at, at(0, 0));
+        assert_eq!(announcements[1].role, WebhookRole::Announcement);
+        assert_eq!(announcements[1].webhook_id, Snowflake::new_checked(1234));
+        assert!(announcements[1].posted_at.is_none());
+
+        let next = repository.next_pending_announcement(at(0, 0)).await.unwrap().unwrap();
+        assert_eq!(next.id, exact_id);
+        repository.mark_announcement_as_posted(exact_id).await.unwrap();
+        let next = repository.next_pending_announcement(at(0, 0)).await.unwrap().unwrap();
+        assert_eq!(next.id, millis_id);
+        assert!(repository.next_pending_announcement(at(3600, 1)).await.unwrap().is_none());
+        assert!(repository.list_announcements().await.unwrap()[1].posted_at.is_some());
+
+        assert!(!repository.for_event("junior").delete_announcement(late_id).await.unwrap());
+        assert!(repository.delete_announcement(late_id).await.unwrap());
+        assert!(!repository.delete_announcement(late_id).await.unwrap());
+        assert_eq!(repository.list_announcements().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn config_announcements_are_upserted_and_pruned() {
+        let repository = repository().await;
+        repository.create_announcement(&announcement(at(120, 0), "manual")).await.unwrap();
+        let main_announcements = [
+            ("start".to_string(), announcement(at(0, 0), "starting")),
+            ("end".to_string(), announcement(at(3600, 0), "ending")),
+            ("hint".to_string(), announcement(at(60, 0), "hint"))
+        ];
+        let junior_announcements = [("end".to_string(), announcement(at(3600, 0), "junior ending"))];
+        repository.replace_config_announcements_of_events(&[("default", &main_announcements), ("junior", &junior_announcements)]).await.unwrap();
+        assert_eq!(repository.list_announcements().await.unwrap().len(), 4);
+        assert_eq!(repository.for_event("junior").list_announcements().await.unwrap().len(), 1);
+
+        repository.replace_config_announcements(&[
+            ("start".to_string(), announcement(at(10, 0), "starting soon")),
+            ("end".to_string(), announcement(at(3600, 0), "ending"))
+        ]).await.unwrap();
+        let start = repository.list_announcements().await.unwrap().into_iter().find(|announcement| announcement.config_key.as_deref() == Some("start")).unwrap();
+        assert_eq!(start.content, "starting soon");
+        assert_eq!(start.announce_at, at(10, 0));
+        let contents = repository.list_announcements().await.unwrap().into_iter().map(|announcement| announcement.content).collect::<Vec<_>>();
+        assert_eq!(contents, ["starting soon", "manual", "ending"]);
+        assert_eq!(repository.for_event("junior").list_announcements().await.unwrap().len(), 1);
+
+        // Posted announcements are history and stay as they were
+        repository.mark_announcement_as_posted(start.id).await.unwrap();
+        repository.replace_config_announcements(&[("start".to_string(), announcement(at(20, 0), "changed after posting"))]).await.unwrap();
+        let contents = repository.list_announcements().await.unwrap().into_iter().map(|announcement| announcement.content).collect::<Vec<_>>();
+        assert_eq!(contents, ["starting soon", "manual"]);
+
+        repository.replace_config_announcements(&[("it's \"quoted\"".to_string(), announcement(at(30, 0), "quoted"))]).await.unwrap();
+        let contents = repository.list_announcements().await.unwrap().into_iter().map(|announcement| announcement.content).collect::<Vec<_>>();
+        assert_eq!(contents, ["starting soon", "quoted", "manual"]);
+        assert_eq!(repository.for_event("junior").list_announcements().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn scoreboard_snapshots_keep_their_entries() {
+        let repository = repository().await;
+        assert!(repository.latest_scoreboard_snapshot().await.unwrap().is_none());
+        let first_id = repository.create_scoreboard_snapshot(&[entry(2, 100), entry(1, 200)]).await.unwrap();
+        let second_id = repository.create_scoreboard_snapshot(&[entry(1, 300)]).await.unwrap();
+        repository.for_event("junior").create_scoreboard_snapshot(&[]).await.unwrap();
+        assert_ne!(first_id, second_id);
+
+        let latest = repository.latest_scoreboard_snapshot().await.unwrap().unwrap();
+        assert_eq!(latest.id, second_id);
+        assert!(latest.taken_at <= Utc::now() && latest.taken_at > Utc::now() - TimeDelta::minutes(1));
+
+        let entries = repository.get_scoreboard_snapshot_entries(first_id).await.unwrap();
+        assert_eq!(entries.iter().map(|entry| (entry.rank, entry.score)).collect::<Vec<_>>(), [(1, 200), (2, 100)]);
+        assert!(repository.get_scoreboard_snapshot_entries(second_id + 100).await.unwrap().is_empty());
+    }
+
+    // Nothing deletes snapshots yet, but the schema relies on foreign keys being enforced
+    #[tokio::test]
+    async fn deleting_a_snapshot_deletes_its_entries() {
+        let database = SqliteDatabase::connect("sqlite::memory:").await.unwrap();
+        let snapshot_id = database.create_scoreboard_snapshot("default", &[entry(1, 100), entry(2, 50)]).await.unwrap();
+        sqlx::query("delete from scoreboard_snapshots where snapshot_id = ?1")
+            .bind(snapshot_id)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+        let entry_count = sqlx::query_scalar::<_, i64>("select count(*) from scoreboard_snapshot_entries")
+            .fetch_one(&database.pool)
+            .await
+            .unwrap();
+        assert_eq!(entry_count, 0);
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, post};
+use axum::Json;
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::request::Parts;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::models::announcement::{Announcement, NewAnnouncement};
+use crate::report::{Report, ReportFormat};
+use crate::services::config_reloader::ReloadError;
+use crate::state::{AppState, EventState};
+
+pub(crate) fn router() -> axum::Router<Arc<AppState>> {
+    axum::Router::new()
+        .route("/announcements", get(get_announcements).post(create_announcement))
+        .route("/announcements/{announcement_id}", delete(delete_announcement))
+        .route("/report", get(get_report))
+        .route("/reload", post(reload_config))
+}
+
+// Admin routes are disabled unless an admin_token is configured
+pub(crate) struct AdminAuth;
+impl FromRequestParts<Arc<AppState>> for AdminAuth {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let Some(admin_token) = &state.admin_token else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+        let provided_token = parts.headers
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "));
+        let Some(provided_token) = provided_token else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+        // Comparing digests keeps the comparison constant-time regardless of the token lengths
+        let is_valid: bool = Sha256::digest(provided_token.as_bytes()).ct_eq(&Sha256::digest(admin_token.as_bytes())).into();
+        if !is_valid {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Ok(AdminAuth)
+    }
+}
+
+// The event from ?event=, which can be left out when only one is configured
+pub(crate) struct SelectedEvent(Arc<EventState>);
+#[derive(Deserialize)]
+struct EventQuery {
+    event: Option<String>
+}
+impl FromRequestParts<Arc<AppState>> for SelectedEvent {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<EventQuery>::try_from_uri(&parts.uri).map_err(|_error| (StatusCode::BAD_REQUEST, "invalid query string"))?;
+        match (query.event, state.events.as_slice()) {
+            (None, [event]) => Ok(SelectedEvent(event.clone())),
+            (None, _) => Err((StatusCode::BAD_REQUEST, "more than one event is configured, pick one with ?event=")),
+            (Some(name), _) => state.find_event(&name).cloned().map(SelectedEvent).ok_or((StatusCode::NOT_FOUND, "no event with that name"))
+        }
+    }
+}
+
+async fn get_announcements(_auth: AdminAuth, SelectedEvent(event): SelectedEvent) -> Result<Json<Vec<Announcement>>, StatusCode> {
+    event.repository.list_announcements()
+        .await
+        .map(Json)
+        .map_err(|error| {
+            tracing::error!(?error, "failed to list announcements");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(Serialize)]
+struct CreatedAnnouncement {
+    id: i32
+}
+async fn create_announcement(_auth: AdminAuth, SelectedEvent(event): SelectedEvent, Json(announcement): Json<NewAnnouncement>) -> Result<(StatusCode, Json<CreatedAnnouncement>), StatusCode> {
+    let id = event.repository.create_announcement(&announcement)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to create announcement");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    event.scheduler_service.reschedule();
+    Ok((StatusCode::CREATED, Json(CreatedAnnouncement { id })))
+}
+
+async fn delete_announcement(_auth: AdminAuth, SelectedEvent(event): SelectedEvent, Path(announcement_id): Path<i32>) -> StatusCode {
+    match event.repository.delete_announcement(announcement_id).await {
+        Ok(true) => {
+            event.scheduler_service.reschedule();
+            StatusCode::NO_CONTENT
+        },
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(error) => {
+            tracing::error!(?error, "failed to delete announcement");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    #[serde(default)]
+    format: ReportFormat
+}
+async fn get_report(_auth: AdminAuth, SelectedEvent(event): SelectedEvent, Query(query): Query<ReportQuery>) -> Result<impl IntoResponse, StatusCode> {
+    let solves = event.repository.get_report_solves()
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to fetch solves for report");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let report = Report::build(solves, event.window_config.start_time);
+    Ok(([(CONTENT_TYPE, query.format.content_type())], report.render(query.format)))
+}
+
+async fn reload_config(_auth: AdminAuth, State(state): State<Arc<AppState>>) -> Result<StatusCode, (StatusCode, String)> {
+    state.config_reloader_service.reload()
+        .await
+        .map(|()| StatusCode::NO_CONTENT)
+        .map_err(|error| {
+            let status_code = match error {
+                ReloadError::ConfigError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                ReloadError::AnnouncementSyncError(..) => StatusCode::SERVICE_UNAVAILABLE
+            };
+            (status_code, error.to_string())
+        })
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::extract::State;
+use axum::routing::get;
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::state::AppState;
+
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) fn router() -> axum::Router<Arc<AppState>> {
+    axum::Router::new()
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness))
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str
+}
+async fn get_health() -> Json<Health> {
+    Json(Health {
+        status: "ok"
+    })
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    checks: BTreeMap<String, ReadinessCheck>
+}
+#[derive(Serialize)]
+struct ReadinessCheck {
+    ok: bool,
+    detail: String
+}
+impl ReadinessCheck {
+    fn new(ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            ok,
+            detail: detail.into()
+        }
+    }
+}
+
+async fn get_readiness(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Readiness>) {
+    let mut checks = BTreeMap::new();
+
+    let database = match timeout(DATABASE_TIMEOUT, state.repository.ping()).await {
+        Ok(Ok(())) => ReadinessCheck::new(true, "reachable"),
+        Ok(Err(error)) => ReadinessCheck::new(false, error.to_string()),
+        Err(_) => ReadinessCheck::new(false, "timed out")
+    };
+    checks.insert("database".to_string(), database);
+
+    // Checks are per event, named after the event once there is more than one
+    for event in &state.events {
+        let check_name = |check: &str| match state.events.len() {
+            1 => check.to_string(),
+            _ => format!("{}.{check}", event.name)
+        };
+        let restart_count = event.solve_fetcher_service.restart_count();
+        let websocket_grace = Duration::from_secs(state.readiness_config.websocket_grace_seconds);
+        let websocket = match event.solve_fetcher_service.disconnected_at() {
+            _ if event.solve_fetcher_service.is_berg_down() => ReadinessCheck::new(false, format!("berg down, {} failed reconnects, {restart_count} restarts", event.solve_fetcher_service.consecutive_failures_count())),
+            _ if event.solve_fetcher_service.is_connected() => ReadinessCheck::new(true, format!("connected, {restart_count} restarts")),
+            _ if event.solve_fetcher_service.is_polling() => ReadinessCheck::new(true, format!("polling solves, {restart_count} restarts")),
+            Some(disconnected_at) => ReadinessCheck::new(
+                disconnected_at.elapsed() <= websocket_grace,
+                format!("disconnected for {}s, {restart_count} restarts", disconnected_at.elapsed().as_secs())
+            ),
+            None => ReadinessCheck::new(false, format!("never connected, {restart_count} restarts"))
+        };
+        checks.insert(check_name("events_websocket"), websocket);
+
+        let caches = [
+            ("players", event.player_fetcher_service.is_populated()),
+            ("teams", event.team_fetcher_service.is_populated()),
+            ("challenges", event.challenge_fetcher_service.is_populated())
+        ];
+        let unpopulated_caches = caches.iter().filter(|(_, is_populated)| !is_populated).map(|(name, _)| *name).collect::<Vec<_>>();
+        checks.insert(check_name("caches"), match unpopulated_caches.is_empty() {
+            true => ReadinessCheck::new(true, "populated"),
+            false => ReadinessCheck::new(false, format!("not populated: {}", unpopulated_caches.join(", ")))
+        });
+
+        let mut missing_roles = Vec::new();
+        for role in &state.readiness_config.required_roles {
+            if event.webhook_service.get_webhook(role.clone()).await.is_none() {
+                missing_roles.push(role.as_str());
+            }
+        }
+        checks.insert(check_name("webhooks"), match missing_roles.is_empty() {
+            true => ReadinessCheck::new(true, "every required role has a webhook"),
+            false => ReadinessCheck::new(false, format!("no webhook for: {}", missing_roles.join(", ")))
+        });
+    }
+
+    let ready = checks.values().all(|check| check.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(Readiness { ready, checks }))
+}
@@ -1,15 +1,65 @@
 use std::sync::Arc;
+use axum::extract::State;
 use axum::routing::get;
 
+use crate::models::websocket::EventKind;
 use crate::state::AppState;
 
-pub(crate) fn router() -> axum::Router<Arc<AppState>> {
+mod admin;
+mod health;
+
+// Routes that are fine to expose to everyone
+pub(crate) fn public_router() -> axum::Router<Arc<AppState>> {
+    health::router()
+}
+
+// Served on the admin listener if one is configured, otherwise next to the public routes
+pub(crate) fn private_router() -> axum::Router<Arc<AppState>> {
     axum::Router::new()
         .route("/metrics", get(get_metrics))
+        .nest("/admin", admin::router())
 }
 
-async fn get_metrics() -> String {
-    let lines = Vec::<String>::new();
+async fn get_metrics(State(state): State<Arc<AppState>>) -> String {
+    let mut lines = Vec::<String>::new();
+    // Everything but config reloads is per event
+    for event in &state.events {
+        let event_label = format!("event=\"{}\"", event.name);
+        lines.push(format!("dal_solve_fetcher_restarts_total{{{event_label}}} {}", event.solve_fetcher_service.restart_count()));
+        lines.push(format!("dal_solve_fetcher_dropped_solves_total{{{event_label}}} {}", event.solve_fetcher_service.dropped_solves_count()));
+        lines.push(format!("dal_solve_fetcher_consecutive_failures{{{event_label}}} {}", event.solve_fetcher_service.consecutive_failures_count()));
+        lines.push(format!("dal_solve_fetcher_berg_down{{{event_label}}} {}", u8::from(event.solve_fetcher_service.is_berg_down())));
+        lines.push(format!("dal_solve_fetcher_polling{{{event_label}}} {}", u8::from(event.solve_fetcher_service.is_polling())));
+        lines.push(format!("dal_solve_fetcher_failed_to_poll_total{{{event_label}}} {}", event.solve_fetcher_service.failed_to_poll_count()));
+        if let Some(berg_client) = &event.berg_client {
+            lines.push(format!("dal_berg_client_logins_total{{{event_label}}} {}", berg_client.logins_count()));
+            lines.push(format!("dal_berg_client_failed_to_login_total{{{event_label}}} {}", berg_client.failed_to_login_count()));
+        }
+        for kind in EventKind::ALL {
+            lines.push(format!("dal_solve_fetcher_events_total{{{event_label},type=\"{}\"}} {}", kind.as_str(), event.solve_fetcher_service.event_count(kind)));
+        }
+        lines.push(format!("dal_solve_fetcher_malformed_events_total{{{event_label}}} {}", event.solve_fetcher_service.malformed_events_count()));
+        lines.push(format!("dal_solve_fetcher_event_version{{{event_label}}} {}", event.solve_fetcher_service.latest_event_version()));
+        lines.push(format!("dal_solve_fetcher_protocol_changes{{{event_label}}} {}", event.solve_fetcher_service.protocol_changes_count()));
+        lines.push(format!("dal_solve_sender_failed_to_send_total{{{event_label}}} {}", event.solve_sender_service.failed_to_send_count()));
+        lines.push(format!("dal_solve_sender_failed_to_process_total{{{event_label}}} {}", event.solve_sender_service.failed_to_process_count()));
+        lines.push(format!("dal_solve_sender_ignored_solves_total{{{event_label}}} {}", event.solve_sender_service.ignored_solves_count()));
+        lines.push(format!("dal_player_fetcher_failed_to_fetch_total{{{event_label}}} {}", event.player_fetcher_service.failed_to_fetch_players_count()));
+        lines.push(format!("dal_team_fetcher_failed_to_fetch_total{{{event_label}}} {}", event.team_fetcher_service.failed_to_fetch_teams_count()));
+        lines.push(format!("dal_scheduler_failed_to_send_total{{{event_label}}} {}", event.scheduler_service.failed_to_send_count()));
+        lines.push(format!("dal_scheduler_failed_to_process_total{{{event_label}}} {}", event.scheduler_service.failed_to_process_count()));
+        lines.push(format!("dal_release_sender_failed_to_send_total{{{event_label}}} {}", event.release_sender_service.failed_to_send_count()));
+        lines.push(format!("dal_release_sender_failed_to_process_total{{{event_label}}} {}", event.release_sender_service.failed_to_process_count()));
+        lines.push(format!("dal_scoreboard_failed_to_send_total{{{event_label}}} {}", event.scoreboard_service.failed_to_send_count()));
+        lines.push(format!("dal_scoreboard_failed_to_process_total{{{event_label}}} {}", event.scoreboard_service.failed_to_process_count()));
+        lines.push(format!("dal_challenge_fetcher_failed_to_fetch_total{{{event_label}}} {}", event.challenge_fetcher_service.failed_to_fetch_challenges_count()));
+        lines.push(format!("dal_alerter_alerts_sent_total{{{event_label}}} {}", event.alerter_service.alerts_sent_count()));
+        lines.push(format!("dal_alerter_failed_to_send_total{{{event_label}}} {}", event.alerter_service.failed_to_send_count()));
+    }
+    lines.push(format!("dal_config_reloader_reloads_total {}", state.config_reloader_service.reload_count()));
+    lines.push(format!("dal_config_reloader_failed_to_reload_total {}", state.config_reloader_service.failed_to_reload_count()));
+    lines.push(format!("dal_alerter_alerts_sent_total {}", state.alerter_service.alerts_sent_count()));
+    lines.push(format!("dal_alerter_failed_to_send_total {}", state.alerter_service.failed_to_send_count()));
 
     lines.join("\n")
 }
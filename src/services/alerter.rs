@@ -0,0 +1,252 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval, timeout, Instant, MissedTickBehavior};
+
+use twilight_model::id::Id as Snowflake;
+use twilight_model::id::marker::WebhookMarker;
+
+use crate::config::{AlertingConfig, BergConnectionConfig, WebhookRole};
+use crate::services::webhook::WebhookService;
+use crate::shutdown::Shutdown;
+use crate::state::{AppState, EventState};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Watches the other services and tells the ops webhooks when something needs a human. Each event
+// has one for its source and solves, a shared one covers the database and errors of every event and
+// posts to the ops webhooks of all events
+pub(crate) struct AlerterService {
+    alerts_sent_count: AtomicU32,
+    failed_to_send_count: AtomicU32,
+    alerting_config: AlertingConfig,
+    berg_down_after: Duration,
+    // From the deprecated berg_connection.alert_webhook_id, gets berg alerts on top of the ops webhooks
+    berg_alert_webhook_id: Option<Snowflake<WebhookMarker>>,
+    webhook_services: Vec<Arc<WebhookService>>
+}
+impl AlerterService {
+    pub(crate) fn new(alerting_config: AlertingConfig, berg_connection_config: &BergConnectionConfig, webhook_service: Arc<WebhookService>) -> Arc<Self> {
+        let berg_down_after = match berg_connection_config.alert_after_seconds {
+            Some(alert_after_seconds) => Duration::from_secs(alert_after_seconds),
+            None => Duration::from_secs(alerting_config.websocket_down_minutes * 60)
+        };
+        Arc::new(Self {
+            alerts_sent_count: AtomicU32::default(),
+            failed_to_send_count: AtomicU32::default(),
+            alerting_config,
+            berg_down_after,
+            berg_alert_webhook_id: berg_connection_config.alert_webhook_id,
+            webhook_services: vec![webhook_service]
+        })
+    }
+    pub(crate) fn new_shared(alerting_config: AlertingConfig, webhook_services: Vec<Arc<WebhookService>>) -> Arc<Self> {
+        Arc::new(Self {
+            alerts_sent_count: AtomicU32::default(),
+            failed_to_send_count: AtomicU32::default(),
+            berg_down_after: Duration::from_secs(alerting_config.websocket_down_minutes * 60),
+            alerting_config,
+            berg_alert_webhook_id: None,
+            webhook_services
+        })
+    }
+    // Without an event, the shared checks are run
+    pub(crate) fn start(self: Arc<Self>, state: Arc<AppState>, event: Option<Arc<EventState>>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
+            let instance = self;
+            async move {
+                instance.run(state, event, shutdown).await
+            }
+        });
+    }
+    pub(crate) fn alerts_sent_count(&self) -> u32 {
+        self.alerts_sent_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_send_count(&self) -> u32 {
+        self.failed_to_send_count.load(Ordering::SeqCst)
+    }
+    async fn run(self: Arc<Self>, state: Arc<AppState>, event: Option<Arc<EventState>>, shutdown: Shutdown) {
+        let cooldown = Duration::from_secs(self.alerting_config.cooldown_minutes * 60);
+        let started_at = Instant::now();
+        // Alerts that haven't resolved yet and when they were last sent
+        let mut active_alerts = HashMap::<Alert, Instant>::new();
+        let mut previous_error_count = total_error_count(&state);
+        let mut check_interval = interval(CHECK_INTERVAL);
+        check_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        check_interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = check_interval.tick() => {},
+                _ = shutdown.triggered() => return
+            }
+
+            let problems = match &event {
+                Some(event) if event.solve_fetcher_service.is_poll_only() => vec![
+                    (Alert::PollingFails, self.check_polling(event)),
+                    (Alert::StuckSolve, self.check_stuck_solves(event))
+                ],
+                Some(event) => vec![
+                    (Alert::BergDown, self.check_berg(event, started_at)),
+                    (Alert::StuckSolve, self.check_stuck_solves(event))
+                ],
+                None => {
+                    let error_count = total_error_count(&state);
+                    let new_error_count = error_count.saturating_sub(previous_error_count);
+                    previous_error_count = error_count;
+                    vec![
+                        (Alert::ErrorRate, self.check_error_rate(new_error_count)),
+                        (Alert::DatabaseUnreachable, check_database(&state).await)
+                    ]
+                }
+            };
+
+            for (alert, problem) in problems {
+                match (problem, active_alerts.get(&alert)) {
+                    (Some(_), Some(last_sent_at)) if last_sent_at.elapsed() < cooldown => {},
+                    (Some(problem), _) => {
+                        if self.send_alert(alert, &format!("🚨 {problem}")).await {
+                            active_alerts.insert(alert, Instant::now());
+                        }
+                    },
+                    (None, Some(_)) => {
+                        active_alerts.remove(&alert);
+                        self.send_alert(alert, &format!("✅ {}", alert.resolved_message(event.as_deref()))).await;
+                    },
+                    (None, None) => {}
+                }
+            }
+        }
+    }
+    fn check_error_rate(&self, new_error_count: u32) -> Option<String> {
+        let threshold = self.alerting_config.errors_per_minute * (CHECK_INTERVAL.as_secs() / 60) as u32;
+        (new_error_count >= threshold).then(|| format!("{new_error_count} errors in the last minute, check the logs"))
+    }
+    fn check_berg(&self, event: &EventState, started_at: Instant) -> Option<String> {
+        let solve_fetcher_service = &event.solve_fetcher_service;
+        // Solves still arrive while polling, so that's not worth waking anyone up for
+        if solve_fetcher_service.is_connected() || solve_fetcher_service.is_polling() {
+            return None;
+        }
+        let down_for = solve_fetcher_service.disconnected_at().unwrap_or(started_at).elapsed();
+        if down_for < self.berg_down_after {
+            return None;
+        }
+        Some(format!(
+            "The berg events websocket of event {} has been down for {} minutes ({} failed reconnects)",
+            event.name,
+            down_for.as_secs() / 60,
+            solve_fetcher_service.consecutive_failures_count()
+        ))
+    }
+    // Without a websocket there is nothing to reconnect, only polls that keep failing
+    fn check_polling(&self, event: &EventState) -> Option<String> {
+        let solve_fetcher_service = &event.solve_fetcher_service;
+        if solve_fetcher_service.is_polling() {
+            return None;
+        }
+        let failed_poll_count = solve_fetcher_service.consecutive_failures_count();
+        let failing_for = Duration::from_secs(event.berg_connection_config.poll_interval_seconds) * failed_poll_count;
+        if failing_for < self.berg_down_after {
+            return None;
+        }
+        Some(format!(
+            "Polling solves from the {} source of event {} has been failing for {} minutes ({} failed polls)",
+            event.source.as_str(),
+            event.name,
+            failing_for.as_secs() / 60,
+            failed_poll_count
+        ))
+    }
+    fn check_stuck_solves(&self, event: &EventState) -> Option<String> {
+        let oldest_pending_solve_age = event.solve_sender_service.oldest_pending_solve_age()?;
+        (oldest_pending_solve_age >= Duration::from_secs(self.alerting_config.stuck_solve_minutes * 60))
+            .then(|| format!("A solve of event {} has been waiting to be announced for {} minutes", event.name, oldest_pending_solve_age.as_secs() / 60))
+    }
+    // Returns whether any webhook got the alert. Events sharing an ops webhook only get it there once
+    async fn send_alert(&self, alert: Alert, content: &str) -> bool {
+        let mut webhook_ids = HashSet::new();
+        let mut results = Vec::new();
+        for webhook_service in &self.webhook_services {
+            let Some(webhook) = webhook_service.get_webhook(WebhookRole::Ops).await else {
+                continue;
+            };
+            if webhook_ids.insert(webhook.id) {
+                results.push(webhook_service.send_message_to(webhook.id, content).await);
+            }
+        }
+        // Only event alerters have a berg alert webhook, and only their own webhooks
+        if let (Alert::BergDown | Alert::PollingFails, Some(berg_alert_webhook_id)) = (alert, self.berg_alert_webhook_id) && !webhook_ids.contains(&berg_alert_webhook_id)
+            && let Some(webhook_service) = self.webhook_services.first() {
+            results.push(webhook_service.send_message_to(berg_alert_webhook_id, content).await);
+        }
+        let mut is_sent = false;
+        for result in results {
+            match result {
+                Ok(Some(_)) => {
+                    self.alerts_sent_count.fetch_add(1, Ordering::SeqCst);
+                    is_sent = true;
+                },
+                Ok(None) => {},
+                Err(error) => {
+                    tracing::error!(?error, "failed to send alert");
+                    self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+        is_sent
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+enum Alert {
+    ErrorRate,
+    BergDown,
+    PollingFails,
+    DatabaseUnreachable,
+    StuckSolve
+}
+impl Alert {
+    // Event alerts are only raised by event alerters, so they always have the event
+    fn resolved_message(&self, event: Option<&EventState>) -> String {
+        let event_name = event.map(|event| event.name.as_str()).unwrap_or_default();
+        match self {
+            Alert::ErrorRate => "Error rate is back to normal".to_string(),
+            Alert::BergDown => format!("The berg events websocket of event {event_name} is back"),
+            Alert::PollingFails => format!("Polling solves from the {} source of event {event_name} works again", event.map(|event| event.source.as_str()).unwrap_or_default()),
+            Alert::DatabaseUnreachable => "The database is reachable again".to_string(),
+            Alert::StuckSolve => format!("No solves of event {event_name} are stuck anymore")
+        }
+    }
+}
+
+async fn check_database(state: &AppState) -> Option<String> {
+    match timeout(DATABASE_TIMEOUT, state.repository.ping()).await {
+        Ok(Ok(())) => None,
+        Ok(Err(error)) => Some(format!("The database is unreachable: {error}")),
+        Err(_) => Some("The database is unreachable: timed out".to_string())
+    }
+}
+
+// Websocket restarts are left out, the berg alerts cover those
+fn total_error_count(state: &AppState) -> u32 {
+    state.events.iter()
+        .flat_map(|event| [
+            event.solve_fetcher_service.dropped_solves_count(),
+            event.solve_sender_service.failed_to_send_count(),
+            event.solve_sender_service.failed_to_process_count(),
+            event.player_fetcher_service.failed_to_fetch_players_count(),
+            event.team_fetcher_service.failed_to_fetch_teams_count(),
+            event.challenge_fetcher_service.failed_to_fetch_challenges_count(),
+            event.scheduler_service.failed_to_send_count(),
+            event.scheduler_service.failed_to_process_count(),
+            event.release_sender_service.failed_to_send_count(),
+            event.release_sender_service.failed_to_process_count(),
+            event.scoreboard_service.failed_to_send_count(),
+            event.scoreboard_service.failed_to_process_count()
+        ])
+        .chain([state.config_reloader_service.failed_to_reload_count()])
+        .fold(0, u32::saturating_add)
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, Instant};
+
+use crate::http_cache::{Conditional, ConditionalResponse};
+use crate::models::challenge::Challenge;
+use crate::sources::ScoreboardSource;
+
+const CACHE_DURATION: Duration = Duration::from_secs(15);
+// Upper bound for berg's Cache-Control, challenges rarely change during a CTF
+const MAX_CACHE_DURATION: Duration = Duration::from_secs(60 * 5);
+
+pub(crate) struct ChallengeFetcherService {
+    signal_tx: mpsc::UnboundedSender<SignalRequest>,
+    failed_to_fetch_challenges_count: AtomicU32,
+    is_populated: AtomicBool,
+    source: Arc<dyn ScoreboardSource>,
+    release_tx: mpsc::UnboundedSender<ChallengeRelease>
+}
+
+impl ChallengeFetcherService {
+    pub(crate) fn new(source: Arc<dyn ScoreboardSource>, release_tx: mpsc::UnboundedSender<ChallengeRelease>) -> Arc<Self> {
+        let (signal_request_tx, signal_request_rx) = mpsc::unbounded_channel();
+        let instance = Arc::new(Self {
+            signal_tx: signal_request_tx,
+            failed_to_fetch_challenges_count: AtomicU32::default(),
+            is_populated: AtomicBool::default(),
+            source,
+            release_tx
+        });
+        tokio::spawn({
+            let instance = instance.clone();
+            async move {
+                instance.run(signal_request_rx).await
+            }
+        });
+        instance
+    }
+    pub(crate) fn failed_to_fetch_challenges_count(&self) -> u32 {
+        self.failed_to_fetch_challenges_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn is_populated(&self) -> bool {
+        self.is_populated.load(Ordering::SeqCst)
+    }
+    pub(crate) async fn get_challenge(&self, challenge_name: String) -> Option<Arc<Challenge>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        // Handled in next line instead
+        let _ = self.signal_tx.send(SignalRequest::GetChallenge(GetChallengeSignalRequest {
+            challenge_name,
+            response_tx
+        }));
+        response_rx.await.ok()
+    }
+    pub(crate) fn upsert_challenge(&self, challenge: Challenge) {
+        let _ = self.signal_tx.send(SignalRequest::UpsertChallenge(challenge));
+    }
+    async fn run(self: Arc<Self>, mut signal_rx: mpsc::UnboundedReceiver<SignalRequest>) {
+        let mut challenges = HashMap::<String, Arc<Challenge>>::new();
+        let mut is_seeded = false;
+        let poll_sleep = sleep(Duration::ZERO);
+        tokio::pin!(poll_sleep);
+
+        loop {
+            tokio::select! {
+                _ = &mut poll_sleep => {
+                    let mut next_poll_in = CACHE_DURATION;
+                    match self.source.fetch_challenges().await {
+                        Ok(ConditionalResponse { body, max_age }) => {
+                            if let Conditional::Modified(new_challenges) = body {
+                                let new_challenges = new_challenges.into_iter().map(|challenge| (challenge.name.clone(), Arc::new(challenge))).collect::<HashMap<_, _>>();
+                                for challenge in new_challenges.values() {
+                                    self.release_if_new(challenges.get(&challenge.name), challenge, !is_seeded);
+                                }
+                                challenges = new_challenges;
+                                is_seeded = true;
+                            }
+                            self.is_populated.store(true, Ordering::SeqCst);
+                            if let Some(max_age) = max_age {
+                                next_poll_in = max_age.clamp(CACHE_DURATION, MAX_CACHE_DURATION);
+                            }
+                        },
+                        Err(error) => {
+                            tracing::error!(?error, "failed to fetch challenges");
+                            self.failed_to_fetch_challenges_count.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                    poll_sleep.as_mut().reset(Instant::now() + next_poll_in);
+                },
+                maybe_message = signal_rx.recv() => {
+                    let Some(message) = maybe_message else {
+                        return;
+                    };
+                    match message {
+                        SignalRequest::GetChallenge(request) => {
+                            if let Some(challenge) = challenges.get(&request.challenge_name) {
+                                let _ = request.response_tx.send(challenge.clone());
+                            }
+                        },
+                        SignalRequest::UpsertChallenge(challenge) => {
+                            let challenge = Arc::new(challenge);
+                            // Before the first fetch we can't tell a release from an update
+                            self.release_if_new(challenges.get(&challenge.name), &challenge, !is_seeded);
+                            challenges.insert(challenge.name.clone(), challenge);
+                        }
+                    }
+                },
+            };
+        }
+    }
+    fn release_if_new(&self, previous: Option<&Arc<Challenge>>, challenge: &Arc<Challenge>, is_seed: bool) {
+        let was_visible = previous.is_some_and(|previous| !previous.hidden);
+        if challenge.hidden || was_visible {
+            return;
+        }
+        let _ = self.release_tx.send(ChallengeRelease {
+            challenge: challenge.clone(),
+            is_seed
+        });
+    }
+}
+
+// A challenge that became visible, either seen on the first fetch or while running
+pub(crate) struct ChallengeRelease {
+    pub(crate) challenge: Arc<Challenge>,
+    pub(crate) is_seed: bool
+}
+
+enum SignalRequest {
+    GetChallenge(GetChallengeSignalRequest),
+    UpsertChallenge(Challenge)
+}
+struct GetChallengeSignalRequest {
+    challenge_name: String,
+    response_tx: oneshot::Sender<Arc<Challenge>>
+}
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
+use tokio::time::interval;
+
+use crate::config::{Config, ConfigError, DEFAULT_CONFIG_PATH};
+use crate::repository::Repository;
+use crate::services::scheduler::config_announcements;
+use crate::shutdown::Shutdown;
+use crate::state::EventState;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Reloads webhooks, ignore rules and announcements when the config file changes, on SIGHUP or
+// through the admin API. Anything else, like the event window or which events there are, still
+// requires a restart.
+pub(crate) struct ConfigReloaderService {
+    reload_count: AtomicU32,
+    failed_to_reload_count: AtomicU32,
+    // The file watcher, SIGHUP and the admin API could otherwise apply configs out of order
+    reload_lock: Mutex<()>,
+    config_path: Option<PathBuf>,
+    events: Vec<Arc<EventState>>,
+    repository: Arc<Repository>
+}
+impl ConfigReloaderService {
+    pub(crate) fn new(config_path: Option<PathBuf>, events: Vec<Arc<EventState>>, repository: Arc<Repository>) -> Arc<Self> {
+        Arc::new(Self {
+            reload_count: AtomicU32::default(),
+            failed_to_reload_count: AtomicU32::default(),
+            reload_lock: Mutex::new(()),
+            config_path,
+            events,
+            repository
+        })
+    }
+    pub(crate) fn start(self: Arc<Self>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
+            let instance = self;
+            async move {
+                tokio::select! {
+                    _ = instance.run() => {},
+                    _ = shutdown.triggered() => {}
+                }
+            }
+        });
+    }
+    pub(crate) fn reload_count(&self) -> u32 {
+        self.reload_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_reload_count(&self) -> u32 {
+        self.failed_to_reload_count.load(Ordering::SeqCst)
+    }
+    // An invalid config is rejected as a whole and the old one keeps running
+    pub(crate) async fn reload(&self) -> Result<(), ReloadError> {
+        let _reload_guard = self.reload_lock.lock().await;
+        let result = self.try_reload().await;
+        if result.is_err() {
+            self.failed_to_reload_count.fetch_add(1, Ordering::SeqCst);
+        }
+        result
+    }
+    async fn try_reload(&self) -> Result<(), ReloadError> {
+        let config = Config::load(self.config_path.as_deref()).await?;
+        for event_config in config.events() {
+            if !self.events.iter().any(|event| event.name == event_config.name) {
+                tracing::warn!(event = event_config.name, "new events only start after a restart");
+            }
+        }
+
+        let mut reloaded_events = Vec::new();
+        for event in &self.events {
+            let Some(event_config) = config.events().iter().find(|event_config| event_config.name == event.name) else {
+                tracing::warn!(event = event.name, "removed events keep running until a restart");
+                continue;
+            };
+            if event_config.window.start_time != event.window_config.start_time || event_config.window.end_time != event.window_config.end_time {
+                tracing::warn!(event = event.name, "start_time and end_time changes only take effect after a restart");
+            }
+            if event_config.source != event.source || event_config.berg_api_base != event.berg_api_base || event_config.berg_connection != event.berg_connection_config {
+                tracing::warn!(event = event.name, "source, berg_api_base and berg_connection changes only take effect after a restart");
+            }
+            reloaded_events.push((event, event_config, config_announcements(event_config)));
+        }
+        // Announcements go first since they are the only part that can fail. Every event's are
+        // replaced in one transaction, so if they fail nothing is swapped for any event
+        let event_announcements = reloaded_events.iter()
+            .map(|(event, _, announcements)| (event.name.as_str(), announcements.as_slice()))
+            .collect::<Vec<_>>();
+        self.repository.replace_config_announcements_of_events(&event_announcements).await?;
+        for (event, event_config, _) in reloaded_events {
+            event.scheduler_service.reschedule();
+            event.webhook_service.replace_webhooks(event_config.webhooks.clone());
+            event.solve_sender_service.replace_ignore_config(event_config.ignore.clone());
+            event.release_sender_service.replace_ignore_config(event_config.ignore.clone());
+        }
+        self.reload_count.fetch_add(1, Ordering::SeqCst);
+        tracing::info!("reloaded config");
+        Ok(())
+    }
+    async fn run(self: Arc<Self>) {
+        let mut hangup_signal = signal(SignalKind::hangup()).expect("installing a SIGHUP handler should never fail");
+        let mut poll_interval = interval(POLL_INTERVAL);
+        let mut last_modified_at = self.config_modified_at().await;
+        loop {
+            tokio::select! {
+                _ = hangup_signal.recv() => {
+                    tracing::info!("received SIGHUP, reloading config");
+                },
+                _ = poll_interval.tick() => {
+                    let modified_at = self.config_modified_at().await;
+                    if modified_at == last_modified_at {
+                        continue;
+                    }
+                    last_modified_at = modified_at;
+                    tracing::info!("config file changed, reloading config");
+                }
+            }
+            if let Err(error) = self.reload().await {
+                tracing::error!(%error, "rejected new config, keeping the old one");
+            }
+        }
+    }
+    async fn config_modified_at(&self) -> Option<SystemTime> {
+        let config_path = self.config_path.as_deref().unwrap_or(Path::new(DEFAULT_CONFIG_PATH));
+        tokio::fs::metadata(config_path).await.and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ReloadError {
+    #[error(transparent)]
+    ConfigError(#[from] ConfigError),
+    #[error("failed to sync announcements, no event was changed: {0}")]
+    AnnouncementSyncError(#[from] sqlx::Error)
+}
@@ -3,3 +3,9 @@ pub(crate) mod solve_sender;
 pub(crate) mod webhook;
 pub(crate) mod player_fetcher;
 pub(crate) mod team_fetcher;
+pub(crate) mod challenge_fetcher;
+pub(crate) mod scheduler;
+pub(crate) mod release_sender;
+pub(crate) mod scoreboard;
+pub(crate) mod config_reloader;
+pub(crate) mod alerter;
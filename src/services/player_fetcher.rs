@@ -1,29 +1,33 @@
-use std::sync::atomic::AtomicU32;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, oneshot};
-use tokio::time::interval;
-use url::Url;
+use tokio::time::{sleep, Instant};
 use uuid::Uuid;
 
+use crate::http_cache::{Conditional, ConditionalResponse};
 use crate::models::player::Player;
+use crate::sources::ScoreboardSource;
 
 const CACHE_DURATION: Duration = Duration::from_secs(15);
+// Upper bound for berg's Cache-Control, the events websocket keeps us fresh in between
+const MAX_CACHE_DURATION: Duration = Duration::from_secs(60 * 5);
 
 pub(crate) struct PlayerFetcherService {
     signal_tx: mpsc::UnboundedSender<SignalRequest>,
     failed_to_fetch_players_count: AtomicU32,
-    http_client: reqwest::Client,
-    berg_api_base: Url
+    is_populated: AtomicBool,
+    source: Arc<dyn ScoreboardSource>
 }
 impl PlayerFetcherService {
-    pub(crate) fn new(berg_api_base: Url, http_client: reqwest::Client) -> Arc<Self> {
+    pub(crate) fn new(source: Arc<dyn ScoreboardSource>) -> Arc<Self> {
         let (signal_request_tx, signal_request_rx) = mpsc::unbounded_channel();
         let instance = Arc::new(Self {
             signal_tx: signal_request_tx,
             failed_to_fetch_players_count: AtomicU32::default(),
-            http_client,
-            berg_api_base
+            is_populated: AtomicBool::default(),
+            source
         });
         tokio::spawn({
             let instance = instance.clone();
@@ -33,6 +37,12 @@ impl PlayerFetcherService {
         });
         instance
     }
+    pub(crate) fn failed_to_fetch_players_count(&self) -> u32 {
+        self.failed_to_fetch_players_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn is_populated(&self) -> bool {
+        self.is_populated.load(Ordering::SeqCst)
+    }
     pub(crate) async fn get_player(&self, player_id: Uuid) -> Option<Arc<Player>> {
         let (response_tx, response_rx) = oneshot::channel();
         // Handled in next line instead
@@ -42,21 +52,34 @@ impl PlayerFetcherService {
         }));
         response_rx.await.ok()
     }
+    pub(crate) fn upsert_player(&self, player: Player) {
+        let _ = self.signal_tx.send(SignalRequest::UpsertPlayer(player));
+    }
     async fn run(self: Arc<Self>, mut signal_rx: mpsc::UnboundedReceiver<SignalRequest>) {
-        let mut players = Vec::<Arc<Player>>::new();
-        let mut poll_interval = interval(CACHE_DURATION);
+        let mut players = HashMap::<Uuid, Arc<Player>>::new();
+        let poll_sleep = sleep(Duration::ZERO);
+        tokio::pin!(poll_sleep);
 
         loop {
             tokio::select! {
-                _ = poll_interval.tick() => {
-                    match self.fetch_players().await {
-                        Ok(new_players) => {
-                            players = new_players.into_iter().map(Arc::new).collect();
+                _ = &mut poll_sleep => {
+                    let mut next_poll_in = CACHE_DURATION;
+                    match self.source.fetch_players().await {
+                        Ok(ConditionalResponse { body, max_age }) => {
+                            if let Conditional::Modified(new_players) = body {
+                                players = new_players.into_iter().map(|player| (player.id, Arc::new(player))).collect();
+                            }
+                            self.is_populated.store(true, Ordering::SeqCst);
+                            if let Some(max_age) = max_age {
+                                next_poll_in = max_age.clamp(CACHE_DURATION, MAX_CACHE_DURATION);
+                            }
                         },
                         Err(error) => {
                             tracing::error!(?error, "failed to fetch players");
+                            self.failed_to_fetch_players_count.fetch_add(1, Ordering::SeqCst);
                         }
                     }
+                    poll_sleep.as_mut().reset(Instant::now() + next_poll_in);
                 },
                 maybe_message = signal_rx.recv() => {
                     let Some(message) = maybe_message else {
@@ -64,33 +87,25 @@ impl PlayerFetcherService {
                     };
                     match message {
                         SignalRequest::GetPlayer(request) => {
-                            let maybe_player = players.iter().filter(|player| player.id == request.player_id).next();
-                            if let Some(player) = maybe_player {
+                            if let Some(player) = players.get(&request.player_id) {
                                 let _ = request.response_tx.send(player.clone());
                             }
+                        },
+                        SignalRequest::UpsertPlayer(player) => {
+                            players.insert(player.id, Arc::new(player));
                         }
                     }
                 },
             };
         }
     }
-    async fn fetch_players(&self) -> Result<Vec<Player>, reqwest::Error> {
-        let players_url = self.berg_api_base.join("players").expect("players is hard-coded and known to be good");
-        self.http_client
-            .get(players_url)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<Vec<Player>>()
-            .await
-    }
 }
 
 enum SignalRequest {
-    GetPlayer(GetPlayerSignalRequest)
+    GetPlayer(GetPlayerSignalRequest),
+    UpsertPlayer(Player)
 }
 struct GetPlayerSignalRequest {
     player_id: Uuid,
     response_tx: oneshot::Sender<Arc<Player>>
 }
-
@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep};
+
+use crate::config::{IgnoreConfig, WebhookRole};
+use crate::models::challenge::Challenge;
+use crate::repository::Repository;
+use crate::services::challenge_fetcher::ChallengeRelease;
+use crate::services::webhook::WebhookService;
+use crate::shutdown::Shutdown;
+
+pub(crate) struct ReleaseSenderService {
+    failed_to_send_count: AtomicU32,
+    failed_to_process_count: AtomicU32,
+    ignore_config: RwLock<IgnoreConfig>,
+    webhook_service: Arc<WebhookService>,
+    repository: Arc<Repository>
+}
+impl ReleaseSenderService {
+    pub(crate) fn new(ignore_config: IgnoreConfig, webhook_service: Arc<WebhookService>, repository: Arc<Repository>) -> Arc<Self> {
+        Arc::new(Self {
+            failed_to_send_count: AtomicU32::default(),
+            failed_to_process_count: AtomicU32::default(),
+            ignore_config: RwLock::new(ignore_config),
+            webhook_service,
+            repository
+        })
+    }
+    pub(crate) fn replace_ignore_config(&self, ignore_config: IgnoreConfig) {
+        *self.ignore_config.write().expect("ignore config lock poisoned") = ignore_config;
+    }
+    pub(crate) fn start(self: Arc<Self>, receiver: mpsc::UnboundedReceiver<ChallengeRelease>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
+            let instance = self;
+            async move {
+                instance.run(receiver, shutdown).await
+            }
+        });
+    }
+    pub(crate) fn failed_to_send_count(&self) -> u32 {
+        self.failed_to_send_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_process_count(&self) -> u32 {
+        self.failed_to_process_count.load(Ordering::SeqCst)
+    }
+    async fn run(self: Arc<Self>, mut receiver: mpsc::UnboundedReceiver<ChallengeRelease>, shutdown: Shutdown) {
+        // On the very first run every existing challenge would look released, so they are only
+        // recorded. After that, challenges released while we were down are announced on startup.
+        let announce_seed = loop {
+            match self.repository.has_released_challenges().await {
+                Ok(has_released_challenges) => break has_released_challenges,
+                Err(error) => {
+                    tracing::error!(?error, "failed to check for released challenges");
+                    self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                    tokio::select! {
+                        _ = sleep(Duration::from_secs(10)) => {},
+                        _ = shutdown.triggered() => return
+                    }
+                }
+            }
+        };
+
+        loop {
+            let release = tokio::select! {
+                release = receiver.recv() => match release {
+                    Some(release) => release,
+                    None => return
+                },
+                _ = shutdown.triggered() => return
+            };
+            let challenge = &release.challenge;
+            let is_ignored = self.ignore_config.read().expect("ignore config lock poisoned").challenge_ignore_reason(&challenge.name, Some(challenge)).is_some();
+            let should_announce = !is_ignored && (announce_seed || !release.is_seed);
+
+            let mut retry_interval = interval(Duration::from_secs(10));
+            loop {
+                retry_interval.tick().await;
+                // Unreleased challenges are picked up again on the next start
+                if shutdown.is_triggered() {
+                    return;
+                }
+                match self.repository.has_released_challenge(&challenge.name).await {
+                    Ok(true) => break,
+                    Ok(false) => {},
+                    Err(error) => {
+                        tracing::error!(?error, "failed to check if challenge has been released");
+                        self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                        continue;
+                    }
+                }
+                if should_announce {
+                    tracing::debug!("announcing release of {}", &challenge.name);
+                    if let Err(error) = self.send_release_notification(challenge).await {
+                        tracing::error!(?error, "failed to send release notification");
+                        self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
+                        continue;
+                    }
+                }
+                if let Err(error) = self.repository.mark_challenge_as_released(&challenge.name, release.is_seed).await {
+                    tracing::error!(?error, "failed to mark challenge as released");
+                    self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                    continue;
+                }
+                break;
+            }
+        }
+    }
+    async fn send_release_notification(&self, challenge: &Challenge) -> Result<(), twilight_http::Error> {
+        let challenge_name = challenge.display_name();
+        let message = match challenge.details() {
+            Some(challenge_details) => format!("🆕 New challenge released: **{challenge_name}** ({challenge_details})"),
+            None => format!("🆕 New challenge released: **{challenge_name}**")
+        };
+        self.webhook_service.send_message(WebhookRole::ChallengeRelease, &message).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+use crate::config::{EventConfig, EventWindowConfig, WebhookRole};
+use crate::models::announcement::{Announcement, NewAnnouncement};
+use crate::repository::Repository;
+use crate::services::webhook::WebhookService;
+use crate::shutdown::Shutdown;
+
+// How late an announcement may be posted, anything older was missed while we were down
+const MAX_ANNOUNCEMENT_DELAY: TimeDelta = TimeDelta::minutes(5);
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(crate) struct SchedulerService {
+    failed_to_send_count: AtomicU32,
+    failed_to_process_count: AtomicU32,
+    wakeup: Notify,
+    window_config: EventWindowConfig,
+    webhook_service: Arc<WebhookService>,
+    repository: Arc<Repository>
+}
+impl SchedulerService {
+    pub(crate) fn new(window_config: EventWindowConfig, webhook_service: Arc<WebhookService>, repository: Arc<Repository>) -> Arc<Self> {
+        Arc::new(Self {
+            failed_to_send_count: AtomicU32::default(),
+            failed_to_process_count: AtomicU32::default(),
+            wakeup: Notify::new(),
+            window_config,
+            webhook_service,
+            repository
+        })
+    }
+    pub(crate) fn start(self: Arc<Self>, config_announcements: Vec<(String, NewAnnouncement)>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
+            let instance = self;
+            async move {
+                instance.sync_config_announcements(config_announcements, &shutdown).await;
+                if !shutdown.is_triggered() {
+                    instance.run(shutdown).await
+                }
+            }
+        });
+    }
+    pub(crate) fn failed_to_send_count(&self) -> u32 {
+        self.failed_to_send_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_process_count(&self) -> u32 {
+        self.failed_to_process_count.load(Ordering::SeqCst)
+    }
+    // Call after changing announcements in the repository so the next one is picked up
+    pub(crate) fn reschedule(&self) {
+        self.wakeup.notify_one();
+    }
+    // Retries until it succeeds or shutdown is triggered
+    async fn sync_config_announcements(&self, config_announcements: Vec<(String, NewAnnouncement)>, shutdown: &Shutdown) {
+        while let Err(error) = self.repository.replace_config_announcements(&config_announcements).await {
+            tracing::error!(?error, "failed to sync announcements from config");
+            self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+            tokio::select! {
+                _ = sleep(RETRY_INTERVAL) => {},
+                _ = shutdown.triggered() => return
+            }
+        }
+    }
+    async fn run(self: Arc<Self>, shutdown: Shutdown) {
+        loop {
+            let not_before = Utc::now() - MAX_ANNOUNCEMENT_DELAY;
+            let announcement = match self.repository.next_pending_announcement(not_before).await {
+                Ok(Some(announcement)) => announcement,
+                Ok(None) => {
+                    tokio::select! {
+                        _ = self.wakeup.notified() => continue,
+                        _ = shutdown.triggered() => return
+                    }
+                },
+                Err(error) => {
+                    tracing::error!(?error, "failed to fetch next announcement");
+                    self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                    tokio::select! {
+                        _ = sleep(RETRY_INTERVAL) => continue,
+                        _ = shutdown.triggered() => return
+                    }
+                }
+            };
+
+            let wait = (announcement.announce_at - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = sleep(wait) => {},
+                // Something changed, the next announcement might be a different one
+                _ = self.wakeup.notified() => continue,
+                _ = shutdown.triggered() => return
+            }
+
+            if let Err(error) = self.send_announcement(&announcement).await {
+                tracing::error!(?error, "failed to send announcement");
+                self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
+                tokio::select! {
+                    _ = sleep(RETRY_INTERVAL) => continue,
+                    _ = shutdown.triggered() => return
+                }
+            }
+            // Posting again would be worse than retrying this for a while
+            while let Err(error) = self.repository.mark_announcement_as_posted(announcement.id).await {
+                tracing::error!(?error, "failed to mark announcement as posted");
+                self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                tokio::select! {
+                    _ = sleep(RETRY_INTERVAL) => {},
+                    _ = shutdown.triggered() => {
+                        tracing::warn!(?announcement, "giving up on marking announcement as posted due to shutdown, it may be posted again");
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    async fn send_announcement(&self, announcement: &Announcement) -> Result<(), twilight_http::Error> {
+        let content = render_template(&announcement.content, &self.window_config);
+        let webhook = match announcement.webhook_id {
+            Some(webhook_id) => self.webhook_service.send_message_to(webhook_id, &content).await?,
+            None => self.webhook_service.send_message(announcement.role.clone(), &content).await?
+        };
+        if webhook.is_none() {
+            tracing::warn!(?announcement, "no webhook configured for announcement, skipping it");
+        }
+        Ok(())
+    }
+}
+
+// Supports {start_time} and {end_time}, rendered as Discord relative timestamps
+fn render_template(template: &str, window_config: &EventWindowConfig) -> String {
+    let discord_timestamp = |time: DateTime<Utc>| format!("<t:{}:R>", time.timestamp());
+    let mut content = template.to_string();
+    if let Some(start_time) = window_config.start_time {
+        content = content.replace("{start_time}", &discord_timestamp(start_time));
+    }
+    if let Some(end_time) = window_config.end_time {
+        content = content.replace("{end_time}", &discord_timestamp(end_time));
+    }
+    content
+}
+
+pub(crate) fn config_announcements(config: &EventConfig) -> Vec<(String, NewAnnouncement)> {
+    let mut announcements = window_announcements(&config.window);
+    for announcement in &config.announcements {
+        announcements.push((format!("config_{}", announcement.name), NewAnnouncement {
+            announce_at: announcement.at,
+            role: announcement.role.clone(),
+            webhook_id: announcement.webhook_id,
+            content: announcement.template.clone()
+        }));
+    }
+    announcements
+}
+
+fn window_announcements(window: &EventWindowConfig) -> Vec<(String, NewAnnouncement)> {
+    let announcement = |announce_at: DateTime<Utc>, content: String| NewAnnouncement {
+        announce_at,
+        role: WebhookRole::Announcement,
+        webhook_id: None,
+        content
+    };
+    let mut announcements = Vec::new();
+    if let (Some(start_time), true) = (window.start_time, window.window_announcements.start) {
+        announcements.push(("window_start".to_string(), announcement(start_time, "🚩 **The CTF has started!** Good luck and have fun".to_string())));
+    }
+    if let Some(end_time) = window.end_time {
+        for remaining_minutes in &window.window_announcements.remaining_minutes {
+            let remaining = match remaining_minutes {
+                60 => "1 hour".to_string(),
+                minutes if minutes % 60 == 0 => format!("{} hours", minutes / 60),
+                1 => "1 minute".to_string(),
+                minutes => format!("{minutes} minutes")
+            };
+            announcements.push((
+                format!("window_remaining_{remaining_minutes}"),
+                announcement(end_time - TimeDelta::minutes(i64::from(*remaining_minutes)), format!("⏰ **{remaining} left** of the CTF"))
+            ));
+        }
+        if window.window_announcements.end {
+            announcements.push(("window_end".to_string(), announcement(end_time, "🏁 **The CTF is over!** Thanks for playing".to_string())));
+        }
+    }
+    announcements
+}
@@ -0,0 +1,241 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use crate::config::{EventWindowConfig, ScoreboardConfig, WebhookRole};
+use crate::models::scoreboard::{RankedScoreboardEntry, ScoreboardEntry};
+use crate::repository::Repository;
+use crate::services::webhook::WebhookService;
+use crate::sources::ScoreboardSource;
+use crate::shutdown::Shutdown;
+
+// How late a one-off post may be, anything older was missed while we were down
+const MAX_POST_DELAY: TimeDelta = TimeDelta::minutes(5);
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+// Discord rejects longer messages, counted in UTF-16 code units to be safe with emoji
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+pub(crate) struct ScoreboardService {
+    failed_to_send_count: AtomicU32,
+    failed_to_process_count: AtomicU32,
+    scoreboard_config: ScoreboardConfig,
+    window_config: EventWindowConfig,
+    source: Arc<dyn ScoreboardSource>,
+    webhook_service: Arc<WebhookService>,
+    repository: Arc<Repository>
+}
+impl ScoreboardService {
+    pub(crate) fn new(scoreboard_config: ScoreboardConfig, window_config: EventWindowConfig, source: Arc<dyn ScoreboardSource>, webhook_service: Arc<WebhookService>, repository: Arc<Repository>) -> Arc<Self> {
+        Arc::new(Self {
+            failed_to_send_count: AtomicU32::default(),
+            failed_to_process_count: AtomicU32::default(),
+            scoreboard_config,
+            window_config,
+            source,
+            webhook_service,
+            repository
+        })
+    }
+    pub(crate) fn start(self: Arc<Self>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
+            let instance = self;
+            async move {
+                instance.run(shutdown).await
+            }
+        });
+    }
+    pub(crate) fn failed_to_send_count(&self) -> u32 {
+        self.failed_to_send_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_process_count(&self) -> u32 {
+        self.failed_to_process_count.load(Ordering::SeqCst)
+    }
+    // Only waits are interrupted by shutdown, a post that is underway gets its snapshot stored
+    async fn run(self: Arc<Self>, shutdown: Shutdown) {
+        loop {
+            // The last snapshot decides when the next post is due, so restarts don't post early
+            let latest_snapshot = match self.repository.latest_scoreboard_snapshot().await {
+                Ok(latest_snapshot) => latest_snapshot,
+                Err(error) => {
+                    tracing::error!(?error, "failed to fetch latest scoreboard snapshot");
+                    self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                    if self.wait_to_retry(&shutdown).await {
+                        continue;
+                    }
+                    return;
+                }
+            };
+            let Some(next_post_at) = self.next_post_at(latest_snapshot.as_ref().map(|snapshot| snapshot.taken_at), Utc::now()) else {
+                tracing::debug!("no scoreboard posts left to schedule");
+                return;
+            };
+            tokio::select! {
+                _ = sleep((next_post_at - Utc::now()).to_std().unwrap_or_default()) => {},
+                _ = shutdown.triggered() => return
+            }
+
+            let previous_entries = match &latest_snapshot {
+                Some(snapshot) => match self.repository.get_scoreboard_snapshot_entries(snapshot.id).await {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        tracing::error!(?error, "failed to fetch previous scoreboard snapshot");
+                        self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                        if self.wait_to_retry(&shutdown).await {
+                            continue;
+                        }
+                        return;
+                    }
+                },
+                None => Vec::new()
+            };
+            let entries = match self.source.fetch_scoreboard().await {
+                Ok(entries) => rank_entries(entries),
+                Err(error) => {
+                    tracing::error!(?error, "failed to fetch scoreboard");
+                    self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                    if self.wait_to_retry(&shutdown).await {
+                        continue;
+                    }
+                    return;
+                }
+            };
+
+            let message = render_scoreboard(&entries, &previous_entries, self.scoreboard_config.top);
+            if let Err(error) = self.webhook_service.send_message(WebhookRole::Scoreboard, &message).await {
+                tracing::error!(?error, "failed to send scoreboard");
+                self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
+                if self.wait_to_retry(&shutdown).await {
+                    continue;
+                }
+                return;
+            }
+            // Without the snapshot we would post again right away
+            while let Err(error) = self.repository.create_scoreboard_snapshot(&entries).await {
+                tracing::error!(?error, "failed to store scoreboard snapshot");
+                self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
+                if !self.wait_to_retry(&shutdown).await {
+                    tracing::warn!("giving up on storing scoreboard snapshot due to shutdown, it may be posted again");
+                    return;
+                }
+            }
+        }
+    }
+    // Returns false if shutdown was triggered while waiting
+    async fn wait_to_retry(&self, shutdown: &Shutdown) -> bool {
+        tokio::select! {
+            _ = sleep(RETRY_INTERVAL) => true,
+            _ = shutdown.triggered() => false
+        }
+    }
+    fn next_post_at(&self, last_posted_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut candidates = Vec::new();
+
+        if let Some(interval_minutes) = self.scoreboard_config.interval_minutes {
+            let interval = TimeDelta::minutes(i64::from(interval_minutes));
+            let mut next = last_posted_at.map(|last_posted_at| last_posted_at + interval).unwrap_or(now);
+            if let Some(start_time) = self.window_config.start_time {
+                next = next.max(start_time);
+            }
+            if self.window_config.end_time.is_none_or(|end_time| next < end_time) {
+                candidates.push(next);
+            }
+        }
+
+        let end_post = self.window_config.end_time.filter(|_| self.scoreboard_config.post_at_end);
+        let one_off_posts = self.scoreboard_config.post_at.iter().copied().chain(end_post);
+        for post_at in one_off_posts {
+            let is_posted = last_posted_at.is_some_and(|last_posted_at| last_posted_at >= post_at);
+            if !is_posted && post_at >= now - MAX_POST_DELAY {
+                candidates.push(post_at);
+            }
+        }
+
+        candidates.into_iter().min()
+    }
+}
+
+// Teams with equal scores share a rank
+fn rank_entries(mut entries: Vec<ScoreboardEntry>) -> Vec<RankedScoreboardEntry> {
+    entries.sort_by_key(|entry| Reverse(entry.score));
+    let mut ranked = Vec::<RankedScoreboardEntry>::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let rank = match ranked.last() {
+            Some(previous) if previous.score == entry.score => previous.rank,
+            _ => index as i32 + 1
+        };
+        ranked.push(RankedScoreboardEntry {
+            team_id: entry.team_id,
+            team_name: entry.team_name,
+            rank,
+            score: entry.score
+        });
+    }
+    ranked
+}
+
+fn render_scoreboard(entries: &[RankedScoreboardEntry], previous_entries: &[RankedScoreboardEntry], top: usize) -> String {
+    let previous_ranks = previous_entries.iter().map(|entry| (entry.team_id, entry.rank)).collect::<HashMap<Uuid, i32>>();
+    let mut message = "🏆 **Scoreboard**".to_string();
+    let shown_entries = &entries[..top.min(entries.len())];
+    for (index, entry) in shown_entries.iter().enumerate() {
+        let movement = match previous_ranks.get(&entry.team_id) {
+            // Nothing to compare against on the first snapshot
+            _ if previous_entries.is_empty() => String::new(),
+            None => "🆕 ".to_string(),
+            Some(previous_rank) if *previous_rank > entry.rank => format!("🔼{} ", previous_rank - entry.rank),
+            Some(previous_rank) if *previous_rank < entry.rank => format!("🔽{} ", entry.rank - previous_rank),
+            Some(_) => "➖ ".to_string()
+        };
+        let line = format!("\n`{:>3}.` {movement}**{}** - {} points", entry.rank, entry.team_name, entry.score);
+        // Leave room for the note about the teams that didn't fit
+        let omitted_note = format!("\n… and {} more", shown_entries.len() - index);
+        let is_last = index + 1 == shown_entries.len();
+        let reserved_length = if is_last { 0 } else { message_length(&omitted_note) };
+        if message_length(&message) + message_length(&line) + reserved_length > MAX_MESSAGE_LENGTH {
+            message.push_str(&omitted_note);
+            break;
+        }
+        message.push_str(&line);
+    }
+    message
+}
+
+fn message_length(message: &str) -> usize {
+    message.encode_utf16().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use crate::models::scoreboard::RankedScoreboardEntry;
+    use super::{message_length, render_scoreboard, MAX_MESSAGE_LENGTH};
+
+    fn entries(count: usize, team_name_length: usize) -> Vec<RankedScoreboardEntry> {
+        (0..count).map(|index| RankedScoreboardEntry {
+            team_id: Uuid::new_v4(),
+            team_name: "🚩".repeat(team_name_length),
+            rank: index as i32 + 1,
+            score: 1000 - index as i64
+        }).collect()
+    }
+
+    #[test]
+    fn short_scoreboard_is_not_truncated() {
+        let message = render_scoreboard(&entries(3, 10), &[], 10);
+        assert_eq!(message.lines().count(), 4);
+        assert!(!message.contains("more"));
+    }
+
+    #[test]
+    fn long_scoreboard_fits_in_one_message() {
+        let message = render_scoreboard(&entries(100, 64), &[], 100);
+        assert!(message_length(&message) <= MAX_MESSAGE_LENGTH);
+        let shown_count = message.lines().count() - 2;
+        assert!(message.ends_with(&format!("… and {} more", 100 - shown_count)));
+    }
+}
@@ -1,42 +1,82 @@
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use bytes::Bytes;
+use chrono::{DateTime, TimeDelta, Utc};
 use futures::StreamExt;
-use tokio::net::TcpStream;
-use tokio::time::interval;
-use tokio_tungstenite::tungstenite::{ClientRequestBuilder, Message as WebSocketMessage};
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use url::Url;
+use tokio::time::{interval, sleep, sleep_until, Instant};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+use crate::backoff::Backoff;
+use crate::config::{BergConnectionConfig, IngestMode};
 use crate::models::solve::Solve;
-use crate::models::websocket::WebSocketResponse;
-use crate::USER_AGENT;
+use crate::models::websocket::{Event, EventKind, EventParseError, WebSocketResponse, SUPPORTED_EVENT_VERSION};
+use crate::services::challenge_fetcher::ChallengeFetcherService;
+use crate::services::player_fetcher::PlayerFetcherService;
+use crate::services::team_fetcher::TeamFetcherService;
+use crate::shutdown::Shutdown;
+use crate::sources::{EventsConnectError, EventsWebSocket, ScoreboardSource, SourceError};
 use tokio::sync::mpsc;
 use futures::SinkExt;
 
+// Reseeding and polling ask for solves well before the last one we saw, as solves can show up late
+// and out of order. The sender skips those already sent, so the overlap only costs a bigger response
+const RESEED_OVERLAP: TimeDelta = TimeDelta::hours(1);
+// More distinct protocol changes than this means something is badly wrong anyway
+const MAX_PROTOCOL_CHANGES: usize = 64;
+
 pub(crate) struct SolveFetcherService {
     restart_count: AtomicU32,
     dropped_solves_count: AtomicU32,
-    berg_api_url: Url,
-    http_client: reqwest::Client,
+    consecutive_failures_count: AtomicU32,
+    failed_to_poll_count: AtomicU32,
+    event_counts: [AtomicU32; EventKind::ALL.len()],
+    malformed_events_count: AtomicU32,
+    latest_event_version: AtomicU32,
+    // Each change is only warned about the first time it's seen
+    protocol_changes: Mutex<HashSet<ProtocolChange>>,
+    is_connected: AtomicBool,
+    // The last poll of /solves worked, only ever set while the websocket isn't used
+    is_polling: AtomicBool,
+    disconnected_at: Mutex<Option<Instant>>,
+    last_solve_at: Mutex<Option<DateTime<Utc>>>,
+    berg_connection_config: BergConnectionConfig,
+    source: Arc<dyn ScoreboardSource>,
+    player_fetcher_service: Arc<PlayerFetcherService>,
+    team_fetcher_service: Arc<TeamFetcherService>,
+    challenge_fetcher_service: Arc<ChallengeFetcherService>,
     sender: mpsc::UnboundedSender<Solve>
 }
 impl SolveFetcherService {
-    pub(crate) fn new(berg_api_url: Url, http_client: reqwest::Client, sender: mpsc::UnboundedSender<Solve>) -> Arc<Self> {
-        let instance = Arc::new(Self {
+    pub(crate) fn new(source: Arc<dyn ScoreboardSource>, berg_connection_config: BergConnectionConfig, player_fetcher_service: Arc<PlayerFetcherService>, team_fetcher_service: Arc<TeamFetcherService>, challenge_fetcher_service: Arc<ChallengeFetcherService>, sender: mpsc::UnboundedSender<Solve>) -> Arc<Self> {
+        Arc::new(Self {
             restart_count: AtomicU32::default(),
             dropped_solves_count: AtomicU32::default(),
-            berg_api_url,
-            http_client,
+            consecutive_failures_count: AtomicU32::default(),
+            failed_to_poll_count: AtomicU32::default(),
+            event_counts: Default::default(),
+            malformed_events_count: AtomicU32::default(),
+            latest_event_version: AtomicU32::default(),
+            protocol_changes: Mutex::default(),
+            is_connected: AtomicBool::default(),
+            is_polling: AtomicBool::default(),
+            disconnected_at: Mutex::default(),
+            last_solve_at: Mutex::default(),
+            berg_connection_config,
+            source,
+            player_fetcher_service,
+            team_fetcher_service,
+            challenge_fetcher_service,
             sender
-        });
-        instance
+        })
     }
-    pub(crate) fn start(self: Arc<Self>) {
-        tokio::spawn({
+    pub(crate) fn start(self: Arc<Self>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
             let instance = self;
             async move {
-                instance.run_with_retries().await
+                instance.run_with_retries(shutdown).await
             }
         });
     }
@@ -46,158 +86,575 @@ impl SolveFetcherService {
     pub(crate) fn dropped_solves_count(&self) -> u32 {
         self.dropped_solves_count.load(Ordering::SeqCst)
     }
-    async fn run_with_retries(self: &Arc<Self>) {
-        // Max retry every 5s
-        let mut retry_interval = interval(Duration::from_secs(5));
+    pub(crate) fn consecutive_failures_count(&self) -> u32 {
+        self.consecutive_failures_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_poll_count(&self) -> u32 {
+        self.failed_to_poll_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn event_count(&self, kind: EventKind) -> u32 {
+        self.event_counts[kind as usize].load(Ordering::SeqCst)
+    }
+    pub(crate) fn malformed_events_count(&self) -> u32 {
+        self.malformed_events_count.load(Ordering::SeqCst)
+    }
+    // Newest event version berg has sent, 0 before the first event
+    pub(crate) fn latest_event_version(&self) -> u32 {
+        self.latest_event_version.load(Ordering::SeqCst)
+    }
+    // How many different signs of berg changing its protocol have been seen
+    pub(crate) fn protocol_changes_count(&self) -> usize {
+        self.protocol_changes.lock().expect("protocol_changes lock poisoned").len()
+    }
+    // The circuit breaker is open and polling isn't making up for it either
+    pub(crate) fn is_berg_down(&self) -> bool {
+        self.consecutive_failures_count() >= self.berg_connection_config.circuit_breaker_threshold && !self.is_polling()
+    }
+    pub(crate) fn is_connected(&self) -> bool {
+        self.is_connected.load(Ordering::SeqCst)
+    }
+    pub(crate) fn is_polling(&self) -> bool {
+        self.is_polling.load(Ordering::SeqCst)
+    }
+    // When the events websocket was last lost, None if it never connected
+    pub(crate) fn disconnected_at(&self) -> Option<Instant> {
+        *self.disconnected_at.lock().expect("disconnected_at lock poisoned")
+    }
+    // Sources without events are polled no matter what the config says
+    pub(crate) fn is_poll_only(&self) -> bool {
+        self.berg_connection_config.mode == IngestMode::Poll || !self.source.supports_events()
+    }
+    async fn run_with_retries(self: &Arc<Self>, shutdown: Shutdown) {
+        if self.is_poll_only() {
+            self.poll_solves(None, &shutdown).await;
+            return;
+        }
+        let max_delay = Duration::from_secs(self.berg_connection_config.reconnect_max_seconds);
+        let mut backoff = Backoff::new(Duration::from_secs(self.berg_connection_config.reconnect_initial_seconds), max_delay);
 
         loop {
-            retry_interval.tick().await;
-            if let Err(error) = self.run().await {
+            let result = self.run(&shutdown).await;
+            if self.is_connected.swap(false, Ordering::SeqCst) {
+                *self.disconnected_at.lock().expect("disconnected_at lock poisoned") = Some(Instant::now());
+                self.consecutive_failures_count.store(0, Ordering::SeqCst);
+                backoff.reset();
+            }
+            if shutdown.is_triggered() {
+                tracing::debug!("stopped listening for events due to shutdown");
+                return;
+            }
+            if let Err(error) = result {
                 tracing::error!(?error, "SolveFetcherService failed, restarting");
                 self.restart_count.fetch_add(1, Ordering::SeqCst);
             }
+
+            let consecutive_failures_count = self.consecutive_failures_count.fetch_add(1, Ordering::SeqCst) + 1;
+            let is_circuit_open = consecutive_failures_count >= self.berg_connection_config.circuit_breaker_threshold;
+            let should_poll = is_circuit_open && self.berg_connection_config.mode == IngestMode::Auto;
+            if consecutive_failures_count == self.berg_connection_config.circuit_breaker_threshold {
+                if should_poll {
+                    tracing::warn!(?consecutive_failures_count, "events websocket keeps failing, polling solves until it's back");
+                } else {
+                    tracing::error!(?consecutive_failures_count, "berg looks down, slowing down reconnects");
+                }
+            }
+
+            let delay = if is_circuit_open { max_delay } else { backoff.next_delay() };
+            tracing::debug!(?delay, "reconnecting to events websocket");
+            if should_poll {
+                self.poll_solves(Some(Instant::now() + delay), &shutdown).await;
+                if shutdown.is_triggered() {
+                    return;
+                }
+                continue;
+            }
+            tokio::select! {
+                _ = sleep(delay) => {},
+                _ = shutdown.triggered() => return
+            }
         }
     }
-    async fn run(self: &Arc<Self>) -> Result<(), SolveFetcherError> {
-        let websocket = {
-            let mut events_url = self.berg_api_url.join("events").expect("hard-coded path should always be fine to join to berg_api_url");
-            let websocket_scheme = match events_url.scheme() {
-                "http" => "ws",
-                "https" => "wss",
-                _ => return Err(SolveFetcherError::UnsupportedAPIScheme)
-            };
-            events_url.set_scheme(websocket_scheme).map_err(|_error| SolveFetcherError::UnsupportedAPIScheme)?;
-            let events_uri = events_url.as_str().parse::<http::Uri>().expect("this is a valid url via the url crate");
-
-            let request = ClientRequestBuilder::new(events_uri)
-                .with_header("User-Agent", USER_AGENT);
-            tokio_tungstenite::connect_async(request).await.map_err(SolveFetcherError::EventWebSocketConnectError)?.0
-        };
-        {
-            let seed_solves = self.fetch_solves().await.map_err(SolveFetcherError::FailedToFetchSeedData)?;
-            tracing::debug!("sending {} seeded solves", seed_solves.len());
-            for solve in seed_solves {
-                if self.sender.send(solve).is_err() {
-                    self.dropped_solves_count.fetch_add(1, Ordering::SeqCst);
+    // Polls until the deadline, or until shutdown without one
+    async fn poll_solves(&self, until: Option<Instant>, shutdown: &Shutdown) {
+        let poll_interval = Duration::from_secs(self.berg_connection_config.poll_interval_seconds);
+        loop {
+            match self.forward_new_solves().await {
+                Ok(solve_count) => {
+                    tracing::debug!(?solve_count, "polled solves");
+                    self.is_polling.store(true, Ordering::SeqCst);
+                    if self.is_poll_only() {
+                        self.consecutive_failures_count.store(0, Ordering::SeqCst);
+                    }
+                },
+                Err(error) => {
+                    tracing::error!(?error, "failed to poll solves");
+                    self.failed_to_poll_count.fetch_add(1, Ordering::SeqCst);
+                    self.is_polling.store(false, Ordering::SeqCst);
+                    // Without a websocket the failed polls are what trips the circuit breaker
+                    if self.is_poll_only() {
+                        self.consecutive_failures_count.fetch_add(1, Ordering::SeqCst);
+                    }
                 }
             }
-            tracing::debug!("sent seeded solves");
+
+            let next_poll_at = match until {
+                Some(until) => until.min(Instant::now() + poll_interval),
+                None => Instant::now() + poll_interval
+            };
+            tokio::select! {
+                _ = sleep_until(next_poll_at) => {},
+                _ = shutdown.triggered() => return
+            }
+            if until.is_some_and(|until| Instant::now() >= until) {
+                return;
+            }
+        }
+    }
+    // Hands the solves around and after the last one we saw to the sender, which skips those already
+    // in the repository
+    async fn forward_new_solves(&self) -> Result<usize, SourceError> {
+        let since = self.last_solve_at.lock().expect("last_solve_at lock poisoned").map(|last_solve_at| last_solve_at - RESEED_OVERLAP);
+        let solves = self.source.fetch_solves_since(since).await?;
+        let solve_count = solves.len();
+        for solve in solves {
+            self.record_solve(&solve);
+            if self.sender.send(solve).is_err() {
+                self.dropped_solves_count.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        Ok(solve_count)
+    }
+    // True the first time a change is seen. Berg could send any number of made up event types, so
+    // changes past MAX_PROTOCOL_CHANGES are neither remembered nor reported
+    fn record_protocol_change(&self, change: ProtocolChange) -> bool {
+        let mut protocol_changes = self.protocol_changes.lock().expect("protocol_changes lock poisoned");
+        if protocol_changes.len() >= MAX_PROTOCOL_CHANGES {
+            return false;
+        }
+        protocol_changes.insert(change)
+    }
+    fn record_solve(&self, solve: &Solve) {
+        if let Some(created_at) = solve.created_at {
+            let mut last_solve_at = self.last_solve_at.lock().expect("last_solve_at lock poisoned");
+            *last_solve_at = (*last_solve_at).max(Some(created_at));
+        }
+    }
+    async fn run(self: &Arc<Self>, shutdown: &Shutdown) -> Result<(), SolveFetcherError> {
+        let websocket = tokio::select! {
+            result = self.source.connect_events() => result.map_err(SolveFetcherError::EventWebSocketConnectError)?,
+            _ = shutdown.triggered() => return Ok(())
+        };
+        // Everything before the last solve we saw has already been handed to the sender
+        let seed_solve_count = self.forward_new_solves().await.map_err(SolveFetcherError::FailedToFetchSeedData)?;
+        tracing::debug!(?seed_solve_count, "sent seeded solves");
+        self.is_connected.store(true, Ordering::SeqCst);
+        if self.is_polling.swap(false, Ordering::SeqCst) {
+            tracing::info!("events websocket is back, stopped polling solves");
         }
         
         let (_message_tx, mut message_rx) = {
             let (message_sender_tx, message_sender_rx) = mpsc::unbounded_channel();
             let (message_receiver_tx, message_receiver_rx) = mpsc::unbounded_channel();
-            tokio::spawn(Self::handle_websocket(websocket, message_sender_rx, message_receiver_tx));
+            shutdown.spawn({
+                let shutdown = shutdown.clone();
+                async move {
+                    match Self::handle_websocket(websocket, message_sender_rx, message_receiver_tx, shutdown, KeepAlive::default()).await {
+                        WebSocketEnd::Closed(Some((code, reason))) => tracing::error!(%code, ?reason, "events websocket closed by berg"),
+                        WebSocketEnd::Closed(None) => tracing::error!("events websocket closed without a close frame"),
+                        WebSocketEnd::PongTimeout => tracing::error!("events websocket stopped responding"),
+                        WebSocketEnd::Error(error) => tracing::error!(?error, "events websocket failed"),
+                        WebSocketEnd::ReceiverGone | WebSocketEnd::Shutdown => tracing::debug!("stopped reading events websocket")
+                    }
+                }
+            });
 
             (message_sender_tx, message_receiver_rx)
         };
 
         while let Some(raw_message) = message_rx.recv().await {
-            let message = match serde_json::from_str::<WebSocketResponse>(&raw_message) {
-                Ok(message) => message,
+            let event = match Event::parse(&raw_message) {
+                Ok(event) => event,
                 Err(error) => {
-                    tracing::warn!(?error, "oopsie");
+                    self.malformed_events_count.fetch_add(1, Ordering::SeqCst);
+                    let change = match &error {
+                        EventParseError::InvalidEnvelope(_) => ProtocolChange::MalformedEnvelope,
+                        EventParseError::InvalidMessage(kind, _) => ProtocolChange::MalformedEvent(*kind)
+                    };
+                    if self.record_protocol_change(change) {
+                        tracing::warn!(?error, ?raw_message, "failed to parse event from berg, its protocol may have changed");
+                    } else {
+                        tracing::debug!(?error, "failed to parse event from berg");
+                    }
                     continue;
                 }
             };
+            self.event_counts[event.response.kind() as usize].fetch_add(1, Ordering::SeqCst);
+            self.latest_event_version.fetch_max(event.version, Ordering::SeqCst);
+            if event.version > SUPPORTED_EVENT_VERSION && self.record_protocol_change(ProtocolChange::NewerVersion(event.version)) {
+                tracing::warn!(version = event.version, supported_version = SUPPORTED_EVENT_VERSION, "berg is sending a newer event version, parsing it as best as possible");
+            }
 
-            let WebSocketResponse::Solve(solve) = message else {
-                continue;
-            };
-            tracing::debug!(?solve, "got solve from events ws");
-            if let Err(error) = self.sender.send(solve) {
-                self.dropped_solves_count.fetch_add(1, Ordering::SeqCst);
-                tracing::error!(?error, "failed to send solve to subscriber");
+            match event.response {
+                WebSocketResponse::Solve(solve) => {
+                    tracing::debug!(?solve, "got solve from events ws");
+                    self.record_solve(&solve);
+                    if let Err(error) = self.sender.send(solve) {
+                        self.dropped_solves_count.fetch_add(1, Ordering::SeqCst);
+                        tracing::error!(?error, "failed to send solve to subscriber");
+                    }
+                },
+                WebSocketResponse::PlayerCreate(player) | WebSocketResponse::PlayerUpdate(player) => {
+                    tracing::debug!(?player, "got player from events ws");
+                    self.player_fetcher_service.upsert_player(player);
+                },
+                WebSocketResponse::TeamCreate(team) | WebSocketResponse::TeamUpdate(team) => {
+                    tracing::debug!(?team, "got team from events ws");
+                    self.team_fetcher_service.upsert_team(team);
+                },
+                WebSocketResponse::ChallengeCreate(challenge) | WebSocketResponse::ChallengeUpdate(challenge) => {
+                    tracing::debug!(?challenge, "got challenge from events ws");
+                    self.challenge_fetcher_service.upsert_challenge(challenge);
+                },
+                WebSocketResponse::Unknown(event_type) => {
+                    if self.record_protocol_change(ProtocolChange::UnknownEventType(event_type.clone())) {
+                        tracing::warn!(?event_type, "ignoring unknown event type from berg");
+                    } else {
+                        tracing::debug!(?event_type, "ignoring unknown event type from berg");
+                    }
+                }
             }
         }
         Err(SolveFetcherError::EventWebSocketDisconnected)
 
     }
-    async fn handle_websocket(websocket: WebSocketStream<MaybeTlsStream<TcpStream>>, mut message_rx: mpsc::UnboundedReceiver<String>, message_tx: mpsc::UnboundedSender<String>) {
+    // Frames are reassembled by tungstenite, so only whole messages show up here
+    async fn handle_websocket(websocket: EventsWebSocket, mut message_rx: mpsc::UnboundedReceiver<String>, message_tx: mpsc::UnboundedSender<String>, shutdown: Shutdown, keep_alive: KeepAlive) -> WebSocketEnd {
         let (mut write, mut read) = websocket.split();
 
-        let mut ping_interval = interval(Duration::from_secs(10));
+        let mut ping_interval = interval(keep_alive.ping_interval);
+        // Anything berg sends proves the connection is alive, not just pongs
+        let mut last_received_at = Instant::now();
 
         loop {
-            // TODO: This does not implement chunked responses. They *shouldn't* be produced by
-            // berg so I don't really care.
             tokio::select! {
-                _ = message_tx.closed() => break,
+                _ = message_tx.closed() => return WebSocketEnd::ReceiverGone,
+                _ = shutdown.triggered() => {
+                    let close_frame = CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "shutting down".into()
+                    };
+                    if let Err(error) = write.send(WebSocketMessage::Close(Some(close_frame))).await {
+                        tracing::warn!(?error, "failed to close events websocket");
+                    }
+                    return WebSocketEnd::Shutdown;
+                },
                 to_send = message_rx.recv() => {
                     let Some(to_send) = to_send else {
-                        break;
+                        return WebSocketEnd::ReceiverGone;
                     };
                     if let Err(error) = write.send(WebSocketMessage::Text(to_send.into())).await {
-                        tracing::error!(?error, "failed to send text to events websocket");
-                        break;
+                        return WebSocketEnd::Error(error);
                     }
                 },
                 _ = ping_interval.tick() => {
+                    if last_received_at.elapsed() >= keep_alive.pong_timeout {
+                        return WebSocketEnd::PongTimeout;
+                    }
                     let payload = Bytes::from_static(b"hi");
                     if let Err(error) = write.send(WebSocketMessage::Ping(payload)).await {
-                        tracing::error!(?error, "failed to ping events websocket");
-                        break;
+                        return WebSocketEnd::Error(error);
                     }
                 },
                 raw_message = read.next() => {
                     let raw_message = match raw_message {
                         Some(Ok(raw_message)) => raw_message,
-                        Some(Err(error)) => {
-                            tracing::error!(?error, "failed to receive from events websocket");
-                            break;
-                        },
-                        None => {
-                            tracing::error!("disconnected from events websocket");
-                            break;
-                        }
+                        Some(Err(error)) => return WebSocketEnd::Error(error),
+                        None => return WebSocketEnd::Closed(None)
                     };
-                    match raw_message {
-                        WebSocketMessage::Text(raw_data) => {
-                            let data = raw_data.to_string();
-                            tracing::debug!(?data, "raw text from events websocket");
-                            if message_tx.send(data).is_err() {
-                                break;
+                    last_received_at = Instant::now();
+                    let data = match raw_message {
+                        WebSocketMessage::Text(raw_data) => raw_data.to_string(),
+                        // Some proxies turn text into binary frames, the payload is still JSON
+                        WebSocketMessage::Binary(raw_data) => match String::from_utf8(raw_data.to_vec()) {
+                            Ok(data) => data,
+                            Err(error) => {
+                                tracing::warn!(?error, "ignoring binary message that isn't utf-8 from events websocket");
+                                continue;
                             }
                         },
-                        WebSocketMessage::Close(_) => {
-                            tracing::error!("disconnected from events websocket");
-                            break;
+                        WebSocketMessage::Close(close_frame) => {
+                            return WebSocketEnd::Closed(close_frame.map(|close_frame| (close_frame.code, close_frame.reason.to_string())));
                         },
-                        WebSocketMessage::Ping(message) => {
-                            if let Err(error) = write.send(WebSocketMessage::Pong(message)).await {
-                                tracing::error!(?error, "failed to send pong events websocket");
-                                break;
-                            }
-                        },
-                        WebSocketMessage::Pong(..) => {},
-                        _ => todo!()
+                        // tungstenite answers pings by itself
+                        WebSocketMessage::Ping(_) | WebSocketMessage::Pong(_) => continue,
+                        // Only produced when writing raw frames, never when reading
+                        WebSocketMessage::Frame(_) => continue
+                    };
+                    tracing::debug!(?data, "raw message from events websocket");
+                    if message_tx.send(data).is_err() {
+                        return WebSocketEnd::ReceiverGone;
                     }
                 }
             }
         }
     }
-    async fn fetch_solves(&self) -> Result<Vec<Solve>, reqwest::Error> {
-        let solves_url = self.berg_api_url.join("solves").expect("hard-coded path should always be fine to join to berg_api_url");
-        let solves = self.http_client.get(solves_url)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<Vec<Solve>>()
-            .await?;
-        Ok(solves)
+    pub(crate) async fn fetch_solves(&self) -> Result<Vec<Solve>, SourceError> {
+        self.source.fetch_solves_since(None).await
     }
 }
 
+// Signs that berg's events no longer match what Dal was written against
+#[derive(PartialEq, Eq, Hash)]
+enum ProtocolChange {
+    UnknownEventType(String),
+    MalformedEnvelope,
+    MalformedEvent(EventKind),
+    NewerVersion(u32)
+}
+
+#[derive(Clone, Copy)]
+struct KeepAlive {
+    ping_interval: Duration,
+    // Without anything received for this long the connection is considered dead
+    pong_timeout: Duration
+}
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(10),
+            pong_timeout: Duration::from_secs(30)
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WebSocketEnd {
+    // Close code and reason, if berg sent a close frame
+    Closed(Option<(CloseCode, String)>),
+    PongTimeout,
+    Error(tokio_tungstenite::tungstenite::Error),
+    ReceiverGone,
+    Shutdown
+}
+
 #[derive(thiserror::Error, Debug)]
 enum SolveFetcherError {
-    #[error("unsupported api scheme")]
-    UnsupportedAPIScheme,
     #[error("websocket connect error")]
-    EventWebSocketConnectError(tokio_tungstenite::tungstenite::Error),
+    EventWebSocketConnectError(EventsConnectError),
     #[error("failed to fetch seed data")]
-    FailedToFetchSeedData(reqwest::Error),
-    #[error("failed to parse message from events websocket")]
-    EventMessageParseError(serde_json::Error),
+    FailedToFetchSeedData(SourceError),
     #[error("event websocket disconnected")]
     EventWebSocketDisconnected
 }
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::time::Duration;
+    use bytes::Bytes;
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::task::JoinHandle;
+    use tokio::time::timeout;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::{CloseCode, Data, OpCode};
+    use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+    use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+    use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+    use tokio_tungstenite::WebSocketStream;
+    use crate::shutdown::Shutdown;
+    use super::{KeepAlive, SolveFetcherService, WebSocketEnd};
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    struct Connection {
+        messages: mpsc::UnboundedReceiver<String>,
+        // Dropping this would end the handler with ReceiverGone
+        _outgoing: mpsc::UnboundedSender<String>,
+        handler: JoinHandle<WebSocketEnd>,
+        shutdown: Shutdown
+    }
+    impl Connection {
+        async fn next_message(&mut self) -> String {
+            timeout(TEST_TIMEOUT, self.messages.recv()).await
+                .expect("handler should forward a message in time")
+                .expect("handler should still be running")
+        }
+        async fn end(self) -> WebSocketEnd {
+            timeout(TEST_TIMEOUT, self.handler).await
+                .expect("handler should stop in time")
+                .expect("handler shouldn't panic")
+        }
+    }
+
+    // Runs script against the handler over a real websocket on localhost
+    async fn connect<F, Fut>(keep_alive: KeepAlive, script: F) -> Connection
+    where
+        F: FnOnce(WebSocketStream<TcpStream>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            script(websocket).await;
+        });
+        let (websocket, _) = tokio_tungstenite::connect_async(format!("ws://{address}")).await.unwrap();
+
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+        let (message_tx, message_rx) = mpsc::unbounded_channel();
+        let shutdown = Shutdown::default();
+        let handler = tokio::spawn(SolveFetcherService::handle_websocket(websocket, outgoing_rx, message_tx, shutdown.clone(), keep_alive));
+        Connection {
+            messages: message_rx,
+            _outgoing: outgoing_tx,
+            handler,
+            shutdown
+        }
+    }
+
+    async fn close(websocket: &mut WebSocketStream<TcpStream>) {
+        let _ = websocket.send(WebSocketMessage::Close(Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: "".into()
+        }))).await;
+        // Wait for the handshake so the client sees the close before the socket goes away
+        while let Some(Ok(_)) = websocket.next().await {}
+    }
+
+    #[tokio::test]
+    async fn forwards_text_messages() {
+        let mut connection = connect(KeepAlive::default(), |mut websocket| async move {
+            websocket.send(WebSocketMessage::Text(r#"{"type":"solve"}"#.into())).await.unwrap();
+            close(&mut websocket).await;
+        }).await;
+
+        assert_eq!(connection.next_message().await, r#"{"type":"solve"}"#);
+        assert!(matches!(connection.end().await, WebSocketEnd::Closed(Some((CloseCode::Normal, _)))));
+    }
+
+    #[tokio::test]
+    async fn forwards_binary_json() {
+        let mut connection = connect(KeepAlive::default(), |mut websocket| async move {
+            websocket.send(WebSocketMessage::Binary(Bytes::from_static(br#"{"type":"solve"}"#))).await.unwrap();
+            close(&mut websocket).await;
+        }).await;
+
+        assert_eq!(connection.next_message().await, r#"{"type":"solve"}"#);
+        assert!(matches!(connection.end().await, WebSocketEnd::Closed(Some(_))));
+    }
+
+    #[tokio::test]
+    async fn skips_binary_that_isnt_utf8() {
+        let mut connection = connect(KeepAlive::default(), |mut websocket| async move {
+            websocket.send(WebSocketMessage::Binary(Bytes::from_static(&[0xff, 0xfe]))).await.unwrap();
+            websocket.send(WebSocketMessage::Text("after".into())).await.unwrap();
+            close(&mut websocket).await;
+        }).await;
+
+        assert_eq!(connection.next_message().await, "after");
+        assert!(matches!(connection.end().await, WebSocketEnd::Closed(Some(_))));
+    }
+
+    #[tokio::test]
+    async fn reassembles_fragmented_messages() {
+        let mut connection = connect(KeepAlive::default(), |mut websocket| async move {
+            let first = Frame::message(Bytes::from_static(br#"{"type":"#), OpCode::Data(Data::Text), false);
+            let middle = Frame::message(Bytes::from_static(br#""sol"#), OpCode::Data(Data::Continue), false);
+            let last = Frame::message(Bytes::from_static(br#"ve"}"#), OpCode::Data(Data::Continue), true);
+            for frame in [first, middle, last] {
+                websocket.send(WebSocketMessage::Frame(frame)).await.unwrap();
+            }
+            close(&mut websocket).await;
+        }).await;
+
+        assert_eq!(connection.next_message().await, r#"{"type":"solve"}"#);
+        assert!(matches!(connection.end().await, WebSocketEnd::Closed(Some(_))));
+    }
+
+    #[tokio::test]
+    async fn answers_pings() {
+        let (pong_tx, mut pong_rx) = mpsc::unbounded_channel();
+        let connection = connect(KeepAlive::default(), |mut websocket| async move {
+            websocket.send(WebSocketMessage::Ping(Bytes::from_static(b"are you there"))).await.unwrap();
+            while let Some(Ok(message)) = websocket.next().await {
+                if let WebSocketMessage::Pong(payload) = message
+                    && payload == b"are you there".as_slice() {
+                    let _ = pong_tx.send(());
+                    break;
+                }
+            }
+            close(&mut websocket).await;
+        }).await;
+
+        timeout(TEST_TIMEOUT, pong_rx.recv()).await
+            .expect("server should get a pong in time")
+            .expect("server should send on pong");
+        assert!(matches!(connection.end().await, WebSocketEnd::Closed(Some(_))));
+    }
+
+    #[tokio::test]
+    async fn reports_close_code_and_reason() {
+        let connection = connect(KeepAlive::default(), |mut websocket| async move {
+            websocket.send(WebSocketMessage::Close(Some(CloseFrame {
+                code: CloseCode::Library(4000),
+                reason: "maintenance".into()
+            }))).await.unwrap();
+            while let Some(Ok(_)) = websocket.next().await {}
+        }).await;
+
+        match connection.end().await {
+            WebSocketEnd::Closed(Some((code, reason))) => {
+                assert_eq!(code, CloseCode::Library(4000));
+                assert_eq!(reason, "maintenance");
+            },
+            end => panic!("expected a close with a reason, got {end:?}")
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_connections_dropped_without_close() {
+        let connection = connect(KeepAlive::default(), |websocket| async move {
+            drop(websocket);
+        }).await;
+
+        assert!(matches!(connection.end().await, WebSocketEnd::Error(_) | WebSocketEnd::Closed(None)));
+    }
+
+    #[tokio::test]
+    async fn times_out_when_server_goes_quiet() {
+        let keep_alive = KeepAlive {
+            ping_interval: Duration::from_millis(50),
+            pong_timeout: Duration::from_millis(200)
+        };
+        // Never reading means pings are never answered
+        let connection = connect(keep_alive, |websocket| async move {
+            let _websocket = websocket;
+            std::future::pending::<()>().await;
+        }).await;
+
+        assert!(matches!(connection.end().await, WebSocketEnd::PongTimeout));
+    }
+
+    #[tokio::test]
+    async fn closes_on_shutdown() {
+        let (close_tx, mut close_rx) = mpsc::unbounded_channel();
+        let connection = connect(KeepAlive::default(), |mut websocket| async move {
+            while let Some(Ok(message)) = websocket.next().await {
+                if let WebSocketMessage::Close(close_frame) = message {
+                    let _ = close_tx.send(close_frame);
+                    break;
+                }
+            }
+        }).await;
+        connection.shutdown.trigger();
+
+        assert!(matches!(connection.end().await, WebSocketEnd::Shutdown));
+        let close_frame = timeout(TEST_TIMEOUT, close_rx.recv()).await
+            .expect("server should get a close frame in time")
+            .expect("server should send on close")
+            .expect("close frame should have a code");
+        assert_eq!(close_frame.code, CloseCode::Away);
+    }
+}
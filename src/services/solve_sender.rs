@@ -1,92 +1,148 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
-use crate::config::WebhookRole;
+use crate::config::{EventWindowConfig, IgnoreConfig, WebhookConfig, WebhookRole};
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
 use crate::models::solve::Solve;
-use crate::repository::Repository;
+use crate::models::team::Team;
+use crate::repository::{Repository, SolveRecord};
+use crate::services::challenge_fetcher::ChallengeFetcherService;
 use crate::services::player_fetcher::PlayerFetcherService;
 use crate::services::team_fetcher::TeamFetcherService;
 use crate::services::webhook::WebhookService;
+use crate::shutdown::Shutdown;
+use chrono::Utc;
 use tokio::sync::mpsc;
-use tokio::time::interval;
-use twilight_model::channel::message::AllowedMentions;
+use tokio::time::{interval, Instant};
+use uuid::Uuid;
+
+// How long after starting solves wait for the caches they need. A source that keeps failing one of
+// them shouldn't hold back every announcement
+const CACHE_WAIT_LIMIT: Duration = Duration::from_secs(60 * 2);
 
 pub(crate) struct SolveSenderService {
     failed_to_send_count: AtomicU32,
     failed_to_process_count: AtomicU32,
+    ignored_solves_count: AtomicU32,
+    // Solves handed to a challenge sender that haven't been delivered, ignored or given up on yet
+    pending_solves: Mutex<HashMap<(String, Uuid), Instant>>,
+    started_at: Instant,
+    has_warned_about_caches: AtomicBool,
+    // Notifications are printed instead of posted and nothing is written to the repository
+    dry_run: bool,
+    ignore_config: RwLock<IgnoreConfig>,
+    window_config: EventWindowConfig,
     webhook_service: Arc<WebhookService>,
     player_fetcher_service: Arc<PlayerFetcherService>,
     team_fetcher_service: Arc<TeamFetcherService>,
-    repository: Arc<Repository>,
-    twilight_client: Arc<twilight_http::Client>
+    challenge_fetcher_service: Arc<ChallengeFetcherService>,
+    repository: Arc<Repository>
 }
 impl SolveSenderService {
-    pub(crate) fn new(webhook_service: Arc<WebhookService>, player_fetcher_service: Arc<PlayerFetcherService>, team_fetcher_service: Arc<TeamFetcherService>, repository: Arc<Repository>) -> Arc<SolveSenderService> {
-        let twilight_client = twilight_http::Client::builder().default_allowed_mentions(AllowedMentions::default()).build();
-        let twilight_client = Arc::new(twilight_client);
-        let instance = Arc::new(Self {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(dry_run: bool, ignore_config: IgnoreConfig, window_config: EventWindowConfig, webhook_service: Arc<WebhookService>, player_fetcher_service: Arc<PlayerFetcherService>, team_fetcher_service: Arc<TeamFetcherService>, challenge_fetcher_service: Arc<ChallengeFetcherService>, repository: Arc<Repository>) -> Arc<SolveSenderService> {
+        Arc::new(Self {
             failed_to_send_count: AtomicU32::default(),
             failed_to_process_count: AtomicU32::default(),
+            ignored_solves_count: AtomicU32::default(),
+            pending_solves: Mutex::default(),
+            started_at: Instant::now(),
+            has_warned_about_caches: AtomicBool::default(),
+            dry_run,
+            ignore_config: RwLock::new(ignore_config),
+            window_config,
             webhook_service,
             player_fetcher_service,
             team_fetcher_service,
-            repository,
-            twilight_client
-        });
-        instance
+            challenge_fetcher_service,
+            repository
+        })
+    }
+    pub(crate) fn failed_to_send_count(&self) -> u32 {
+        self.failed_to_send_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn failed_to_process_count(&self) -> u32 {
+        self.failed_to_process_count.load(Ordering::SeqCst)
     }
-    pub(crate) fn start(self: Arc<Self>, receiver: mpsc::UnboundedReceiver<Solve>) {
-        tokio::spawn({
+    pub(crate) fn ignored_solves_count(&self) -> u32 {
+        self.ignored_solves_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn oldest_pending_solve_age(&self) -> Option<Duration> {
+        self.pending_solves.lock().expect("pending_solves lock poisoned").values().min().map(Instant::elapsed)
+    }
+    // Solves already being processed keep the old rules
+    pub(crate) fn replace_ignore_config(&self, ignore_config: IgnoreConfig) {
+        *self.ignore_config.write().expect("ignore config lock poisoned") = ignore_config;
+    }
+    pub(crate) fn start(self: Arc<Self>, receiver: mpsc::UnboundedReceiver<Solve>, shutdown: Shutdown) {
+        shutdown.clone().spawn({
             let instance = self;
             async move {
-                instance.run(receiver).await
+                instance.run(receiver, shutdown).await
             }
         });
     }
-    async fn run(self: Arc<Self>, mut receiver: mpsc::UnboundedReceiver<Solve>) {
+    async fn run(self: Arc<Self>, mut receiver: mpsc::UnboundedReceiver<Solve>, shutdown: Shutdown) {
         let mut challenge_to_solve_channels = HashMap::<String, mpsc::UnboundedSender<Solve>>::new();
         loop {
-            let solve = receiver.recv().await.expect("solve channel closed");
-            
-            let maybe_solve_channel = challenge_to_solve_channels.get(&solve.challenge_name);
-            match maybe_solve_channel {
-                Some(solve_channel) => {
-                    if let Err(mpsc::error::SendError(solve)) = solve_channel.send(solve) {
-                        let (solve_channel_tx, solve_channel_rx) = mpsc::unbounded_channel();
-                        tokio::spawn({
-                            let instance = self.clone();
-                            async move {
-                                instance.challenge_sender(solve_channel_rx).await
-                            }
-                        });
-                        challenge_to_solve_channels.insert(solve.challenge_name.clone(), solve_channel_tx.clone());
-                        if solve_channel_tx.send(solve).is_err() {
-                            self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
-                            tracing::error!("failed to send solve due to solve_channel");
-                            continue;
-                        }
+            let solve = tokio::select! {
+                solve = receiver.recv() => solve.expect("solve channel closed"),
+                _ = shutdown.triggered() => {
+                    // Hand out whatever is already queued, dropping the channels afterwards lets the
+                    // challenge senders finish their queues and exit
+                    while let Ok(solve) = receiver.try_recv() {
+                        self.dispatch(&mut challenge_to_solve_channels, solve, &shutdown);
                     }
-                },
-                None => {
+                    return;
+                }
+            };
+            self.dispatch(&mut challenge_to_solve_channels, solve, &shutdown);
+        }
+    }
+    fn dispatch(self: &Arc<Self>, challenge_to_solve_channels: &mut HashMap<String, mpsc::UnboundedSender<Solve>>, mut solve: Solve, shutdown: &Shutdown) {
+        // A solve without a timestamp happened no later than now, stamping it later (after waiting in a
+        // challenge queue or for retries) could move it past end_time
+        solve.created_at.get_or_insert_with(Utc::now);
+        self.pending_solves.lock().expect("pending_solves lock poisoned").entry((solve.challenge_name.clone(), solve.player_id)).or_insert_with(Instant::now);
+        let maybe_solve_channel = challenge_to_solve_channels.get(&solve.challenge_name);
+        match maybe_solve_channel {
+            Some(solve_channel) => {
+                if let Err(mpsc::error::SendError(solve)) = solve_channel.send(solve) {
                     let (solve_channel_tx, solve_channel_rx) = mpsc::unbounded_channel();
-                    tokio::spawn({
+                    shutdown.spawn({
                         let instance = self.clone();
+                        let shutdown = shutdown.clone();
                         async move {
-                            instance.challenge_sender(solve_channel_rx).await
+                            instance.challenge_sender(solve_channel_rx, shutdown).await
                         }
                     });
                     challenge_to_solve_channels.insert(solve.challenge_name.clone(), solve_channel_tx.clone());
                     if solve_channel_tx.send(solve).is_err() {
                         self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
                         tracing::error!("failed to send solve due to solve_channel");
-                        continue;
                     }
                 }
+            },
+            None => {
+                let (solve_channel_tx, solve_channel_rx) = mpsc::unbounded_channel();
+                shutdown.spawn({
+                    let instance = self.clone();
+                    let shutdown = shutdown.clone();
+                    async move {
+                        instance.challenge_sender(solve_channel_rx, shutdown).await
+                    }
+                });
+                challenge_to_solve_channels.insert(solve.challenge_name.clone(), solve_channel_tx.clone());
+                if solve_channel_tx.send(solve).is_err() {
+                    self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
+                    tracing::error!("failed to send solve due to solve_channel");
+                }
             }
         }
     }
-    async fn challenge_sender(self: Arc<Self>, mut receiver: mpsc::UnboundedReceiver<Solve>) {
+    async fn challenge_sender(self: Arc<Self>, mut receiver: mpsc::UnboundedReceiver<Solve>, shutdown: Shutdown) {
         // If any error happens during execution we refuse to give out any first bloods until next
         // restart - this is to avoid giving out false first bloods
         let mut has_been_solved = false;
@@ -102,64 +158,154 @@ impl SolveSenderService {
                     break;
                 }
             };
-            
+            if !self.wait_for_caches(&shutdown).await {
+                tracing::warn!("giving up on solve for {} due to shutdown", &solve.challenge_name);
+                self.pending_solves.lock().expect("pending_solves lock poisoned").remove(&(solve.challenge_name.clone(), solve.player_id));
+                continue;
+            }
+            let context = self.solve_context(&solve).await;
+
             let mut retry_interval = interval(Duration::from_secs(10));
             loop {
                 retry_interval.tick().await;
-                let is_first_blood = if has_been_solved {
-                    false
-                } else {
-                    match self.repository.has_been_solved(&solve.challenge_name).await {
-                        Ok(has_been_solved) => !has_been_solved,
-                        Err(error) => {
-                            tracing::error!(?error, "failed to check if challenge is solved");
-                            self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
-                            continue;
-                        }
-                    }
-                };
-
-                let has_sent_solve = match self.repository.has_sent_solve(&solve.challenge_name, &solve.player_id).await {
-                    Ok(has_sent_solve) => has_sent_solve,
-                    Err(error) => {
-                        tracing::error!(?error, "failed to check if player has submitted solve");
+                match self.process_solve(&solve, &context, has_been_solved).await {
+                    Ok(SolveOutcome::Announced { .. }) => {
+                        has_been_solved = true;
+                        break;
+                    },
+                    Ok(SolveOutcome::AlreadySent | SolveOutcome::Ignored(_)) => break,
+                    Err(ProcessSolveError::RepositoryError(error)) => {
+                        tracing::error!(?error, "failed to process solve");
                         self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
-                        continue;
+                    },
+                    Err(ProcessSolveError::NotificationSendError(error)) => {
+                        tracing::error!(?error, "failed to send solve notification");
+                        self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
                     }
-                };
-
-                if has_sent_solve {
+                }
+                // It will be seeded again on the next start
+                if shutdown.is_triggered() {
+                    tracing::warn!("giving up on solve for {} due to shutdown", &solve.challenge_name);
                     break;
                 }
-                tracing::debug!(?is_first_blood, ?has_been_solved, "sending notification for {}", &solve.challenge_name);
-                if let Err(error) = self.send_solve_notification(&solve, is_first_blood).await {
-                    tracing::error!(?error, "failed to send solve notification");
-                    self.failed_to_send_count.fetch_add(1, Ordering::SeqCst);
-                    continue;
+            }
+            self.pending_solves.lock().expect("pending_solves lock poisoned").remove(&(solve.challenge_name.clone(), solve.player_id));
+        }
+    }
+    // Before the first fetch a hidden challenge would be missing from the cache and count as visible,
+    // and ignore rules on players and teams wouldn't match. Players and teams are only waited for when
+    // there are such rules. Past CACHE_WAIT_LIMIT solves go ahead with whatever is cached. Returns false
+    // if shutdown was triggered while waiting
+    async fn wait_for_caches(&self, shutdown: &Shutdown) -> bool {
+        let mut check_interval = interval(Duration::from_secs(1));
+        loop {
+            let missing_caches = self.missing_caches();
+            if missing_caches.is_empty() {
+                return true;
+            }
+            if self.started_at.elapsed() >= CACHE_WAIT_LIMIT {
+                if !self.has_warned_about_caches.swap(true, Ordering::SeqCst) {
+                    tracing::warn!(?missing_caches, "caches are still empty, announcing solves without them, with no first bloods for unknown challenges and ignore rules on players and teams only matching once they are fetched");
                 }
-                if let Err(error) = self.repository.mark_challenge_as_solved(&solve.challenge_name, &solve.player_id).await {
-                    tracing::error!(?error, "failed to mark challenge as solved");
-                    self.failed_to_process_count.fetch_add(1, Ordering::SeqCst);
-                    continue;
-                };
-
-                has_been_solved = true;
-                break;
+                return true;
+            }
+            tokio::select! {
+                _ = check_interval.tick() => {},
+                _ = shutdown.triggered() => return false
             }
         }
     }
-    async fn send_solve_notification(&self, solve: &Solve, is_first_blood: bool) -> Result<(), NotificationSendError> {
-        let maybe_player = self.player_fetcher_service.get_player(solve.player_id.clone()).await;
-        
-        let player_name = match maybe_player {
+    fn missing_caches(&self) -> Vec<&'static str> {
+        let (needs_players, needs_teams) = {
+            let ignore_config = self.ignore_config.read().expect("ignore config lock poisoned");
+            (ignore_config.needs_players(), ignore_config.needs_teams())
+        };
+        [
+            ("challenges", true, self.challenge_fetcher_service.is_populated()),
+            ("players", needs_players, self.player_fetcher_service.is_populated()),
+            ("teams", needs_teams, self.team_fetcher_service.is_populated())
+        ].into_iter()
+            .filter(|(_, is_needed, is_populated)| *is_needed && !is_populated)
+            .map(|(cache, _, _)| cache)
+            .collect()
+    }
+    // Runs solves through the pipeline one by one without any retries, used by the replay command
+    pub(crate) async fn replay(&self, solves: Vec<Solve>) -> Vec<(Solve, Result<SolveOutcome, ProcessSolveError>)> {
+        let mut solved_challenges = HashSet::<String>::new();
+        let mut outcomes = Vec::with_capacity(solves.len());
+        for solve in solves {
+            let context = self.solve_context(&solve).await;
+            let has_been_solved = solved_challenges.contains(&solve.challenge_name);
+            let outcome = self.process_solve(&solve, &context, has_been_solved).await;
+            if let Ok(SolveOutcome::Announced { .. }) = outcome {
+                solved_challenges.insert(solve.challenge_name.clone());
+            }
+            outcomes.push((solve, outcome));
+        }
+        outcomes
+    }
+    async fn solve_context(&self, solve: &Solve) -> SolveContext {
+        let challenge = self.challenge_fetcher_service.get_challenge(solve.challenge_name.clone()).await;
+        let player = self.player_fetcher_service.get_player(solve.player_id).await;
+        let team = self.team_fetcher_service.get_players_team(solve.player_id).await;
+        // Only replayed solves can still lack a timestamp here, there is no telling when they happened so
+        // the window isn't checked for them
+        let solved_at = solve.created_at.unwrap_or_else(Utc::now);
+        let ignore_reason = solve.created_at.and_then(|created_at| self.window_config.outside_reason(created_at))
+            .or_else(|| self.ignore_config.read().expect("ignore config lock poisoned").ignore_reason(solve, challenge.as_deref(), player.as_deref(), team.as_deref()));
+        let record = SolveRecord {
+            challenge_name: solve.challenge_name.clone(),
+            player_id: solve.player_id,
+            solved_at,
+            player_name: player.as_ref().map(|player| player.name.clone()),
+            team_id: team.as_ref().map(|team| team.id),
+            team_name: team.as_ref().map(|team| team.name.clone()),
+            challenge_category: challenge.as_ref().and_then(|challenge| challenge.category.clone())
+        };
+        SolveContext {
+            // Without any fetched challenges there is no telling whether it is hidden
+            is_unknown_challenge: challenge.is_none() && !self.challenge_fetcher_service.is_populated(),
+            challenge,
+            player,
+            team,
+            ignore_reason,
+            record
+        }
+    }
+    async fn process_solve(&self, solve: &Solve, context: &SolveContext, has_been_solved: bool) -> Result<SolveOutcome, ProcessSolveError> {
+        if self.repository.has_sent_solve(&solve.challenge_name, &solve.player_id).await? {
+            return Ok(SolveOutcome::AlreadySent);
+        }
+        // Ignored solves are recorded for auditing but never count towards first blood
+        if let Some(ignore_reason) = context.ignore_reason {
+            tracing::debug!(?ignore_reason, "ignoring solve for {}", &solve.challenge_name);
+            if !self.dry_run {
+                self.repository.mark_solve_as_ignored(&context.record, ignore_reason).await?;
+            }
+            self.ignored_solves_count.fetch_add(1, Ordering::SeqCst);
+            return Ok(SolveOutcome::Ignored(ignore_reason));
+        }
+
+        // Hidden challenges are usually tests, never give out first bloods for them
+        let is_hidden = context.is_unknown_challenge || context.challenge.as_ref().is_some_and(|challenge| challenge.hidden);
+        let is_first_blood = !has_been_solved && !is_hidden && !self.repository.has_been_solved(&solve.challenge_name).await?;
+
+        tracing::debug!(?is_first_blood, ?has_been_solved, "sending notification for {}", &solve.challenge_name);
+        self.send_solve_notification(solve, context.challenge.as_deref(), context.player.as_deref(), context.team.as_deref(), is_first_blood).await?;
+        if !self.dry_run {
+            self.repository.mark_challenge_as_solved(&context.record, is_first_blood).await?;
+        }
+        Ok(SolveOutcome::Announced { is_first_blood })
+    }
+    async fn send_solve_notification(&self, solve: &Solve, challenge: Option<&Challenge>, player: Option<&Player>, team: Option<&Team>, is_first_blood: bool) -> Result<(), NotificationSendError> {
+        let player_name = match player {
             Some(player) => player.name.clone(),
             None => "Unknown player (blame cache)".to_string()
         };
         
-        let challenge_name = &solve.challenge_name;
+        let challenge_name = challenge.map(Challenge::display_name).unwrap_or(&solve.challenge_name);
 
-        let maybe_team = self.team_fetcher_service.get_players_team(solve.player_id.clone()).await;
-        let (first_blood_message, solve_message) = match maybe_team {
+        let (mut first_blood_message, solve_message) = match team {
             Some(team) => {
                 let team_name = &team.name;
                 let first_blood_message = format!("🩸 **{player_name}** from **{team_name}** solved **{challenge_name}**");
@@ -172,43 +318,59 @@ impl SolveSenderService {
                 (first_blood_message, solve_message)
             }
         };
+        if let Some(challenge_details) = challenge.and_then(Challenge::details) {
+            first_blood_message.push_str(&format!(" ({challenge_details})"));
+        }
 
         if is_first_blood {
-            let first_blood_webhook = self.webhook_service.get_webhook(WebhookRole::FirstBlood).await;
-            if let Some(first_blood_webhook) = &first_blood_webhook {
-                self.twilight_client
-                    .execute_webhook(first_blood_webhook.id, &first_blood_webhook.token)
-                    .content(&first_blood_message)
-                    .await
-                    .map_err(NotificationSendError::WebhookExecutionError)?;
-            }
+            let first_blood_webhook = self.deliver(WebhookRole::FirstBlood, &first_blood_message).await?;
             let is_first_blood_also_solve_webhook = first_blood_webhook.map(|config| config.roles.contains(&WebhookRole::Solve)).unwrap_or(false);
             if !is_first_blood_also_solve_webhook {
-                let solve_webhook = self.webhook_service.get_webhook(WebhookRole::Solve).await;
-                if let Some(solve_webhook) = solve_webhook {
-                    self.twilight_client
-                        .execute_webhook(solve_webhook.id, &solve_webhook.token)
-                        .content(&solve_message)
-                        .await
-                        .map_err(NotificationSendError::WebhookExecutionError)?;
-                }
+                self.deliver(WebhookRole::Solve, &solve_message).await?;
             }
         } else {
-            let solve_webhook = self.webhook_service.get_webhook(WebhookRole::Solve).await;
-            if let Some(solve_webhook) = solve_webhook {
-                self.twilight_client
-                    .execute_webhook(solve_webhook.id, &solve_webhook.token)
-                    .content(&solve_message)
-                    .await
-                    .map_err(NotificationSendError::WebhookExecutionError)?;
-            }
+            self.deliver(WebhookRole::Solve, &solve_message).await?;
         }
         Ok(())
     }
+    async fn deliver(&self, role: WebhookRole, content: &str) -> Result<Option<Arc<WebhookConfig>>, NotificationSendError> {
+        if self.dry_run {
+            println!("[dry run] {}: {content}", role.as_str());
+            return Ok(None);
+        }
+        self.webhook_service
+            .send_message(role, content)
+            .await
+            .map_err(NotificationSendError::WebhookExecutionError)
+    }
+}
+
+struct SolveContext {
+    challenge: Option<Arc<Challenge>>,
+    is_unknown_challenge: bool,
+    player: Option<Arc<Player>>,
+    team: Option<Arc<Team>>,
+    ignore_reason: Option<&'static str>,
+    record: SolveRecord
+}
+
+#[derive(Debug)]
+pub(crate) enum SolveOutcome {
+    AlreadySent,
+    Ignored(&'static str),
+    Announced { is_first_blood: bool }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum ProcessSolveError {
+    #[error("repository error")]
+    RepositoryError(#[from] sqlx::Error),
+    #[error("failed to send notification")]
+    NotificationSendError(#[from] NotificationSendError)
 }
 
 #[derive(thiserror::Error, Debug)]
-enum NotificationSendError {
+pub(crate) enum NotificationSendError {
     #[error("webhook execution error")]
     WebhookExecutionError(twilight_http::Error)
 }
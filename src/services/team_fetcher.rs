@@ -1,29 +1,33 @@
-use std::sync::atomic::AtomicU32;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, oneshot};
-use tokio::time::interval;
-use url::Url;
+use tokio::time::{sleep, Instant};
 use uuid::Uuid;
 
+use crate::http_cache::{Conditional, ConditionalResponse};
 use crate::models::team::Team;
+use crate::sources::ScoreboardSource;
 
 const CACHE_DURATION: Duration = Duration::from_secs(15);
+// Upper bound for berg's Cache-Control, the events websocket keeps us fresh in between
+const MAX_CACHE_DURATION: Duration = Duration::from_secs(60 * 5);
 
 pub(crate) struct TeamFetcherService {
     signal_tx: mpsc::UnboundedSender<SignalRequest>,
     failed_to_fetch_teams_count: AtomicU32,
-    http_client: reqwest::Client,
-    berg_api_base: Url
+    is_populated: AtomicBool,
+    source: Arc<dyn ScoreboardSource>
 }
 impl TeamFetcherService {
-    pub(crate) fn new(berg_api_base: Url, http_client: reqwest::Client) -> Arc<Self> {
+    pub(crate) fn new(source: Arc<dyn ScoreboardSource>) -> Arc<Self> {
         let (signal_request_tx, signal_request_rx) = mpsc::unbounded_channel();
         let instance = Arc::new(Self {
             signal_tx: signal_request_tx,
             failed_to_fetch_teams_count: AtomicU32::default(),
-            http_client,
-            berg_api_base
+            is_populated: AtomicBool::default(),
+            source
         });
         tokio::spawn({
             let instance = instance.clone();
@@ -33,6 +37,12 @@ impl TeamFetcherService {
         });
         instance
     }
+    pub(crate) fn failed_to_fetch_teams_count(&self) -> u32 {
+        self.failed_to_fetch_teams_count.load(Ordering::SeqCst)
+    }
+    pub(crate) fn is_populated(&self) -> bool {
+        self.is_populated.load(Ordering::SeqCst)
+    }
     pub(crate) async fn get_players_team(&self, player_id: Uuid) -> Option<Arc<Team>> {
         let (response_tx, response_rx) = oneshot::channel();
         // Handled in next line instead
@@ -42,21 +52,34 @@ impl TeamFetcherService {
         }));
         response_rx.await.ok()
     }
+    pub(crate) fn upsert_team(&self, team: Team) {
+        let _ = self.signal_tx.send(SignalRequest::UpsertTeam(team));
+    }
     async fn run(self: Arc<Self>, mut signal_rx: mpsc::UnboundedReceiver<SignalRequest>) {
-        let mut teams = Vec::<Arc<Team>>::new();
-        let mut poll_interval = interval(CACHE_DURATION);
+        let mut teams = HashMap::<Uuid, Arc<Team>>::new();
+        let poll_sleep = sleep(Duration::ZERO);
+        tokio::pin!(poll_sleep);
 
         loop {
             tokio::select! {
-                _ = poll_interval.tick() => {
-                    match self.fetch_teams().await {
-                        Ok(new_teams) => {
-                            teams = new_teams.into_iter().map(Arc::new).collect();
+                _ = &mut poll_sleep => {
+                    let mut next_poll_in = CACHE_DURATION;
+                    match self.source.fetch_teams().await {
+                        Ok(ConditionalResponse { body, max_age }) => {
+                            if let Conditional::Modified(new_teams) = body {
+                                teams = new_teams.into_iter().map(|team| (team.id, Arc::new(team))).collect();
+                            }
+                            self.is_populated.store(true, Ordering::SeqCst);
+                            if let Some(max_age) = max_age {
+                                next_poll_in = max_age.clamp(CACHE_DURATION, MAX_CACHE_DURATION);
+                            }
                         },
                         Err(error) => {
-                            tracing::error!(?error, "failed to fetch players");
+                            tracing::error!(?error, "failed to fetch teams");
+                            self.failed_to_fetch_teams_count.fetch_add(1, Ordering::SeqCst);
                         }
                     }
+                    poll_sleep.as_mut().reset(Instant::now() + next_poll_in);
                 },
                 maybe_message = signal_rx.recv() => {
                     let Some(message) = maybe_message else {
@@ -64,30 +87,24 @@ impl TeamFetcherService {
                     };
                     match message {
                         SignalRequest::GetPlayersTeam(request) => {
-                            let maybe_team = teams.iter().filter(|team| team.player_ids.contains(&request.player_id)).next();
+                            let maybe_team = teams.values().find(|team| team.player_ids.contains(&request.player_id));
                             if let Some(team) = maybe_team {
                                 let _ = request.response_tx.send(team.clone());
                             }
+                        },
+                        SignalRequest::UpsertTeam(team) => {
+                            teams.insert(team.id, Arc::new(team));
                         }
                     }
                 },
             };
         }
     }
-    async fn fetch_teams(&self) -> Result<Vec<Team>, reqwest::Error> {
-        let teams_url = self.berg_api_base.join("teams").expect("teams is hard-coded and known to be good");
-        self.http_client
-            .get(teams_url)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<Vec<Team>>()
-            .await
-    }
 }
 
 enum SignalRequest {
-    GetPlayersTeam(GetPlayersTeamSignalRequest)
+    GetPlayersTeam(GetPlayersTeamSignalRequest),
+    UpsertTeam(Team)
 }
 struct GetPlayersTeamSignalRequest {
     player_id: Uuid,
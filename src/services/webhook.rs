@@ -2,21 +2,28 @@ use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot};
 use crate::config::{WebhookConfig, WebhookRole};
 use rand::seq::IndexedRandom;
+use twilight_model::channel::message::AllowedMentions;
+use twilight_model::id::Id as Snowflake;
+use twilight_model::id::marker::WebhookMarker;
 
 #[derive(Clone)]
 pub(crate) struct WebhookService {
-    signal_tx: mpsc::UnboundedSender<WebhookRequest>
+    signal_tx: mpsc::UnboundedSender<WebhookRequest>,
+    twilight_client: Arc<twilight_http::Client>
 }
 impl WebhookService {
     pub(crate) fn new(webhooks: Vec<WebhookConfig>) -> Self {
         let (signal_tx, signal_rx) = mpsc::unbounded_channel();
+        let twilight_client = twilight_http::Client::builder().default_allowed_mentions(AllowedMentions::default()).build();
+        let twilight_client = Arc::new(twilight_client);
         tokio::spawn(Self::run(webhooks, signal_rx));
         Self {
-            signal_tx 
+            signal_tx,
+            twilight_client
         }
     }
     async fn run(webhooks: Vec<WebhookConfig>, mut receiver: mpsc::UnboundedReceiver<WebhookRequest>) {
-        let webhooks = webhooks.into_iter().map(|config| Arc::new(config)).collect::<Vec<_>>();
+        let mut webhooks = webhooks.into_iter().map(Arc::new).collect::<Vec<_>>();
         while let Some(request) = receiver.recv().await {
             match request {
                 WebhookRequest::RequestWebhook(request) => {
@@ -25,6 +32,15 @@ impl WebhookService {
                     if let Some(webhook) = webhook {
                         let _ = request.response_tx.send((*webhook).clone());
                     }
+                },
+                WebhookRequest::RequestWebhookById(request) => {
+                    let webhook = webhooks.iter().find(|webhook| webhook.id == request.webhook_id);
+                    if let Some(webhook) = webhook {
+                        let _ = request.response_tx.send(webhook.clone());
+                    }
+                },
+                WebhookRequest::ReplaceWebhooks(new_webhooks) => {
+                    webhooks = new_webhooks.into_iter().map(Arc::new).collect();
                 }
             }
         }
@@ -38,12 +54,60 @@ impl WebhookService {
         }));
         response_rx.await.ok()
     }
+    pub(crate) async fn get_webhook_by_id(&self, webhook_id: Snowflake<WebhookMarker>) -> Option<Arc<WebhookConfig>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        // Handled in next line instead
+        let _ = self.signal_tx.send(WebhookRequest::RequestWebhookById(RequestWebhookByIdRequest {
+            webhook_id,
+            response_tx
+        }));
+        response_rx.await.ok()
+    }
+    // Requests queued before this still use the old webhooks
+    pub(crate) fn replace_webhooks(&self, webhooks: Vec<WebhookConfig>) {
+        let _ = self.signal_tx.send(WebhookRequest::ReplaceWebhooks(webhooks));
+    }
+    // Returns the webhook the message was posted to, or None if no webhook has the role
+    pub(crate) async fn send_message(&self, required_role: WebhookRole, content: &str) -> Result<Option<Arc<WebhookConfig>>, twilight_http::Error> {
+        let Some(webhook) = self.get_webhook(required_role).await else {
+            return Ok(None);
+        };
+        self.execute(&webhook, content).await?;
+        Ok(Some(webhook))
+    }
+    pub(crate) async fn send_message_to(&self, webhook_id: Snowflake<WebhookMarker>, content: &str) -> Result<Option<Arc<WebhookConfig>>, twilight_http::Error> {
+        let Some(webhook) = self.get_webhook_by_id(webhook_id).await else {
+            return Ok(None);
+        };
+        self.execute(&webhook, content).await?;
+        Ok(Some(webhook))
+    }
+    pub(crate) async fn check_webhook(&self, webhook: &WebhookConfig) -> Result<(), twilight_http::Error> {
+        self.twilight_client
+            .webhook(webhook.id)
+            .token(&webhook.token)
+            .await?;
+        Ok(())
+    }
+    async fn execute(&self, webhook: &WebhookConfig, content: &str) -> Result<(), twilight_http::Error> {
+        self.twilight_client
+            .execute_webhook(webhook.id, &webhook.token)
+            .content(content)
+            .await?;
+        Ok(())
+    }
 }
 
 pub(crate) enum WebhookRequest {
-    RequestWebhook(RequestWebhookRequest)
+    RequestWebhook(RequestWebhookRequest),
+    RequestWebhookById(RequestWebhookByIdRequest),
+    ReplaceWebhooks(Vec<WebhookConfig>)
 }
 pub(crate) struct RequestWebhookRequest {
     pub(crate) required_role: WebhookRole,
     pub(crate) response_tx: oneshot::Sender<Arc<WebhookConfig>>
 }
+pub(crate) struct RequestWebhookByIdRequest {
+    pub(crate) webhook_id: Snowflake<WebhookMarker>,
+    pub(crate) response_tx: oneshot::Sender<Arc<WebhookConfig>>
+}
@@ -0,0 +1,44 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+// Services spawn their tasks through this so shutdown can wait for in-flight deliveries. Tasks are
+// expected to stop picking up new work once cancelled, but to finish whatever they are doing.
+#[derive(Clone, Default)]
+pub(crate) struct Shutdown {
+    token: CancellationToken,
+    tracker: TaskTracker
+}
+impl Shutdown {
+    pub(crate) fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static
+    {
+        self.tracker.spawn(task);
+    }
+    pub(crate) fn trigger(&self) {
+        self.token.cancel();
+    }
+    pub(crate) fn is_triggered(&self) -> bool {
+        self.token.is_cancelled()
+    }
+    pub(crate) async fn triggered(&self) {
+        self.token.cancelled().await
+    }
+    // Returns false if some tasks were still running at the deadline
+    pub(crate) async fn drain(&self, deadline: Duration) -> bool {
+        self.tracker.close();
+        timeout(deadline, self.tracker.wait()).await.is_ok()
+    }
+}
+
+// Resolves on SIGTERM or Ctrl+C
+pub(crate) async fn shutdown_signal() {
+    let mut terminate_signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("installing a SIGTERM handler should never fail");
+    tokio::select! {
+        _ = terminate_signal.recv() => {},
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
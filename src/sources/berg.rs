@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::ClientRequestBuilder;
+
+use crate::berg_client::BergClient;
+use crate::http_cache::{CacheValidators, ConditionalResponse};
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
+use crate::models::scoreboard::ScoreboardEntry;
+use crate::models::solve::Solve;
+use crate::models::team::Team;
+use crate::sources::{EventsConnectError, EventsWebSocket, ScoreboardSource, SourceError};
+use crate::USER_AGENT;
+
+pub(crate) struct BergSource {
+    berg_client: Arc<BergClient>,
+    // Validators from the last response of each cached endpoint
+    players_cache_validators: Mutex<CacheValidators>,
+    teams_cache_validators: Mutex<CacheValidators>,
+    challenges_cache_validators: Mutex<CacheValidators>
+}
+impl BergSource {
+    pub(crate) fn new(berg_client: Arc<BergClient>) -> Arc<Self> {
+        Arc::new(Self {
+            berg_client,
+            players_cache_validators: Mutex::default(),
+            teams_cache_validators: Mutex::default(),
+            challenges_cache_validators: Mutex::default()
+        })
+    }
+    async fn connect_events_websocket(&self) -> Result<EventsWebSocket, EventsConnectError> {
+        let mut events_url = self.berg_client.url("events");
+        let websocket_scheme = match events_url.scheme() {
+            "http" => "ws",
+            "https" => "wss",
+            _ => return Err(EventsConnectError::UnsupportedAPIScheme)
+        };
+        events_url.set_scheme(websocket_scheme).map_err(|_error| EventsConnectError::UnsupportedAPIScheme)?;
+        let events_uri = events_url.as_str().parse::<http::Uri>().expect("this is a valid url via the url crate");
+
+        let credential = self.berg_client.credential().await.map_err(EventsConnectError::CredentialError)?;
+        let mut request = ClientRequestBuilder::new(events_uri)
+            .with_header("User-Agent", USER_AGENT);
+        if let Some(credential) = &credential {
+            request = request.with_header(credential.name.as_str(), String::from_utf8_lossy(credential.value.as_bytes()));
+        }
+        match tokio_tungstenite::connect_async(request).await {
+            Ok((websocket, _response)) => Ok(websocket),
+            Err(error) => {
+                // The next attempt uses whatever the new login gave us
+                if let tokio_tungstenite::tungstenite::Error::Http(response) = &error && response.status() == http::StatusCode::UNAUTHORIZED {
+                    tracing::info!("berg rejected our credentials for the events websocket, logging in again");
+                    if let Err(error) = self.berg_client.relogin(credential).await {
+                        tracing::debug!(?error, "failed to log in again for the events websocket");
+                    }
+                }
+                Err(EventsConnectError::WebSocket(error))
+            }
+        }
+    }
+    async fn get_solves(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Solve>, SourceError> {
+        let mut solves_url = self.berg_client.url("solves");
+        if let Some(since) = since {
+            solves_url.query_pairs_mut().append_pair("since", &since.to_rfc3339());
+        }
+        Ok(self.berg_client.get(solves_url, |request| request)
+            .await?
+            .error_for_status()?
+            .json::<Vec<Solve>>()
+            .await?)
+    }
+    async fn get_scoreboard(&self) -> Result<Vec<ScoreboardEntry>, SourceError> {
+        let scoreboard_url = self.berg_client.url("scoreboard");
+        Ok(self.berg_client
+            .get(scoreboard_url, |request| request)
+            .await?
+            .error_for_status()?
+            .json::<Vec<ScoreboardEntry>>()
+            .await?)
+    }
+}
+impl ScoreboardSource for BergSource {
+    fn fetch_players(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Player>>, SourceError>> {
+        async move {
+            let players_url = self.berg_client.url("players");
+            crate::http_cache::get_json(&self.berg_client, players_url, &mut *self.players_cache_validators.lock().await).await
+        }.boxed()
+    }
+    fn fetch_teams(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Team>>, SourceError>> {
+        async move {
+            let teams_url = self.berg_client.url("teams");
+            crate::http_cache::get_json(&self.berg_client, teams_url, &mut *self.teams_cache_validators.lock().await).await
+        }.boxed()
+    }
+    fn fetch_challenges(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Challenge>>, SourceError>> {
+        async move {
+            let challenges_url = self.berg_client.url("challenges");
+            crate::http_cache::get_json(&self.berg_client, challenges_url, &mut *self.challenges_cache_validators.lock().await).await
+        }.boxed()
+    }
+    fn fetch_solves_since(&self, since: Option<DateTime<Utc>>) -> BoxFuture<'_, Result<Vec<Solve>, SourceError>> {
+        self.get_solves(since).boxed()
+    }
+    fn fetch_scoreboard(&self) -> BoxFuture<'_, Result<Vec<ScoreboardEntry>, SourceError>> {
+        self.get_scoreboard().boxed()
+    }
+    fn supports_events(&self) -> bool {
+        true
+    }
+    fn connect_events(&self) -> BoxFuture<'_, Result<EventsWebSocket, EventsConnectError>> {
+        self.connect_events_websocket().boxed()
+    }
+}
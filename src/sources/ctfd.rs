@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::{FutureExt, TryFutureExt};
+use http::header::{AUTHORIZATION, USER_AGENT as USER_AGENT_HEADER_KEY};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::CtfdConfig;
+use crate::http_cache::{Conditional, ConditionalResponse};
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
+use crate::models::scoreboard::ScoreboardEntry;
+use crate::models::solve::Solve;
+use crate::models::team::Team;
+use crate::sources::{EventsConnectError, EventsWebSocket, ScoreboardSource, SourceError};
+use crate::USER_AGENT;
+
+// Reads a CTFd instance through its REST API. CTFd has nothing like berg's events websocket, so
+// solves are always polled
+pub(crate) struct CtfdSource {
+    http_client: reqwest::Client,
+    api_base: Url,
+    // From the last challenge fetch, so solves don't need the challenge listing every poll
+    challenge_names: RwLock<HashMap<u64, String>>,
+    // From the last player fetch, as team listings don't include members
+    team_member_ids: RwLock<Option<HashMap<u64, Vec<Uuid>>>>
+}
+impl CtfdSource {
+    pub(crate) fn new(ctfd_config: &CtfdConfig) -> Arc<Self> {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(USER_AGENT_HEADER_KEY, HeaderValue::from_static(USER_AGENT));
+        let mut authorization = HeaderValue::try_from(format!("Token {}", ctfd_config.token)).expect("ctfd token is checked when the config is loaded");
+        authorization.set_sensitive(true);
+        default_headers.insert(AUTHORIZATION, authorization);
+        let http_client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .build()
+            .expect("all options is known to be good");
+        Arc::new(Self {
+            http_client,
+            api_base: ctfd_config.url.join("api/v1/").expect("hard-coded path should always be fine to join to ctfd.url"),
+            challenge_names: RwLock::default(),
+            team_member_ids: RwLock::default()
+        })
+    }
+    async fn get_page<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)], page: u32) -> Result<CtfdResponse<Vec<T>>, reqwest::Error> {
+        let mut url = self.api_base.join(path).expect("hard-coded path should always be fine to join to ctfd.url");
+        url.query_pairs_mut()
+            .extend_pairs(query)
+            .append_pair("page", &page.to_string());
+        self.http_client.get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<CtfdResponse<Vec<T>>>()
+            .await
+    }
+    // Follows CTFd's pagination until there is no next page
+    async fn get_all<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<Vec<T>, reqwest::Error> {
+        let mut items = Vec::new();
+        let mut page = 1;
+        loop {
+            let response = self.get_page::<T>(path, query, page).await?;
+            let next_page = response.pagination().and_then(|pagination| pagination.next);
+            items.extend(response.data);
+            match next_page {
+                Some(next) => page = next,
+                None => return Ok(items)
+            }
+        }
+    }
+    async fn get_users(&self) -> Result<Vec<CtfdUser>, reqwest::Error> {
+        self.get_all("users", &[("view", "admin")]).await
+    }
+    async fn get_players(&self) -> Result<ConditionalResponse<Vec<Player>>, reqwest::Error> {
+        let users = self.get_users().await?;
+        self.remember_team_members(&users);
+        Ok(modified(users.into_iter().map(player_from).collect()))
+    }
+    fn remember_team_members(&self, users: &[CtfdUser]) {
+        *self.team_member_ids.write().expect("team_member_ids lock poisoned") = Some(team_member_ids(users));
+    }
+    async fn get_teams(&self) -> Result<ConditionalResponse<Vec<Team>>, reqwest::Error> {
+        let teams = match self.get_all::<CtfdTeam>("teams", &[("view", "admin")]).await {
+            Ok(teams) => teams,
+            // Events in user mode have no teams at all
+            Err(error) if matches!(error.status(), Some(StatusCode::FORBIDDEN | StatusCode::NOT_FOUND)) => return Ok(modified(Vec::new())),
+            Err(error) => return Err(error)
+        };
+        // Members come from the player fetch, users are only read here if teams are fetched first
+        let cached_member_ids = self.team_member_ids.read().expect("team_member_ids lock poisoned").clone();
+        let mut member_ids = match cached_member_ids {
+            Some(member_ids) => member_ids,
+            None => {
+                let users = self.get_users().await?;
+                self.remember_team_members(&users);
+                team_member_ids(&users)
+            }
+        };
+        let teams = teams.into_iter()
+            .map(|team| {
+                let player_ids = member_ids.remove(&team.id).unwrap_or_default();
+                team_from(team, player_ids)
+            })
+            .collect();
+        Ok(modified(teams))
+    }
+    async fn get_challenges(&self) -> Result<Vec<CtfdChallenge>, reqwest::Error> {
+        let challenges = self.get_all::<CtfdChallenge>("challenges", &[("view", "admin")]).await?;
+        *self.challenge_names.write().expect("challenge_names lock poisoned") = challenges.iter()
+            .map(|challenge| (challenge.id, challenge.name.clone()))
+            .collect();
+        Ok(challenges)
+    }
+    // CTFd lists submissions oldest first and can't filter by time, so with since the pages are read
+    // from the last one back until one starts before since
+    async fn get_submissions(&self, since: Option<DateTime<Utc>>) -> Result<Vec<CtfdSubmission>, reqwest::Error> {
+        let query = [("type", "correct")];
+        let Some(since) = since else {
+            return self.get_all("submissions", &query).await;
+        };
+        let first_page = self.get_page::<CtfdSubmission>("submissions", &query, 1).await?;
+        let page_count = first_page.pagination().and_then(|pagination| pagination.pages).unwrap_or(1);
+        let mut submissions = Vec::new();
+        for page in (2..=page_count).rev() {
+            let response = self.get_page::<CtfdSubmission>("submissions", &query, page).await?;
+            let reached_since = response.data.first().is_none_or(|submission| submission.date < since);
+            submissions.extend(response.data);
+            if reached_since {
+                return Ok(submissions);
+            }
+        }
+        submissions.extend(first_page.data);
+        Ok(submissions)
+    }
+    async fn get_solves(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Solve>, reqwest::Error> {
+        let submissions = self.get_submissions(since).await?;
+        let is_missing_challenges = {
+            let challenge_names = self.challenge_names.read().expect("challenge_names lock poisoned");
+            submissions.iter().any(|submission| !challenge_names.contains_key(&submission.challenge_id))
+        };
+        // Solves for challenges added since the last challenge fetch
+        if is_missing_challenges {
+            self.get_challenges().await?;
+        }
+        let challenge_names = self.challenge_names.read().expect("challenge_names lock poisoned");
+        Ok(submissions.into_iter().filter_map(|submission| solve_from(submission, &challenge_names)).collect())
+    }
+    async fn get_scoreboard(&self) -> Result<Vec<ScoreboardEntry>, reqwest::Error> {
+        let entries = self.get_all::<CtfdScoreboardEntry>("scoreboard", &[]).await?
+            .into_iter()
+            .map(scoreboard_entry_from)
+            .collect();
+        Ok(entries)
+    }
+}
+impl ScoreboardSource for CtfdSource {
+    fn fetch_players(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Player>>, SourceError>> {
+        self.get_players().err_into().boxed()
+    }
+    fn fetch_teams(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Team>>, SourceError>> {
+        self.get_teams().err_into().boxed()
+    }
+    fn fetch_challenges(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Challenge>>, SourceError>> {
+        async move {
+            let challenges = self.get_challenges().await?
+                .into_iter()
+                .map(challenge_from)
+                .collect();
+            Ok(modified(challenges))
+        }.boxed()
+    }
+    fn fetch_solves_since(&self, since: Option<DateTime<Utc>>) -> BoxFuture<'_, Result<Vec<Solve>, SourceError>> {
+        self.get_solves(since).err_into().boxed()
+    }
+    fn fetch_scoreboard(&self) -> BoxFuture<'_, Result<Vec<ScoreboardEntry>, SourceError>> {
+        self.get_scoreboard().err_into().boxed()
+    }
+    fn supports_events(&self) -> bool {
+        false
+    }
+    fn connect_events(&self) -> BoxFuture<'_, Result<EventsWebSocket, EventsConnectError>> {
+        async { Err(EventsConnectError::Unsupported) }.boxed()
+    }
+}
+
+// CTFd uses integer ids where berg uses uuids, they're embedded so everything stored stays a uuid
+fn ctfd_id(id: u64) -> Uuid {
+    Uuid::from_u64_pair(0, id)
+}
+
+fn player_from(user: CtfdUser) -> Player {
+    Player {
+        id: ctfd_id(user.id),
+        admin: user.kind.as_deref() == Some("admin"),
+        name: user.name
+    }
+}
+fn team_member_ids(users: &[CtfdUser]) -> HashMap<u64, Vec<Uuid>> {
+    let mut member_ids = HashMap::<u64, Vec<Uuid>>::new();
+    for user in users {
+        if let Some(team_id) = user.team_id {
+            member_ids.entry(team_id).or_default().push(ctfd_id(user.id));
+        }
+    }
+    member_ids
+}
+fn team_from(team: CtfdTeam, player_ids: Vec<Uuid>) -> Team {
+    Team {
+        id: ctfd_id(team.id),
+        name: team.name,
+        player_ids,
+        hidden: team.hidden || team.banned
+    }
+}
+fn challenge_from(challenge: CtfdChallenge) -> Challenge {
+    Challenge {
+        hidden: challenge.state.as_deref() == Some("hidden"),
+        name: challenge.name,
+        display_name: None,
+        category: challenge.category,
+        author: None,
+        difficulty: None,
+        points: challenge.value
+    }
+}
+fn solve_from(submission: CtfdSubmission, challenge_names: &HashMap<u64, String>) -> Option<Solve> {
+    let Some(challenge_name) = challenge_names.get(&submission.challenge_id) else {
+        tracing::warn!(challenge_id = submission.challenge_id, "ignoring ctfd solve for unknown challenge");
+        return None;
+    };
+    Some(Solve {
+        player_id: ctfd_id(submission.user_id),
+        challenge_name: challenge_name.clone(),
+        created_at: Some(submission.date)
+    })
+}
+fn scoreboard_entry_from(entry: CtfdScoreboardEntry) -> ScoreboardEntry {
+    ScoreboardEntry {
+        team_id: ctfd_id(entry.account_id),
+        team_name: entry.name,
+        score: entry.score
+    }
+}
+
+// CTFd doesn't send cache headers, so every fetch counts as modified
+fn modified<T>(body: T) -> ConditionalResponse<T> {
+    ConditionalResponse {
+        body: Conditional::Modified(body),
+        max_age: None
+    }
+}
+
+#[derive(Deserialize)]
+struct CtfdResponse<T> {
+    data: T,
+    meta: Option<CtfdMeta>
+}
+impl<T> CtfdResponse<T> {
+    fn pagination(&self) -> Option<&CtfdPagination> {
+        self.meta.as_ref().and_then(|meta| meta.pagination.as_ref())
+    }
+}
+#[derive(Deserialize)]
+struct CtfdMeta {
+    pagination: Option<CtfdPagination>
+}
+#[derive(Deserialize)]
+struct CtfdPagination {
+    next: Option<u32>,
+    pages: Option<u32>
+}
+
+#[derive(Deserialize)]
+struct CtfdUser {
+    id: u64,
+    name: String,
+    team_id: Option<u64>,
+    // Only included for admin tokens
+    #[serde(rename = "type")]
+    kind: Option<String>
+}
+#[derive(Deserialize)]
+struct CtfdTeam {
+    id: u64,
+    name: String,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    banned: bool
+}
+#[derive(Deserialize)]
+struct CtfdChallenge {
+    id: u64,
+    name: String,
+    category: Option<String>,
+    value: Option<u32>,
+    // visible or hidden, only included for admin tokens
+    state: Option<String>
+}
+#[derive(Deserialize)]
+struct CtfdSubmission {
+    challenge_id: u64,
+    user_id: u64,
+    date: DateTime<Utc>
+}
+#[derive(Deserialize)]
+struct CtfdScoreboardEntry {
+    account_id: u64,
+    name: String,
+    score: i64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use serde::de::DeserializeOwned;
+    use super::*;
+
+    // Trimmed down responses of a CTFd 3 instance read with an admin token
+    const USERS: &str = r#"{
+        "success": true,
+        "data": [
+            {"id": 1, "name": "admin", "team_id": null, "type": "admin", "verified": true},
+            {"id": 2, "name": "alice", "team_id": 1, "type": "user", "verified": true},
+            {"id": 3, "name": "bob", "team_id": 1, "type": "user", "verified": false},
+            {"id": 4, "name": "carol", "team_id": 2}
+        ],
+        "meta": {"pagination": {"page": 1, "next": null, "prev": null, "pages": 1, "per_page": 50, "total": 4}}
+    }"#;
+    const TEAMS: &str = r#"{
+        "success": true,
+        "data": [
+            {"id": 1, "name": "flaggers", "hidden": false, "banned": false},
+            {"id": 2, "name": "cheaters", "hidden": false, "banned": true},
+            {"id": 3, "name": "nobody"}
+        ],
+        "meta": {"pagination": {"page": 1, "next": null, "prev": null, "pages": 1, "per_page": 50, "total": 3}}
+    }"#;
+    const CHALLENGES: &str = r#"{
+        "success": true,
+        "data": [
+            {"id": 7, "name": "baby rev", "category": "rev", "value": 100, "state": "visible", "type": "standard"},
+            {"id": 8, "name": "secret", "category": null, "value": null, "state": "hidden", "type": "dynamic"}
+        ]
+    }"#;
+    const SUBMISSIONS: &str = r#"{
+        "success": true,
+        "data": [
+            {"id": 10, "challenge_id": 7, "user_id": 2, "team_id": 1, "type": "correct", "date": "2026-10-18T12:00:00.123456+00:00"},
+            {"id": 11, "challenge_id": 9, "user_id": 3, "team_id": 1, "type": "correct", "date": "2026-10-18T12:05:00+00:00"}
+        ],
+        "meta": {"pagination": {"page": 3, "next": 4, "prev": 2, "pages": 5, "per_page": 50, "total": 202}}
+    }"#;
+    const SCOREBOARD: &str = r#"{
+        "success": true,
+        "data": [
+            {"pos": 1, "account_id": 1, "account_type": "team", "name": "flaggers", "score": 600, "members": []}
+        ]
+    }"#;
+
+    fn data<T: DeserializeOwned>(fixture: &str) -> Vec<T> {
+        serde_json::from_str::<CtfdResponse<Vec<T>>>(fixture).expect("fixture should parse").data
+    }
+
+    #[test]
+    fn converts_users_to_players() {
+        let players = data::<CtfdUser>(USERS).into_iter().map(player_from).collect::<Vec<_>>();
+        assert_eq!(players.len(), 4);
+        assert_eq!(players[0].id, Uuid::from_u64_pair(0, 1));
+        assert!(players[0].admin);
+        assert_eq!(players[1].name, "alice");
+        assert!(!players[1].admin);
+        assert!(!players[3].admin);
+    }
+
+    #[test]
+    fn converts_teams_with_members_from_users() {
+        let mut member_ids = team_member_ids(&data::<CtfdUser>(USERS));
+        let teams = data::<CtfdTeam>(TEAMS).into_iter()
+            .map(|team| {
+                let player_ids = member_ids.remove(&team.id).unwrap_or_default();
+                team_from(team, player_ids)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(teams[0].id, Uuid::from_u64_pair(0, 1));
+        assert_eq!(teams[0].player_ids, [Uuid::from_u64_pair(0, 2), Uuid::from_u64_pair(0, 3)]);
+        assert!(!teams[0].hidden);
+        assert!(teams[1].hidden);
+        assert!(teams[2].player_ids.is_empty());
+        assert!(!teams[2].hidden);
+    }
+
+    #[test]
+    fn converts_challenges() {
+        let challenges = data::<CtfdChallenge>(CHALLENGES).into_iter().map(challenge_from).collect::<Vec<_>>();
+        assert_eq!(challenges[0].name, "baby rev");
+        assert_eq!(challenges[0].category.as_deref(), Some("rev"));
+        assert_eq!(challenges[0].points, Some(100));
+        assert!(!challenges[0].hidden);
+        assert!(challenges[1].hidden);
+        assert_eq!(challenges[1].points, None);
+    }
+
+    #[test]
+    fn converts_submissions_of_known_challenges() {
+        let challenge_names = HashMap::from([(7, "baby rev".to_string())]);
+        let solves = data::<CtfdSubmission>(SUBMISSIONS).into_iter()
+            .filter_map(|submission| solve_from(submission, &challenge_names))
+            .collect::<Vec<_>>();
+        assert_eq!(solves.len(), 1);
+        assert_eq!(solves[0].player_id, Uuid::from_u64_pair(0, 2));
+        assert_eq!(solves[0].challenge_name, "baby rev");
+        assert_eq!(solves[0].created_at.map(|created_at| created_at.to_rfc3339()).as_deref(), Some("2026-10-18T12:00:00.123456+00:00"));
+    }
+
+    #[test]
+    fn reads_pagination() {
+        let response = serde_json::from_str::<CtfdResponse<Vec<CtfdSubmission>>>(SUBMISSIONS).unwrap();
+        let pagination = response.pagination().unwrap();
+        assert_eq!((pagination.next, pagination.pages), (Some(4), Some(5)));
+        let response = serde_json::from_str::<CtfdResponse<Vec<CtfdChallenge>>>(CHALLENGES).unwrap();
+        assert!(response.pagination().is_none());
+    }
+
+    #[test]
+    fn converts_scoreboard() {
+        let entries = data::<CtfdScoreboardEntry>(SCOREBOARD).into_iter().map(scoreboard_entry_from).collect::<Vec<_>>();
+        assert_eq!(entries[0].team_id, Uuid::from_u64_pair(0, 1));
+        assert_eq!(entries[0].team_name, "flaggers");
+        assert_eq!(entries[0].score, 600);
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::header::USER_AGENT as USER_AGENT_HEADER_KEY;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::{JsonChallengesEndpoint, JsonPlayersEndpoint, JsonScoreboardEndpoint, JsonSolvesEndpoint, JsonSourceConfig};
+use crate::http_cache::{Conditional, ConditionalResponse};
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
+use crate::models::scoreboard::ScoreboardEntry;
+use crate::models::solve::Solve;
+use crate::models::team::Team;
+use crate::sources::{EventsConnectError, EventsWebSocket, ScoreboardSource, SourceError};
+use crate::USER_AGENT;
+
+// Reads any API described in [json], for platforms Dal has no adapter for. Items missing a
+// required field are skipped with a warning instead of failing the whole fetch
+pub(crate) struct JsonSource {
+    http_client: reqwest::Client,
+    config: JsonSourceConfig,
+    // Without a players endpoint, players and teams come from the last solves download
+    solve_members: tokio::sync::Mutex<Option<Vec<MemberRecord>>>
+}
+impl JsonSource {
+    pub(crate) fn new(config: &JsonSourceConfig) -> Arc<Self> {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(USER_AGENT_HEADER_KEY, HeaderValue::from_static(USER_AGENT));
+        for (name, value) in &config.headers {
+            let name = HeaderName::try_from(name.as_str()).expect("json.headers is validated while loading the config");
+            let mut value = HeaderValue::try_from(value.as_str()).expect("json.headers is validated while loading the config");
+            value.set_sensitive(true);
+            default_headers.insert(name, value);
+        }
+        let http_client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .build()
+            .expect("all options is known to be good");
+        Arc::new(Self {
+            http_client,
+            config: config.clone(),
+            solve_members: tokio::sync::Mutex::default()
+        })
+    }
+    async fn get_items(&self, url: &Url, items_path: &str) -> Result<Vec<Value>, SourceError> {
+        let mut response = self.http_client.get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        match lookup_mut(&mut response, items_path).map(Value::take) {
+            Some(Value::Array(items)) => Ok(items),
+            _ => Err(SourceError::UnexpectedResponse(format!("{items_path:?} in {url} is not a list")))
+        }
+    }
+    async fn download_solve_records(&self) -> Result<Vec<SolveRecord>, SourceError> {
+        let endpoint = &self.config.solves;
+        let records = self.get_items(&endpoint.url, &endpoint.items).await?
+            .iter()
+            .filter_map(|item| {
+                let record = SolveRecord::parse(endpoint, item);
+                if record.is_none() {
+                    tracing::warn!(url = %endpoint.url, "ignoring solve without a player id or challenge");
+                }
+                record
+            })
+            .collect();
+        Ok(records)
+    }
+    async fn get_solve_records(&self) -> Result<Vec<SolveRecord>, SourceError> {
+        let records = self.download_solve_records().await?;
+        if self.config.players.is_none() {
+            *self.solve_members.lock().await = Some(records.iter().map(|record| record.member.clone()).collect());
+        }
+        Ok(records)
+    }
+    async fn get_member_records(&self) -> Result<Vec<MemberRecord>, SourceError> {
+        let Some(endpoint) = &self.config.players else {
+            // Everyone who has solved something is all we know about. Solves are polled anyway, so
+            // they're only downloaded here until the first poll
+            let mut solve_members = self.solve_members.lock().await;
+            if let Some(records) = &*solve_members {
+                return Ok(records.clone());
+            }
+            let records = self.download_solve_records().await?
+                .into_iter()
+                .map(|record| record.member)
+                .collect::<Vec<_>>();
+            *solve_members = Some(records.clone());
+            return Ok(records);
+        };
+        let records = self.get_items(&endpoint.url, &endpoint.items).await?
+            .iter()
+            .filter_map(|item| {
+                let record = MemberRecord::parse(endpoint, item);
+                if record.is_none() {
+                    tracing::warn!(url = %endpoint.url, "ignoring player without an id or name");
+                }
+                record
+            })
+            .collect();
+        Ok(records)
+    }
+    async fn get_players(&self) -> Result<ConditionalResponse<Vec<Player>>, SourceError> {
+        let mut players = HashMap::new();
+        for record in self.get_member_records().await? {
+            players.insert(record.player_id, Player {
+                id: record.player_id,
+                name: record.player_name,
+                admin: false
+            });
+        }
+        Ok(modified(players.into_values().collect()))
+    }
+    async fn get_teams(&self) -> Result<ConditionalResponse<Vec<Team>>, SourceError> {
+        let mut teams = HashMap::<Uuid, Team>::new();
+        for record in self.get_member_records().await? {
+            let Some((team_id, team_name)) = record.team else {
+                continue;
+            };
+            let team = teams.entry(team_id).or_insert_with(|| Team {
+                id: team_id,
+                name: team_name,
+                player_ids: Vec::new(),
+                hidden: false
+            });
+            if !team.player_ids.contains(&record.player_id) {
+                team.player_ids.push(record.player_id);
+            }
+        }
+        Ok(modified(teams.into_values().collect()))
+    }
+    async fn get_challenges(&self, endpoint: &JsonChallengesEndpoint) -> Result<ConditionalResponse<Vec<Challenge>>, SourceError> {
+        let challenges = self.get_items(&endpoint.url, &endpoint.items).await?
+            .iter()
+            .filter_map(|item| {
+                let challenge = parse_challenge(endpoint, item);
+                if challenge.is_none() {
+                    tracing::warn!(url = %endpoint.url, "ignoring challenge without a name");
+                }
+                challenge
+            })
+            .collect();
+        Ok(modified(challenges))
+    }
+    async fn get_solves(&self) -> Result<Vec<Solve>, SourceError> {
+        let solves = self.get_solve_records().await?
+            .into_iter()
+            .map(|record| Solve {
+                player_id: record.member.player_id,
+                challenge_name: record.challenge_name,
+                created_at: record.created_at
+            })
+            .collect();
+        Ok(solves)
+    }
+    async fn get_scoreboard(&self, endpoint: &JsonScoreboardEndpoint) -> Result<Vec<ScoreboardEntry>, SourceError> {
+        let entries = self.get_items(&endpoint.url, &endpoint.items).await?
+            .iter()
+            .filter_map(|item| {
+                let entry = parse_scoreboard_entry(endpoint, item);
+                if entry.is_none() {
+                    tracing::warn!(url = %endpoint.url, "ignoring scoreboard entry without a team id, name or score");
+                }
+                entry
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+impl ScoreboardSource for JsonSource {
+    fn fetch_players(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Player>>, SourceError>> {
+        self.get_players().boxed()
+    }
+    fn fetch_teams(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Team>>, SourceError>> {
+        self.get_teams().boxed()
+    }
+    fn fetch_challenges(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Challenge>>, SourceError>> {
+        async move {
+            match &self.config.challenges {
+                Some(endpoint) => self.get_challenges(endpoint).await,
+                None => Ok(modified(Vec::new()))
+            }
+        }.boxed()
+    }
+    // The endpoints can't filter by time, so since is ignored
+    fn fetch_solves_since(&self, _since: Option<DateTime<Utc>>) -> BoxFuture<'_, Result<Vec<Solve>, SourceError>> {
+        self.get_solves().boxed()
+    }
+    fn fetch_scoreboard(&self) -> BoxFuture<'_, Result<Vec<ScoreboardEntry>, SourceError>> {
+        async move {
+            // Scoreboard posts are rejected while loading the config without an endpoint
+            match &self.config.scoreboard {
+                Some(endpoint) => self.get_scoreboard(endpoint).await,
+                None => Ok(Vec::new())
+            }
+        }.boxed()
+    }
+    fn supports_events(&self) -> bool {
+        false
+    }
+    fn connect_events(&self) -> BoxFuture<'_, Result<EventsWebSocket, EventsConnectError>> {
+        async { Err(EventsConnectError::Unsupported) }.boxed()
+    }
+}
+
+// A player and their team, as found in a solve or the players endpoint
+#[derive(Clone)]
+struct MemberRecord {
+    player_id: Uuid,
+    player_name: String,
+    team: Option<(Uuid, String)>
+}
+impl MemberRecord {
+    fn parse(endpoint: &JsonPlayersEndpoint, item: &Value) -> Option<Self> {
+        Some(Self {
+            player_id: lookup_id(item, &endpoint.id)?,
+            player_name: lookup_text(item, &endpoint.name)?,
+            team: parse_team(item, endpoint.team_id.as_deref(), endpoint.team_name.as_deref())
+        })
+    }
+}
+struct SolveRecord {
+    member: MemberRecord,
+    challenge_name: String,
+    created_at: Option<DateTime<Utc>>
+}
+impl SolveRecord {
+    fn parse(endpoint: &JsonSolvesEndpoint, item: &Value) -> Option<Self> {
+        let player_id = lookup_id(item, &endpoint.player_id)?;
+        // Without a name the id is the best there is
+        let player_name = endpoint.player_name.as_deref()
+            .and_then(|path| lookup_text(item, path))
+            .or_else(|| lookup_text(item, &endpoint.player_id))?;
+        Some(Self {
+            member: MemberRecord {
+                player_id,
+                player_name,
+                team: parse_team(item, endpoint.team_id.as_deref(), endpoint.team_name.as_deref())
+            },
+            challenge_name: lookup_text(item, &endpoint.challenge)?,
+            created_at: endpoint.timestamp.as_deref().and_then(|path| lookup(item, path)).and_then(parse_timestamp)
+        })
+    }
+}
+fn parse_team(item: &Value, team_id_path: Option<&str>, team_name_path: Option<&str>) -> Option<(Uuid, String)> {
+    let team_id_path = team_id_path?;
+    let team_id = lookup_id(item, team_id_path)?;
+    let team_name = team_name_path
+        .and_then(|path| lookup_text(item, path))
+        .or_else(|| lookup_text(item, team_id_path))?;
+    Some((team_id, team_name))
+}
+fn parse_challenge(endpoint: &JsonChallengesEndpoint, item: &Value) -> Option<Challenge> {
+    let optional_text = |path: &Option<String>| path.as_deref().and_then(|path| lookup_text(item, path));
+    Some(Challenge {
+        name: lookup_text(item, &endpoint.name)?,
+        display_name: optional_text(&endpoint.display_name),
+        category: optional_text(&endpoint.category),
+        author: None,
+        difficulty: None,
+        points: endpoint.points.as_deref()
+            .and_then(|path| lookup(item, path))
+            .and_then(Value::as_u64)
+            .and_then(|points| u32::try_from(points).ok()),
+        hidden: endpoint.hidden.as_deref()
+            .and_then(|path| lookup(item, path))
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    })
+}
+fn parse_scoreboard_entry(endpoint: &JsonScoreboardEndpoint, item: &Value) -> Option<ScoreboardEntry> {
+    Some(ScoreboardEntry {
+        team_id: lookup_id(item, &endpoint.team_id)?,
+        team_name: lookup_text(item, &endpoint.team_name)?,
+        score: lookup(item, &endpoint.score).and_then(|score| score.as_i64().or_else(|| score.as_f64().map(|score| score as i64)))?
+    })
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(object) => object.get(key),
+        Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+        _ => None
+    })
+}
+fn lookup_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(object) => object.get_mut(key),
+        Value::Array(array) => array.get_mut(key.parse::<usize>().ok()?),
+        _ => None
+    })
+}
+// Ids and names are often numbers in other APIs
+fn lookup_text(value: &Value, path: &str) -> Option<String> {
+    match lookup(value, path)? {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None
+    }
+}
+// Everything stored is a uuid, so other ids are mapped to one. Numbers, also when sent as strings,
+// are embedded like CTFd ids, anything else is hashed so the same id always gives the same uuid
+fn lookup_id(value: &Value, path: &str) -> Option<Uuid> {
+    match lookup(value, path)? {
+        Value::Number(number) if let Some(id) = number.as_u64() => Some(Uuid::from_u64_pair(0, id)),
+        Value::String(id) if let Ok(id) = id.parse::<u64>() => Some(Uuid::from_u64_pair(0, id)),
+        Value::String(id) => match Uuid::parse_str(id) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                let hash = Sha256::digest(id.as_bytes());
+                Some(Uuid::new_v8(hash[..16].try_into().expect("sha256 is longer than 16 bytes")))
+            }
+        },
+        _ => None
+    }
+}
+// RFC 3339 strings, or unix timestamps in seconds or milliseconds. Anything past the year 5138 in
+// seconds is taken to be milliseconds
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    const MAX_SECONDS: i64 = 100_000_000_000;
+    let timestamp = match value {
+        Value::String(text) => return DateTime::parse_from_rfc3339(text).ok().map(|timestamp| timestamp.to_utc()),
+        Value::Number(number) => number.as_i64().or_else(|| number.as_f64().map(|timestamp| timestamp as i64))?,
+        _ => return None
+    };
+    if timestamp.abs() < MAX_SECONDS {
+        DateTime::from_timestamp(timestamp, 0)
+    } else {
+        DateTime::from_timestamp_millis(timestamp)
+    }
+}
+
+// Nothing is known about cache headers of arbitrary APIs, so every fetch counts as modified
+fn modified<T>(body: T) -> ConditionalResponse<T> {
+    ConditionalResponse {
+        body: Conditional::Modified(body),
+        max_age: None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+    use super::{lookup, lookup_id, parse_timestamp};
+
+    #[test]
+    fn lookup_follows_objects_and_arrays() {
+        let value = json!({"data": {"solves": [{"user": {"id": 7}}]}});
+        assert_eq!(lookup(&value, ""), Some(&value));
+        assert_eq!(lookup(&value, "data.solves.0.user.id"), Some(&json!(7)));
+        assert_eq!(lookup(&value, "data.solves.1"), None);
+        assert_eq!(lookup(&value, "data.solves.first"), None);
+        assert_eq!(lookup(&value, "data.missing"), None);
+        assert_eq!(lookup(&value, "data.solves.0.user.id.deeper"), None);
+    }
+
+    #[test]
+    fn lookup_id_embeds_numbers_also_as_strings() {
+        let value = json!({"number": 42, "text": "42", "negative": -1, "fraction": 1.5});
+        assert_eq!(lookup_id(&value, "number"), Some(Uuid::from_u64_pair(0, 42)));
+        assert_eq!(lookup_id(&value, "text"), Some(Uuid::from_u64_pair(0, 42)));
+        assert_eq!(lookup_id(&value, "negative"), None);
+        assert_eq!(lookup_id(&value, "fraction"), None);
+    }
+
+    #[test]
+    fn lookup_id_keeps_uuids_and_hashes_other_text() {
+        let uuid = Uuid::new_v4();
+        let value = json!({"uuid": uuid.to_string(), "name": "alice", "same_name": "alice", "other_name": "bob", "list": []});
+        assert_eq!(lookup_id(&value, "uuid"), Some(uuid));
+        let alice = lookup_id(&value, "name").unwrap();
+        assert_eq!(alice.get_version_num(), 8);
+        assert_eq!(lookup_id(&value, "same_name"), Some(alice));
+        assert_ne!(lookup_id(&value, "other_name"), Some(alice));
+        assert_eq!(lookup_id(&value, "list"), None);
+        assert_eq!(lookup_id(&value, "missing"), None);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let expected = "2026-10-18T12:00:00+00:00";
+        let parse = |value| parse_timestamp(&value).map(|timestamp| timestamp.to_rfc3339());
+        assert_eq!(parse(json!("2026-10-18T14:00:00+02:00")).as_deref(), Some(expected));
+        assert_eq!(parse(json!(1_792_324_800)).as_deref(), Some(expected));
+        assert_eq!(parse(json!(1_792_324_800_000_i64)).as_deref(), Some(expected));
+        assert_eq!(parse(json!(1_792_324_800.9)).as_deref(), Some(expected));
+        assert_eq!(parse(json!("yesterday")), None);
+        assert_eq!(parse(json!(null)), None);
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::berg_client::CredentialError;
+use crate::http_cache::ConditionalResponse;
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
+use crate::models::scoreboard::ScoreboardEntry;
+use crate::models::solve::Solve;
+use crate::models::team::Team;
+
+pub(crate) mod berg;
+pub(crate) mod ctfd;
+pub(crate) mod json;
+pub(crate) mod rctf;
+
+pub(crate) type EventsWebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// The platform an event runs on. Everything past this only sees Dal's own models
+pub(crate) trait ScoreboardSource: Send + Sync {
+    fn fetch_players(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Player>>, SourceError>>;
+    fn fetch_teams(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Team>>, SourceError>>;
+    fn fetch_challenges(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Challenge>>, SourceError>>;
+    // since only narrows the request, sources may ignore it and return older solves too. Callers
+    // rely on the repository to skip solves already sent
+    fn fetch_solves_since(&self, since: Option<DateTime<Utc>>) -> BoxFuture<'_, Result<Vec<Solve>, SourceError>>;
+    fn fetch_scoreboard(&self) -> BoxFuture<'_, Result<Vec<ScoreboardEntry>, SourceError>>;
+    // Sources without a berg style events websocket can only be polled
+    fn supports_events(&self) -> bool;
+    fn connect_events(&self) -> BoxFuture<'_, Result<EventsWebSocket, EventsConnectError>>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum SourceError {
+    #[error("request failed")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("no credentials")]
+    CredentialError(#[from] CredentialError),
+    // The response parsed, but didn't have what we were looking for in it
+    #[error("unexpected response: {0}")]
+    UnexpectedResponse(String)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum EventsConnectError {
+    #[error("source has no events websocket")]
+    Unsupported,
+    #[error("unsupported api scheme")]
+    UnsupportedAPIScheme,
+    #[error("no credentials")]
+    CredentialError(CredentialError),
+    #[error("websocket connect error")]
+    WebSocket(tokio_tungstenite::tungstenite::Error)
+}
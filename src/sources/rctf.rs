@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::header::{AUTHORIZATION, USER_AGENT as USER_AGENT_HEADER_KEY};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::RctfConfig;
+use crate::http_cache::{Conditional, ConditionalResponse};
+use crate::models::challenge::Challenge;
+use crate::models::player::Player;
+use crate::models::scoreboard::ScoreboardEntry;
+use crate::models::solve::Solve;
+use crate::models::team::Team;
+use crate::sources::{EventsConnectError, EventsWebSocket, ScoreboardSource, SourceError};
+use crate::USER_AGENT;
+
+// rCTF caps limit at 100 for both solves and the leaderboard
+const PAGE_SIZE: usize = 100;
+
+// Reads an rCTF instance through the same API its frontend uses. rCTF has no events websocket, so
+// solves are always polled. Every rCTF user is a team of its own, so there are no teams, only players
+pub(crate) struct RctfSource {
+    http_client: reqwest::Client,
+    api_base: Url,
+    team_token: String,
+    // Exchanged for the team token on first use and whenever rCTF rejects it
+    auth_token: tokio::sync::Mutex<Option<String>>,
+    // Solve counts of challenges when their solves were last read, polls skip challenges whose
+    // count hasn't changed since
+    solve_counts: Mutex<HashMap<String, u32>>
+}
+impl RctfSource {
+    pub(crate) fn new(rctf_config: &RctfConfig) -> Arc<Self> {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(USER_AGENT_HEADER_KEY, HeaderValue::from_static(USER_AGENT));
+        let http_client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .build()
+            .expect("all options is known to be good");
+        Arc::new(Self {
+            http_client,
+            api_base: rctf_config.url.join("api/v1/").expect("hard-coded path should always be fine to join to rctf.url"),
+            team_token: rctf_config.team_token.clone(),
+            auth_token: tokio::sync::Mutex::default(),
+            solve_counts: Mutex::default()
+        })
+    }
+    // Only meant for hard-coded paths and challenge ids
+    fn url(&self, path: &str) -> Url {
+        self.api_base.join(path).expect("path should always be fine to join to rctf.url")
+    }
+    async fn login(&self) -> Result<String, SourceError> {
+        let response = self.http_client.post(self.url("auth/login"))
+            .json(&json!({
+                "teamToken": self.team_token
+            }))
+            .send()
+            .await?
+            .json::<RctfResponse<RctfLogin>>()
+            .await?;
+        match response.data {
+            Some(login) if response.kind == "goodLogin" => {
+                tracing::debug!("logged in to rctf");
+                Ok(login.auth_token)
+            },
+            _ => Err(SourceError::UnexpectedResponse(format!("rctf login failed with {}", response.kind)))
+        }
+    }
+    async fn auth_token(&self) -> Result<String, SourceError> {
+        let mut auth_token = self.auth_token.lock().await;
+        if let Some(auth_token) = &*auth_token {
+            return Ok(auth_token.clone());
+        }
+        let new_auth_token = self.login().await?;
+        *auth_token = Some(new_auth_token.clone());
+        Ok(new_auth_token)
+    }
+    // Logs in again once if rCTF says the auth token is bad. rCTF answers most errors with a kind
+    // rather than just a status, so those are handed back for the caller to look at
+    async fn get<T: DeserializeOwned>(&self, url: Url) -> Result<RctfResponse<T>, SourceError> {
+        let mut is_retry = false;
+        loop {
+            let auth_token = self.auth_token().await?;
+            let response = self.http_client.get(url.clone())
+                .header(AUTHORIZATION, format!("Bearer {auth_token}"))
+                .send()
+                .await?;
+            if response.status() == StatusCode::UNAUTHORIZED && !is_retry {
+                let response = response.json::<RctfResponse<serde_json::Value>>().await?;
+                if response.kind == "badToken" {
+                    tracing::info!(%url, "rctf rejected our auth token, logging in again");
+                    let mut current_auth_token = self.auth_token.lock().await;
+                    // Someone else may have logged in already
+                    if current_auth_token.as_deref() == Some(auth_token.as_str()) {
+                        *current_auth_token = None;
+                    }
+                    is_retry = true;
+                    continue;
+                }
+                return Ok(RctfResponse {
+                    kind: response.kind,
+                    data: None
+                });
+            }
+            return Ok(response.json::<RctfResponse<T>>().await?);
+        }
+    }
+    async fn get_challenges(&self) -> Result<Vec<RctfChallenge>, SourceError> {
+        let response = self.get::<Vec<RctfChallenge>>(self.url("challs")).await?;
+        match (response.kind.as_str(), response.data) {
+            ("goodChallenges", Some(challenges)) => Ok(challenges),
+            // Challenges are only listed while the CTF is running
+            ("badNotStarted" | "badEnded", _) => Ok(Vec::new()),
+            (kind, _) => Err(SourceError::UnexpectedResponse(format!("rctf answered challs with {kind}")))
+        }
+    }
+    async fn get_challenge_solves(&self, challenge_id: &str) -> Result<Vec<RctfSolve>, SourceError> {
+        let mut solves = Vec::new();
+        loop {
+            let mut url = self.url(&format!("challs/{challenge_id}/solves"));
+            url.query_pairs_mut()
+                .append_pair("limit", &PAGE_SIZE.to_string())
+                .append_pair("offset", &solves.len().to_string());
+            let response = self.get::<RctfChallengeSolves>(url).await?;
+            let page = match (response.kind.as_str(), response.data) {
+                ("goodChallengeSolves", Some(page)) => page.solves,
+                (kind, _) => return Err(SourceError::UnexpectedResponse(format!("rctf answered solves for {challenge_id} with {kind}")))
+            };
+            let is_last_page = page.len() < PAGE_SIZE;
+            solves.extend(page);
+            if is_last_page {
+                return Ok(solves);
+            }
+        }
+    }
+    // There is no listing of every solve, so each challenge is read on its own. With since, only
+    // challenges with new solves are, their solves before that were already returned
+    async fn get_solves(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Solve>, SourceError> {
+        let known_solve_counts = self.solve_counts.lock().expect("solve_counts lock poisoned").clone();
+        let mut solves = Vec::new();
+        // Only kept once every challenge was read, otherwise a failed poll would skip the
+        // challenges it did read next time and lose their solves
+        let mut new_solve_counts = Vec::new();
+        for challenge in self.get_challenges().await? {
+            if !has_new_solves(&challenge, &known_solve_counts, since.is_some()) {
+                continue;
+            }
+            let challenge_solves = self.get_challenge_solves(&challenge.id).await?;
+            if let Some(solve_count) = challenge.solves {
+                new_solve_counts.push((challenge.id.clone(), solve_count));
+            }
+            solves.extend(challenge_solves.into_iter().filter_map(|solve| solve_from(&challenge.id, solve)));
+        }
+        self.solve_counts.lock().expect("solve_counts lock poisoned").extend(new_solve_counts);
+        Ok(solves)
+    }
+    // The leaderboard is public and the only listing of users rCTF has
+    async fn get_leaderboard(&self) -> Result<Vec<RctfLeaderboardEntry>, SourceError> {
+        let mut entries = Vec::new();
+        loop {
+            let mut url = self.url("leaderboard/now");
+            url.query_pairs_mut()
+                .append_pair("limit", &PAGE_SIZE.to_string())
+                .append_pair("offset", &entries.len().to_string());
+            let response = self.get::<RctfLeaderboard>(url).await?;
+            let page = match (response.kind.as_str(), response.data) {
+                ("goodLeaderboard", Some(page)) => page,
+                ("badNotStarted", _) => return Ok(entries),
+                (kind, _) => return Err(SourceError::UnexpectedResponse(format!("rctf answered leaderboard with {kind}")))
+            };
+            let is_last_page = page.leaderboard.is_empty() || entries.len() + page.leaderboard.len() >= page.total;
+            entries.extend(page.leaderboard);
+            if is_last_page {
+                return Ok(entries);
+            }
+        }
+    }
+    async fn get_players(&self) -> Result<ConditionalResponse<Vec<Player>>, SourceError> {
+        let players = self.get_leaderboard().await?
+            .into_iter()
+            .map(|entry| Player {
+                id: entry.id,
+                name: entry.name,
+                admin: false
+            })
+            .collect();
+        Ok(modified(players))
+    }
+    async fn get_scoreboard(&self) -> Result<Vec<ScoreboardEntry>, SourceError> {
+        let entries = self.get_leaderboard().await?
+            .into_iter()
+            .map(|entry| ScoreboardEntry {
+                team_id: entry.id,
+                team_name: entry.name,
+                score: entry.score
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+impl ScoreboardSource for RctfSource {
+    fn fetch_players(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Player>>, SourceError>> {
+        self.get_players().boxed()
+    }
+    fn fetch_teams(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Team>>, SourceError>> {
+        async { Ok(modified(Vec::new())) }.boxed()
+    }
+    fn fetch_challenges(&self) -> BoxFuture<'_, Result<ConditionalResponse<Vec<Challenge>>, SourceError>> {
+        async move {
+            // Solves refer to challenges by id, the name is only for display
+            let challenges = self.get_challenges().await?
+                .into_iter()
+                .map(|challenge| Challenge {
+                    name: challenge.id,
+                    display_name: Some(challenge.name),
+                    category: challenge.category,
+                    author: challenge.author,
+                    difficulty: None,
+                    points: challenge.points,
+                    hidden: false
+                })
+                .collect();
+            Ok(modified(challenges))
+        }.boxed()
+    }
+    fn fetch_solves_since(&self, since: Option<DateTime<Utc>>) -> BoxFuture<'_, Result<Vec<Solve>, SourceError>> {
+        self.get_solves(since).boxed()
+    }
+    fn fetch_scoreboard(&self) -> BoxFuture<'_, Result<Vec<ScoreboardEntry>, SourceError>> {
+        self.get_scoreboard().boxed()
+    }
+    fn supports_events(&self) -> bool {
+        false
+    }
+    fn connect_events(&self) -> BoxFuture<'_, Result<EventsWebSocket, EventsConnectError>> {
+        async { Err(EventsConnectError::Unsupported) }.boxed()
+    }
+}
+
+// Challenges without solves are always skipped, the others only when polling for new solves and
+// their count is the same as last time
+fn has_new_solves(challenge: &RctfChallenge, known_solve_counts: &HashMap<String, u32>, is_incremental: bool) -> bool {
+    match challenge.solves {
+        Some(0) => false,
+        Some(solve_count) if is_incremental => known_solve_counts.get(&challenge.id) != Some(&solve_count),
+        _ => true
+    }
+}
+fn solve_from(challenge_id: &str, solve: RctfSolve) -> Option<Solve> {
+    let Some(created_at) = DateTime::from_timestamp_millis(solve.created_at) else {
+        tracing::warn!(challenge_id, created_at = solve.created_at, "ignoring rctf solve with invalid timestamp");
+        return None;
+    };
+    Some(Solve {
+        player_id: solve.user_id,
+        challenge_name: challenge_id.to_string(),
+        created_at: Some(created_at)
+    })
+}
+
+// rCTF doesn't send cache headers, so every fetch counts as modified
+fn modified<T>(body: T) -> ConditionalResponse<T> {
+    ConditionalResponse {
+        body: Conditional::Modified(body),
+        max_age: None
+    }
+}
+
+#[derive(Deserialize)]
+struct RctfResponse<T> {
+    kind: String,
+    data: Option<T>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RctfLogin {
+    auth_token: String
+}
+#[derive(Deserialize)]
+struct RctfChallenge {
+    id: String,
+    name: String,
+    category: Option<String>,
+    author: Option<String>,
+    points: Option<u32>,
+    solves: Option<u32>
+}
+#[derive(Deserialize)]
+struct RctfChallengeSolves {
+    solves: Vec<RctfSolve>
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RctfSolve {
+    // Milliseconds since the unix epoch
+    created_at: i64,
+    user_id: Uuid
+}
+#[derive(Deserialize)]
+struct RctfLeaderboard {
+    total: usize,
+    leaderboard: Vec<RctfLeaderboardEntry>
+}
+#[derive(Deserialize)]
+struct RctfLeaderboardEntry {
+    id: Uuid,
+    name: String,
+    score: i64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use uuid::Uuid;
+    use super::{has_new_solves, solve_from, RctfChallenge, RctfSolve};
+
+    fn challenge(solves: Option<u32>) -> RctfChallenge {
+        serde_json::from_value(json!({
+            "id": "pwn-1",
+            "name": "Pwn 1",
+            "category": "pwn",
+            "author": "alice",
+            "points": 500,
+            "solves": solves
+        })).unwrap()
+    }
+
+    #[test]
+    fn unchanged_challenges_are_only_skipped_when_polling_for_new_solves() {
+        let known_solve_counts = HashMap::from([("pwn-1".to_string(), 3)]);
+        assert!(!has_new_solves(&challenge(Some(3)), &known_solve_counts, true));
+        assert!(has_new_solves(&challenge(Some(4)), &known_solve_counts, true));
+        assert!(has_new_solves(&challenge(Some(3)), &known_solve_counts, false));
+        assert!(has_new_solves(&challenge(Some(3)), &HashMap::new(), true));
+        assert!(has_new_solves(&challenge(None), &known_solve_counts, true));
+        assert!(!has_new_solves(&challenge(Some(0)), &HashMap::new(), false));
+    }
+
+    #[test]
+    fn solve_timestamps_are_milliseconds() {
+        let user_id = Uuid::new_v4();
+        let solve = serde_json::from_value::<RctfSolve>(json!({"id": "s1", "createdAt": 1760961600123_i64, "userId": user_id, "userName": "alice"})).unwrap();
+        let solve = solve_from("pwn-1", solve).unwrap();
+        assert_eq!(solve.player_id, user_id);
+        assert_eq!(solve.challenge_name, "pwn-1");
+        assert_eq!(solve.created_at, Some(Utc.with_ymd_and_hms(2025, 10, 20, 12, 0, 0).unwrap() + chrono::Duration::milliseconds(123)));
+    }
+
+    #[test]
+    fn solves_with_invalid_timestamps_are_skipped() {
+        let solve = RctfSolve {
+            created_at: i64::MAX,
+            user_id: Uuid::new_v4()
+        };
+        assert!(solve_from("pwn-1", solve).is_none());
+    }
+}
@@ -1,7 +1,56 @@
 use std::sync::Arc;
+use url::Url;
 
+use crate::berg_client::BergClient;
+use crate::config::{BergConnectionConfig, EventWindowConfig, ReadinessConfig, SourceKind};
+use crate::repository::Repository;
+use crate::services::alerter::AlerterService;
+use crate::services::challenge_fetcher::ChallengeFetcherService;
+use crate::services::config_reloader::ConfigReloaderService;
+use crate::services::player_fetcher::PlayerFetcherService;
+use crate::services::release_sender::ReleaseSenderService;
+use crate::services::scheduler::SchedulerService;
+use crate::services::scoreboard::ScoreboardService;
 use crate::services::solve_fetcher::SolveFetcherService;
+use crate::services::solve_sender::SolveSenderService;
+use crate::services::team_fetcher::TeamFetcherService;
+use crate::services::webhook::WebhookService;
 
 pub(crate) struct AppState {
-    pub(crate) solve_fetcher_service: Arc<SolveFetcherService>
+    pub(crate) admin_token: Option<String>,
+    pub(crate) readiness_config: ReadinessConfig,
+    // Not scoped to any event, only for checks that don't care
+    pub(crate) repository: Arc<Repository>,
+    pub(crate) events: Vec<Arc<EventState>>,
+    pub(crate) config_reloader_service: Arc<ConfigReloaderService>,
+    // Database and error rate alerts, shared by every event
+    pub(crate) alerter_service: Arc<AlerterService>
+}
+impl AppState {
+    pub(crate) fn find_event(&self, name: &str) -> Option<&Arc<EventState>> {
+        self.events.iter().find(|event| event.name == name)
+    }
+}
+
+// Everything that runs once per configured event
+pub(crate) struct EventState {
+    pub(crate) name: String,
+    pub(crate) window_config: EventWindowConfig,
+    // Kept to tell about changes on reload that need a restart
+    pub(crate) source: SourceKind,
+    pub(crate) berg_api_base: Option<Url>,
+    pub(crate) berg_connection_config: BergConnectionConfig,
+    pub(crate) repository: Arc<Repository>,
+    // Only set when the source is berg
+    pub(crate) berg_client: Option<Arc<BergClient>>,
+    pub(crate) webhook_service: Arc<WebhookService>,
+    pub(crate) solve_fetcher_service: Arc<SolveFetcherService>,
+    pub(crate) solve_sender_service: Arc<SolveSenderService>,
+    pub(crate) player_fetcher_service: Arc<PlayerFetcherService>,
+    pub(crate) team_fetcher_service: Arc<TeamFetcherService>,
+    pub(crate) challenge_fetcher_service: Arc<ChallengeFetcherService>,
+    pub(crate) scheduler_service: Arc<SchedulerService>,
+    pub(crate) release_sender_service: Arc<ReleaseSenderService>,
+    pub(crate) scoreboard_service: Arc<ScoreboardService>,
+    pub(crate) alerter_service: Arc<AlerterService>
 }
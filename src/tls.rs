@@ -0,0 +1,85 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::serve::Listener;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) fn load_tls_acceptor(tls_config: &TlsConfig) -> Result<TlsAcceptor, TlsLoadError> {
+    let certificates = CertificateDer::pem_file_iter(&tls_config.cert_path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(TlsLoadError::FailedToReadCertificates)?;
+    let key = PrivateKeyDer::from_pem_file(&tls_config.key_path).map_err(TlsLoadError::FailedToReadKey)?;
+    // rustls is built with more than one crypto provider, so it has to be picked explicitly
+    let server_config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(TlsLoadError::InvalidConfig)?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(TlsLoadError::InvalidConfig)?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+// Handshakes happen in their own tasks so a slow client can't hold up everyone else
+pub(crate) struct TlsListener {
+    local_addr: SocketAddr,
+    stream_rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>
+}
+impl TlsListener {
+    pub(crate) fn new(mut listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (stream_tx, stream_rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            while !stream_tx.is_closed() {
+                let (stream, remote_addr) = Listener::accept(&mut listener).await;
+                let acceptor = acceptor.clone();
+                let stream_tx = stream_tx.clone();
+                tokio::spawn(async move {
+                    match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = stream_tx.send((stream, remote_addr)).await;
+                        },
+                        Ok(Err(error)) => tracing::debug!(?error, ?remote_addr, "tls handshake failed"),
+                        Err(_) => tracing::debug!(?remote_addr, "tls handshake timed out")
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            stream_rx
+        })
+    }
+}
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        self.stream_rx.recv().await.expect("accept task lives as long as the listener")
+    }
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum TlsLoadError {
+    #[error("failed to read certificates")]
+    FailedToReadCertificates(rustls::pki_types::pem::Error),
+    #[error("failed to read private key")]
+    FailedToReadKey(rustls::pki_types::pem::Error),
+    #[error("invalid tls config")]
+    InvalidConfig(rustls::Error)
+}